@@ -0,0 +1,724 @@
+extern crate brotli;
+
+use brotli::{ BlockTypesInfo, ContextMapInfo, Decompressor, Field, MetaBlockInfo, PrefixCodeInfo, StreamStructure };
+
+use std::cmp;
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::io::{ Read, Write };
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+
+/// Column at which the annotation of a field begins, after its bits.
+const COMMENT_COLUMN: usize = 20;
+/// Number of bytes of uncompressed data or metadata that are shown.
+const MAX_DATA_BYTES: usize = 16;
+/// Order in which the code lengths of the code length symbols of a complex prefix code are stored.
+const CODE_LENGTH_ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+/// Number of bits of the static prefix code of every code length code length, 0...5.
+const CODE_LENGTH_CODE_BITS: [usize; 6] = [2, 4, 3, 2, 2, 4];
+
+/// Writes the bits of a brotli stream next to the fields they decode to,
+/// in the format of the hand-written docs/analysis_*.txt files.
+struct Annotator<'a> {
+	input: &'a [u8],
+	out: String,
+	/// number of input bytes that have been shown in binary so far
+	shown_bytes: usize,
+	/// input bit offset up to which bits have been annotated
+	bit_pos: usize,
+}
+
+impl<'a> Annotator<'a> {
+	fn new(input: &'a [u8]) -> Annotator<'a> {
+		Annotator{
+			input,
+			out: String::new(),
+			shown_bytes: 0,
+			bit_pos: 0,
+		}
+	}
+
+	fn line(&mut self, line: &str) {
+		self.out.push_str(line);
+		self.out.push('\n');
+	}
+
+	fn section(&mut self, title: &str) {
+		self.line("");
+		self.line(title);
+		self.line(&"=".repeat(title.len()));
+	}
+
+	/// Shows the input bytes in binary, up to the one that contains the bit before end.
+	fn show_bytes(&mut self, end: usize) {
+		let end = cmp::min((end + 7) >> 3, self.input.len());
+
+		if end <= self.shown_bytes {
+			return;
+		}
+
+		if !self.out.is_empty() && !self.out.ends_with("\n\n") && !self.out.ends_with("=\n") {
+			self.line("");
+		}
+
+		for row in self.input[self.shown_bytes..end].chunks(4) {
+			let hex = row.iter().map(|byte| format!("0x{:02x}", byte)).collect::<Vec<_>>().join(" ");
+			let binary = row.iter().map(|byte| format!("{:04b} {:04b}", byte >> 4, byte & 0x0f)).collect::<Vec<_>>().join(" ");
+
+			self.line(&format!("{} = {}", hex, binary));
+		}
+
+		self.line("");
+		self.shown_bytes = end;
+	}
+
+	/// Returns the bits in range, the last one first, in groups of four from the right.
+	fn bits(&self, range: Range<usize>) -> Vec<String> {
+		let bits = range.rev()
+			.map(|i| if (self.input[i >> 3] >> (i & 7)) & 1 == 1 { '1' } else { '0' })
+			.collect::<Vec<_>>();
+		let first = match bits.len() % 4 {
+			0 => 4,
+			n => n,
+		};
+		let mut groups = vec![bits[..cmp::min(first, bits.len())].iter().collect::<String>()];
+
+		for group in bits[cmp::min(first, bits.len())..].chunks(4) {
+			groups.push(group.iter().collect());
+		}
+
+		groups
+	}
+
+	/// Annotates the bits in range with comments, after the bits that have been skipped before them.
+	fn field(&mut self, range: Range<usize>, comments: &[String]) {
+		if range.start > self.bit_pos {
+			let skipped = self.bit_pos..range.start;
+
+			self.fill(skipped, "fill bits, MUST be zero");
+		}
+
+		self.show_bytes(range.end);
+
+		let lines = if range.start < range.end {
+			let groups = self.bits(range.clone());
+			// lines of up to 8 groups, the first one shorter, as if the bits were one long number
+			let first = match groups.len() % 8 {
+				0 => 8,
+				n => n,
+			};
+			let mut lines = vec![groups[..cmp::min(first, groups.len())].join(" ")];
+
+			lines.extend(groups[cmp::min(first, groups.len())..].chunks(8).map(|line| line.join(" ")));
+
+			lines
+		} else {
+			Vec::new()
+		};
+		let mut comments = comments.iter();
+
+		match lines.len() {
+			1 if lines[0].len() < COMMENT_COLUMN => match comments.next() {
+				Some(comment) => self.line(&format!("{:width$}# {}", lines[0], comment, width = COMMENT_COLUMN)),
+				None => self.line(&lines[0]),
+			},
+			_ => for line in &lines {
+				self.line(line);
+			},
+		}
+
+		for comment in comments {
+			self.line(&format!("{:width$}# {}", "", comment, width = COMMENT_COLUMN));
+		}
+
+		self.bit_pos = cmp::max(self.bit_pos, range.end);
+	}
+
+	/// Annotates bits that do not encode a value, which are shown in brackets.
+	fn fill(&mut self, range: Range<usize>, comment: &str) {
+		self.show_bytes(range.end);
+
+		let bits = format!("[{}]", self.bits(range.clone()).join(" "));
+
+		self.line(&format!("{:width$}# {}", bits, comment, width = COMMENT_COLUMN));
+		self.bit_pos = range.end;
+	}
+
+	/// Returns the value of the bits in range, the first one least significant.
+	fn value_of(&self, range: Range<usize>) -> usize {
+		range.clone().fold(0, |value, i| value | (((self.input[i >> 3] >> (i & 7)) & 1) as usize) << (i - range.start))
+	}
+
+	/// Shows the first bytes in range, which starts at a byte boundary, in hex next to comment,
+	/// for uncompressed data and metadata.
+	fn data(&mut self, range: Range<usize>, comment: &str) {
+		if range.start > self.bit_pos {
+			let skipped = self.bit_pos..range.start;
+
+			self.fill(skipped, "fill bits, MUST be zero");
+		}
+
+		let bytes = &self.input[cmp::min(range.start >> 3, self.input.len())..cmp::min(range.end >> 3, self.input.len())];
+		let mut rows = bytes[..cmp::min(bytes.len(), MAX_DATA_BYTES)].chunks(4)
+			.map(|row| row.iter().map(|byte| format!("0x{:02x}", byte)).collect::<Vec<_>>().join(" "))
+			.collect::<Vec<_>>();
+
+		if bytes.len() > MAX_DATA_BYTES {
+			rows.push(String::from("…"));
+		}
+
+		self.line(&format!("{:width$}# {}", rows.first().map_or("", |row| row.as_str()), comment, width = COMMENT_COLUMN));
+
+		for row in rows.iter().skip(1) {
+			self.line(row);
+		}
+
+		self.shown_bytes = cmp::max(self.shown_bytes, cmp::min((range.end + 7) >> 3, self.input.len()));
+		self.bit_pos = range.end;
+	}
+
+	/// Annotates bits without showing them, for the data of a meta-block.
+	fn skip(&mut self, range: Range<usize>, comment: &str) {
+		if range.start > self.bit_pos {
+			let skipped = self.bit_pos..range.start;
+
+			self.fill(skipped, "fill bits, MUST be zero");
+		}
+
+		self.line(&format!("{:width$}# {}", "", comment, width = COMMENT_COLUMN));
+		self.shown_bytes = cmp::max(self.shown_bytes, cmp::min((range.end + 7) >> 3, self.input.len()));
+		self.bit_pos = range.end;
+	}
+
+	fn value<T: Display>(&mut self, field: &Option<Field<T>>, comments: &[String]) {
+		if let Some(ref field) = *field {
+			self.field(field.bits.clone(), comments);
+		}
+	}
+
+	fn stream(&mut self, name: &str, structure: &StreamStructure) {
+		let mut hex = self.input.iter().take(16).map(|byte| format!("0x{:02x}", byte)).collect::<Vec<_>>().join(" ");
+
+		if self.input.len() > 16 {
+			hex.push_str(" …");
+		}
+
+		self.line(&format!("Analysis of {}:", name));
+		self.line(&hex);
+		self.section("Brotli Stream Header");
+
+		if let Some(ref wbits) = structure.wbits {
+			self.field(wbits.bits.clone(), &[
+				format!("WBITS => decodes to {}", wbits.value),
+				format!("window size = 1 << WBITS - 16 = {}", (1usize << wbits.value) - 16),
+			]);
+		}
+
+		for meta_block in &structure.meta_blocks {
+			self.meta_block(meta_block);
+		}
+
+		if let Some(ref trailing_bits) = structure.trailing_bits {
+			self.line("");
+
+			if trailing_bits.start < trailing_bits.end {
+				self.fill(trailing_bits.clone(), "trailing bits, MUST be zero");
+				self.line("");
+			}
+
+			self.line("End of Stream");
+		}
+	}
+
+	fn meta_block(&mut self, meta_block: &MetaBlockInfo) {
+		self.section("Brotli Meta-Block Header");
+
+		self.value(&meta_block.is_last, &[match meta_block.is_last.as_ref().map(|is_last| is_last.value) {
+			Some(true) => String::from("ISLAST => true => this is the last Meta-Block"),
+			_ => String::from("ISLAST => false => this is not the last Meta-Block"),
+		}]);
+		self.value(&meta_block.is_last_empty, &[match meta_block.is_last_empty.as_ref().map(|is_last_empty| is_last_empty.value) {
+			Some(true) => String::from("ISLASTEMPTY => true => the Meta-Block is empty, stream ends at this bit"),
+			_ => String::from("ISLASTEMPTY => false => the Meta-Block is not empty"),
+		}]);
+
+		if let Some(ref m_nibbles) = meta_block.m_nibbles {
+			match m_nibbles.value {
+				0 => {
+					self.field(m_nibbles.bits.clone(), &[String::from("MNIBBLES => 3 => MNIBBLES == 0 => metadata Meta-Block")]);
+					self.fill(m_nibbles.bits.end..m_nibbles.bits.end + 1, "reserved bit, MUST be zero");
+				},
+				n => self.field(m_nibbles.bits.clone(), &[format!("MNIBBLES => {} => MNIBBLES == {}", n - 4, n)]),
+			}
+		}
+
+		if let Some(ref m_skip_bytes) = meta_block.m_skip_bytes {
+			self.field(m_skip_bytes.bits.clone(), &[format!("MSKIPBYTES => {}", m_skip_bytes.value)]);
+		}
+
+		if let Some(ref m_skip_len) = meta_block.m_skip_len {
+			self.field(m_skip_len.bits.clone(), &[format!("MSKIPLEN - 1 == {} => MSKIPLEN == {}", m_skip_len.value - 1, m_skip_len.value)]);
+		}
+
+		if let Some(ref m_len) = meta_block.m_len {
+			self.field(m_len.bits.clone(), &[format!("MLEN - 1 == {} => MLEN == {}", m_len.value - 1, m_len.value)]);
+		}
+
+		if let Some(ref is_uncompressed) = meta_block.is_uncompressed {
+			self.field(is_uncompressed.bits.clone(), &[format!("ISUNCOMPRESSED => {}", is_uncompressed.value)]);
+		}
+
+		self.block_types("NBLTYPESL", "literals", &meta_block.literal_block_types);
+		self.block_types("NBLTYPESI", "insert-and-copy lengths", &meta_block.insert_and_copy_length_block_types);
+		self.block_types("NBLTYPESD", "distances", &meta_block.distance_block_types);
+
+		if let Some(ref n_postfix) = meta_block.n_postfix {
+			self.field(n_postfix.bits.clone(), &[format!("NPOSTFIX => {}", n_postfix.value)]);
+
+			if let Some(ref n_direct) = meta_block.n_direct {
+				self.field(n_direct.bits.clone(), &[
+					format!("NDIRECT => {}", n_direct.value >> n_postfix.value),
+					format!("{} direct distance codes", n_direct.value),
+				]);
+			}
+		}
+
+		if let Some(ref context_modes) = meta_block.context_modes {
+			let start = context_modes.bits.start;
+
+			for (i, context_mode) in context_modes.value.iter().enumerate() {
+				self.field(start + 2 * i..start + 2 * i + 2, &[format!("context mode for literal block type {} => {}", i, context_mode)]);
+			}
+		}
+
+		self.value(&meta_block.n_trees_l, &[format!("NTREESL => {}", meta_block.n_trees_l.as_ref().map_or(0, |n_trees_l| n_trees_l.value))]);
+
+		if let Some(ref context_map) = meta_block.literal_context_map {
+			self.context_map("literals", context_map);
+		}
+
+		self.value(&meta_block.n_trees_d, &[format!("NTREESD => {}", meta_block.n_trees_d.as_ref().map_or(0, |n_trees_d| n_trees_d.value))]);
+
+		if let Some(ref context_map) = meta_block.distance_context_map {
+			self.context_map("distances", context_map);
+		}
+
+		for (i, prefix_code) in meta_block.literal_prefix_codes.iter().enumerate() {
+			self.prefix_code(&format!("prefix code {} for literals", i), prefix_code);
+		}
+
+		for (i, prefix_code) in meta_block.insert_and_copy_length_prefix_codes.iter().enumerate() {
+			self.prefix_code(&format!("prefix code {} for insert-and-copy lengths", i), prefix_code);
+		}
+
+		for (i, prefix_code) in meta_block.distance_prefix_codes.iter().enumerate() {
+			self.prefix_code(&format!("prefix code {} for distances", i), prefix_code);
+		}
+
+		if let Some(ref data_bits) = meta_block.data_bits {
+			self.line("");
+
+			match (meta_block.m_skip_len.as_ref(), meta_block.is_uncompressed.as_ref().map(|is_uncompressed| is_uncompressed.value)) {
+				(Some(m_skip_len), _) => self.data(data_bits.clone(), &format!("MSKIPLEN (== {}) bytes of metadata", m_skip_len.value)),
+				(None, Some(true)) => self.data(data_bits.clone(), &format!("MLEN (== {}) bytes of literal data", meta_block.m_len.as_ref().map_or(0, |m_len| m_len.value))),
+				_ => self.skip(data_bits.clone(), &format!("{} bits of compressed data", data_bits.end - data_bits.start)),
+			}
+		}
+
+		if meta_block.bits.end > self.bit_pos {
+			let fill = self.bit_pos..meta_block.bits.end;
+
+			self.fill(fill, "fill bits, MUST be zero");
+		}
+	}
+
+	fn block_types(&mut self, name: &str, category: &str, block_types: &Option<BlockTypesInfo>) {
+		if let Some(ref block_types) = *block_types {
+			self.field(block_types.n_bltypes.bits.clone(), &[format!("{} => {}", name, block_types.n_bltypes.value)]);
+
+			if let Some(ref prefix_code) = block_types.block_type_code {
+				self.prefix_code(&format!("prefix code of block types for {}", category), prefix_code);
+			}
+
+			if let Some(ref prefix_code) = block_types.block_count_code {
+				self.prefix_code(&format!("prefix code of block counts for {}", category), prefix_code);
+			}
+
+			if let Some(ref first_block_count) = block_types.first_block_count {
+				self.field(first_block_count.bits.clone(), &[format!("first block count for {} => {}", category, first_block_count.value)]);
+			}
+		}
+	}
+
+	fn context_map(&mut self, category: &str, context_map: &ContextMapInfo) {
+		self.field(context_map.rle_max.bits.clone(), &[format!("RLEMAX => {}", context_map.rle_max.value)]);
+		self.prefix_code(&format!("prefix code of the context map for {}", category), &context_map.prefix_code);
+
+		let mut comments = vec![format!("context map for {}, {} entries", category, context_map.map.len())];
+
+		comments.extend(context_map.map.chunks(16).map(join));
+
+		self.field(context_map.prefix_code.bits.end..context_map.inverse_move_to_front.bits.start, &comments);
+		self.field(context_map.inverse_move_to_front.bits.clone(), &[format!("IMTF => {}", context_map.inverse_move_to_front.value)]);
+	}
+
+	/// Annotates the fields of a prefix code, the code lengths it comes out to after the last one.
+	fn prefix_code(&mut self, title: &str, prefix_code: &PrefixCodeInfo) {
+		let start = prefix_code.bits.start;
+		let mut fields = Vec::new();
+
+		self.show_bytes(start + 2);
+
+		if !self.out.ends_with("\n\n") {
+			self.line("");
+		}
+
+		self.line(&format!("{:width$}# {}", "", title, width = COMMENT_COLUMN));
+
+		match (prefix_code.h_skip, prefix_code.code_length_code_lengths.as_ref()) {
+			(Some(h_skip), Some(code_length_code_lengths)) => {
+				let mut space = 0;
+				let mut end = start + 2;
+
+				for &symbol in &CODE_LENGTH_ORDER[h_skip as usize..] {
+					let code_length = code_length_code_lengths[symbol];
+
+					end += CODE_LENGTH_CODE_BITS[code_length];
+
+					if code_length > 0 {
+						space += 32 >> code_length;
+
+						if space >= 32 {
+							break;
+						}
+					}
+				}
+
+				fields.push((start..start + 2, vec![format!("HSKIP => {} => complex prefix code", h_skip)]));
+				fields.push((start + 2..end, vec![format!("code lengths of code length symbols 0...17 => {}", join(code_length_code_lengths))]));
+				fields.push((end..prefix_code.bits.end, vec![String::from("code lengths of the symbols")]));
+			},
+			_ => {
+				let n_sym = prefix_code.symbols.len();
+				let width = alphabet_bits(prefix_code.alphabet_size);
+
+				fields.push((start..start + 2, vec![String::from("simple prefix code")]));
+				fields.push((start + 2..start + 4, vec![format!("NSYM - 1 == {} => NSYM == {}", n_sym - 1, n_sym)]));
+
+				for i in 0..n_sym {
+					let bits = start + 4 + i * width..start + 4 + (i + 1) * width;
+					let symbol = self.value_of(bits.clone());
+
+					fields.push((bits, vec![format!("Symbol #{} => 0x{:02x} == {}", i + 1, symbol, symbol)]));
+				}
+
+				if let Some(tree_select) = prefix_code.tree_select {
+					fields.push((start + 4 + n_sym * width..prefix_code.bits.end, vec![format!("tree-select => {}", tree_select)]));
+				}
+			},
+		}
+
+		if let Some(&mut (_, ref mut comments)) = fields.last_mut() {
+			comments.push(format!("alphabet size {}, {} symbols, as symbol:code length", prefix_code.alphabet_size, prefix_code.symbols.len()));
+			comments.extend(prefix_code.symbols.iter()
+				.zip(&prefix_code.code_lengths)
+				.map(|(symbol, code_length)| format!("{}:{}", symbol, code_length))
+				.collect::<Vec<_>>()
+				.chunks(8)
+				.map(|chunk| chunk.join(" ")));
+		}
+
+		for (bits, comments) in fields {
+			self.field(bits, &comments);
+		}
+	}
+}
+
+/// Returns the number of bits of a symbol of a simple prefix code for an alphabet.
+fn alphabet_bits(alphabet_size: usize) -> usize {
+	let mut bits = 0;
+
+	while 1 << bits < alphabet_size {
+		bits += 1;
+	}
+
+	bits
+}
+
+fn join<T: Display>(values: &[T]) -> String {
+	values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// Minimal JSON document, for --json.
+enum Json {
+	Null,
+	Bool(bool),
+	Number(u64),
+	String(String),
+	Array(Vec<Json>),
+	Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+	fn write(&self, out: &mut String, indent: usize) {
+		match *self {
+			Json::Null => out.push_str("null"),
+			Json::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+			Json::Number(n) => out.push_str(&n.to_string()),
+			Json::String(ref s) => {
+				out.push('"');
+
+				for c in s.chars() {
+					match c {
+						'"' => out.push_str("\\\""),
+						'\\' => out.push_str("\\\\"),
+						'\n' => out.push_str("\\n"),
+						c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+						c => out.push(c),
+					}
+				}
+
+				out.push('"');
+			},
+			Json::Array(ref items) if items.iter().all(Json::is_scalar) => {
+				out.push('[');
+
+				for (i, item) in items.iter().enumerate() {
+					if i > 0 {
+						out.push_str(", ");
+					}
+
+					item.write(out, indent);
+				}
+
+				out.push(']');
+			},
+			Json::Array(ref items) => {
+				out.push('[');
+
+				for (i, item) in items.iter().enumerate() {
+					out.push_str(if i > 0 { ",\n" } else { "\n" });
+					out.push_str(&"  ".repeat(indent + 1));
+					item.write(out, indent + 1);
+				}
+
+				out.push('\n');
+				out.push_str(&"  ".repeat(indent));
+				out.push(']');
+			},
+			Json::Object(ref members) => {
+				out.push('{');
+
+				for (i, &(key, ref value)) in members.iter().enumerate() {
+					out.push_str(if i > 0 { ",\n" } else { "\n" });
+					out.push_str(&"  ".repeat(indent + 1));
+					out.push_str(&format!("\"{}\": ", key));
+					value.write(out, indent + 1);
+				}
+
+				out.push('\n');
+				out.push_str(&"  ".repeat(indent));
+				out.push('}');
+			},
+		}
+	}
+
+	fn is_scalar(&self) -> bool {
+		!matches!(*self, Json::Array(_) | Json::Object(_))
+	}
+}
+
+fn json_bits(bits: &Range<usize>) -> Json {
+	Json::Array(vec![Json::Number(bits.start as u64), Json::Number(bits.end as u64)])
+}
+
+fn json_field<T, F: Fn(&T) -> Json>(field: &Option<Field<T>>, value: F) -> Json {
+	match *field {
+		Some(ref field) => Json::Object(vec![
+			("bits", json_bits(&field.bits)),
+			("value", value(&field.value)),
+		]),
+		None => Json::Null,
+	}
+}
+
+fn json_number<T: Copy + Into<u64>>(value: &T) -> Json {
+	Json::Number((*value).into())
+}
+
+fn json_bool(value: &bool) -> Json {
+	Json::Bool(*value)
+}
+
+fn json_numbers<T: Copy + Into<u64>>(values: &[T]) -> Json {
+	Json::Array(values.iter().map(json_number).collect())
+}
+
+fn json_usizes(values: &[usize]) -> Json {
+	Json::Array(values.iter().map(|&value| Json::Number(value as u64)).collect())
+}
+
+fn json_prefix_code(prefix_code: &PrefixCodeInfo) -> Json {
+	Json::Object(vec![
+		("bits", json_bits(&prefix_code.bits)),
+		("alphabet_size", Json::Number(prefix_code.alphabet_size as u64)),
+		("h_skip", prefix_code.h_skip.map_or(Json::Null, |h_skip| Json::Number(h_skip as u64))),
+		("code_length_code_lengths", prefix_code.code_length_code_lengths.as_ref().map_or(Json::Null, |lengths| json_usizes(lengths))),
+		("tree_select", prefix_code.tree_select.map_or(Json::Null, Json::Bool)),
+		("symbols", json_numbers(&prefix_code.symbols)),
+		("code_lengths", json_usizes(&prefix_code.code_lengths)),
+	])
+}
+
+fn json_block_types(block_types: &Option<BlockTypesInfo>) -> Json {
+	match *block_types {
+		Some(ref block_types) => Json::Object(vec![
+			("n_bltypes", json_field(&Some(block_types.n_bltypes.clone()), json_number)),
+			("block_type_code", block_types.block_type_code.as_ref().map_or(Json::Null, json_prefix_code)),
+			("block_count_code", block_types.block_count_code.as_ref().map_or(Json::Null, json_prefix_code)),
+			("first_block_count", json_field(&block_types.first_block_count, json_number)),
+		]),
+		None => Json::Null,
+	}
+}
+
+fn json_context_map(context_map: &Option<ContextMapInfo>) -> Json {
+	match *context_map {
+		Some(ref context_map) => Json::Object(vec![
+			("bits", json_bits(&context_map.bits)),
+			("rle_max", json_field(&Some(context_map.rle_max.clone()), json_number)),
+			("prefix_code", json_prefix_code(&context_map.prefix_code)),
+			("inverse_move_to_front", json_field(&Some(context_map.inverse_move_to_front.clone()), json_bool)),
+			("map", json_numbers(&context_map.map)),
+		]),
+		None => Json::Null,
+	}
+}
+
+fn json_meta_block(meta_block: &MetaBlockInfo, input: &[u8]) -> Json {
+	Json::Object(vec![
+		("bits", json_bits(&meta_block.bits)),
+		("is_last", json_field(&meta_block.is_last, json_bool)),
+		("is_last_empty", json_field(&meta_block.is_last_empty, json_bool)),
+		("m_nibbles", json_field(&meta_block.m_nibbles, json_number)),
+		("m_skip_bytes", json_field(&meta_block.m_skip_bytes, json_number)),
+		("m_skip_len", json_field(&meta_block.m_skip_len, json_number)),
+		("m_len", json_field(&meta_block.m_len, json_number)),
+		("is_uncompressed", json_field(&meta_block.is_uncompressed, json_bool)),
+		("literal_block_types", json_block_types(&meta_block.literal_block_types)),
+		("insert_and_copy_length_block_types", json_block_types(&meta_block.insert_and_copy_length_block_types)),
+		("distance_block_types", json_block_types(&meta_block.distance_block_types)),
+		("n_postfix", json_field(&meta_block.n_postfix, json_number)),
+		("n_direct", json_field(&meta_block.n_direct, json_number)),
+		("context_modes", json_field(&meta_block.context_modes, |modes| json_numbers(modes))),
+		("n_trees_l", json_field(&meta_block.n_trees_l, json_number)),
+		("literal_context_map", json_context_map(&meta_block.literal_context_map)),
+		("n_trees_d", json_field(&meta_block.n_trees_d, json_number)),
+		("distance_context_map", json_context_map(&meta_block.distance_context_map)),
+		("literal_prefix_codes", Json::Array(meta_block.literal_prefix_codes.iter().map(json_prefix_code).collect())),
+		("insert_and_copy_length_prefix_codes", Json::Array(meta_block.insert_and_copy_length_prefix_codes.iter().map(json_prefix_code).collect())),
+		("distance_prefix_codes", Json::Array(meta_block.distance_prefix_codes.iter().map(json_prefix_code).collect())),
+		("data_bits", meta_block.data_bits.as_ref().map_or(Json::Null, json_bits)),
+		("data", json_data(meta_block, input)),
+	])
+}
+
+/// Returns the bytes of uncompressed data or metadata, which are not encoded otherwise.
+fn json_data(meta_block: &MetaBlockInfo, input: &[u8]) -> Json {
+	let is_uncompressed = meta_block.is_uncompressed.as_ref().is_some_and(|is_uncompressed| is_uncompressed.value);
+
+	match meta_block.data_bits {
+		Some(ref data_bits) if is_uncompressed || meta_block.m_skip_len.is_some() => {
+			let bytes = &input[cmp::min(data_bits.start >> 3, input.len())..cmp::min(data_bits.end >> 3, input.len())];
+
+			Json::Array(bytes.iter().map(|&byte| Json::Number(u64::from(byte))).collect())
+		},
+		_ => Json::Null,
+	}
+}
+
+fn json_stream(structure: &StreamStructure, input: &[u8], error: Option<&io::Error>) -> Json {
+	Json::Object(vec![
+		("wbits", json_field(&structure.wbits, json_number)),
+		("window_size", structure.window_size().map_or(Json::Null, |window_size| Json::Number(window_size as u64))),
+		("meta_blocks", Json::Array(structure.meta_blocks.iter().map(|meta_block| json_meta_block(meta_block, input)).collect())),
+		("trailing_bits", structure.trailing_bits.as_ref().map_or(Json::Null, json_bits)),
+		("error", error.map_or(Json::Null, |e| Json::String(e.to_string()))),
+	])
+}
+
+fn usage() -> ! {
+	let _ = writeln!(io::stderr(), "usage: brotli-dump [--json] [FILE]");
+	let _ = writeln!(io::stderr(), "Annotates the bits of the brotli stream in FILE, or on standard input, with the fields they decode to.");
+
+	process::exit(2);
+}
+
+fn main() {
+	let mut json = false;
+	let mut path = None;
+
+	for arg in env::args().skip(1) {
+		match arg.as_str() {
+			"--json" => json = true,
+			"-h" | "--help" => usage(),
+			_ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+			_ => usage(),
+		}
+	}
+
+	let mut input = Vec::new();
+	let read = match path {
+		Some(ref path) => File::open(path).and_then(|mut file| file.read_to_end(&mut input)),
+		None => io::stdin().read_to_end(&mut input),
+	};
+
+	if let Err(e) = read {
+		let _ = writeln!(io::stderr(), "brotli-dump: {}", e);
+
+		process::exit(1);
+	}
+
+	let name = match path {
+		Some(ref path) => Path::new(path).file_name().map_or(path.clone(), |name| name.to_string_lossy().into_owned()),
+		None => String::from("standard input"),
+	};
+
+	// an invalid stream is dumped up to the point where it fails to decode
+	let mut decompressor = Decompressor::new(&input[..]);
+
+	decompressor.record_structure();
+
+	let result = io::copy(&mut decompressor, &mut io::sink());
+	let structure = match decompressor.structure() {
+		Some(structure) => structure,
+		None => unreachable!(), // confirmed unreachable, recording has been started before the first read
+	};
+
+	let mut out = if json {
+		let mut out = String::new();
+
+		json_stream(structure, &input, result.as_ref().err()).write(&mut out, 0);
+		out.push('\n');
+
+		out
+	} else {
+		let mut annotator = Annotator::new(&input);
+
+		annotator.stream(&name, structure);
+
+		annotator.out
+	};
+
+	if let (false, Err(e)) = (json, &result) {
+		out.push_str(&format!("\nError: {}\n", e));
+	}
+
+	if io::stdout().write_all(out.as_bytes()).is_err() || result.is_err() {
+		process::exit(1);
+	}
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{ Display, Formatter };
@@ -23,6 +24,12 @@ pub struct BitReader<R: Read> {
 	bit_pos: u8,
 	current_byte: Option<u8>,
 	global_bit_pos: usize,
+	/// bytes read from inner since the last call to mark(), if any
+	recorded: Option<Vec<u8>>,
+	/// position as of the last call to mark()
+	marked: (usize, u8, Option<u8>),
+	/// bytes that have been rewound by reset(), to be read again before reading from inner
+	replay: VecDeque<u8>,
 }
 
 impl<R: Read> BitReader<R> {
@@ -33,20 +40,114 @@ impl<R: Read> BitReader<R> {
 			bit_pos: 0,
 			current_byte: None,
 			global_bit_pos: 0,
+			recorded: None,
+			marked: (0, 0, None),
+			replay: VecDeque::new(),
 		}
 	}
 
-	fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
-		while !buf.is_empty() {
-			match self.inner.read(buf) {
+	/// Creates a BitReader that continues reading a stream from a previously saved position,
+	/// as returned by position(). The inner reader must be positioned at the first byte that
+	/// has not been read at all, i.e. current_byte, if any, has already been consumed from it.
+	pub fn resume(inner: R, global_bit_pos: usize, bit_pos: u8, current_byte: Option<u8>) -> BitReader<R> {
+		BitReader{
+			inner: BufReader::new(inner),
+			bit_pos,
+			current_byte,
+			global_bit_pos,
+			recorded: None,
+			marked: (0, 0, None),
+			replay: VecDeque::new(),
+		}
+	}
+
+	/// Returns the position within the stream as (global_bit_pos, bit_pos, current_byte),
+	/// where current_byte holds the partially consumed byte, if any.
+	pub fn position(&self) -> (usize, u8, Option<u8>) {
+		(self.global_bit_pos, self.bit_pos, self.current_byte)
+	}
+
+	/// Unwraps this BitReader, returning the underlying reader.
+	/// Data that has been buffered, but not yet read, is lost.
+	pub fn into_inner(self) -> R {
+		self.inner.into_inner()
+	}
+
+	/// Gets a reference to the underlying reader.
+	pub fn get_ref(&self) -> &R {
+		self.inner.get_ref()
+	}
+
+	/// Gets a mutable reference to the underlying reader.
+	pub fn get_mut(&mut self) -> &mut R {
+		self.inner.get_mut()
+	}
+
+	/// Remembers the current position, and starts recording the bytes that are read from here on,
+	/// so that reset() can rewind the BitReader to this point.
+	pub fn mark(&mut self) {
+		match self.recorded {
+			Some(ref mut recorded) => recorded.clear(),
+			None => self.recorded = Some(Vec::new()),
+		}
+
+		self.marked = self.position();
+	}
+
+	/// Returns the number of bytes that have been read since the last call to mark().
+	pub fn len_since_mark(&self) -> usize {
+		self.recorded.as_ref().map_or(0, |recorded| recorded.len())
+	}
+
+	/// Rewinds the BitReader to the position of the last call to mark(),
+	/// the bytes read since then will be read again.
+	pub fn reset(&mut self) {
+		if let Some(ref mut recorded) = self.recorded {
+			for &byte in recorded.iter().rev() {
+				self.replay.push_front(byte);
+			}
+			recorded.clear();
+
+			let (global_bit_pos, bit_pos, current_byte) = self.marked;
+			self.global_bit_pos = global_bit_pos;
+			self.bit_pos = bit_pos;
+			self.current_byte = current_byte;
+		}
+	}
+
+	/// Returns the number of bits that have been read from the stream so far.
+	pub fn global_bit_pos(&self) -> usize {
+		self.global_bit_pos
+	}
+
+	fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+		let mut pos = 0;
+
+		while pos < buf.len() {
+			match self.replay.pop_front() {
+				Some(byte) => {
+					buf[pos] = byte;
+					pos += 1;
+				},
+				None => break,
+			}
+		}
+
+		while pos < buf.len() {
+			match self.inner.read(&mut buf[pos..]) {
 				Ok(0) => break,
-				Ok(n) => { let tmp = buf; buf = &mut tmp[n..]; }
+				Ok(n) => pos += n,
 				Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
 				Err(e) => return Err(e),
 			}
 		}
-		if !buf.is_empty() {
-			Err(io::Error::new(ErrorKind::Other, "EOF"))
+
+		if let Some(ref mut recorded) = self.recorded {
+			recorded.extend_from_slice(&buf[0..pos]);
+		}
+
+		if pos < buf.len() {
+			Err(io::Error::new(ErrorKind::UnexpectedEof, "EOF"))
 		} else {
 			Ok(())
 		}
@@ -75,8 +176,8 @@ impl<R: Read> BitReader<R> {
 				self.global_bit_pos += 8;
 				Ok(byte)
 			},
-			(_, _, Err(e)) => if e.description() == "EOF" {
-				Err(BitReaderError::EOF)
+			(_, _, Err(e)) => if e.kind() == ErrorKind::UnexpectedEof {
+				Err(BitReaderError::Eof)
 			} else {
 				Err(BitReaderError::Unspecified)
 			},
@@ -98,8 +199,8 @@ impl<R: Read> BitReader<R> {
 					self.current_byte = Some(buf[0]);
 					Ok(buf[0] & 0x0f)
 				},
-				Err(e) => if e.description() == "EOF" {
-					Err(BitReaderError::EOF)
+				Err(e) => if e.kind() == ErrorKind::UnexpectedEof {
+					Err(BitReaderError::Eof)
 				} else {
 					Err(BitReaderError::Unspecified)
 				},
@@ -123,8 +224,8 @@ impl<R: Read> BitReader<R> {
 						self.current_byte = Some(buf[0]);
 						Ok(((byte >> (bit_pos)) | (buf[0] << (8 - bit_pos))) & 0x0f)
 					},
-					Err(e) => if e.description() == "EOF" {
-						Err(BitReaderError::EOF)
+					Err(e) => if e.kind() == ErrorKind::UnexpectedEof {
+						Err(BitReaderError::Eof)
 					} else {
 						Err(BitReaderError::Unspecified)
 					},
@@ -315,7 +416,7 @@ pub enum BitReaderError {
 	/// Tried to read u32 from more than 32 bits.
 	TooManyBitsForU32,
 	/// Unexpected end of file
-	EOF,
+	Eof,
 }
 
 impl Display for BitReaderError {
@@ -330,7 +431,7 @@ impl Error for BitReaderError {
 			BitReaderError::TooManyBitsForU8 => "Tried reading u8 from more than 8 bits",
 			BitReaderError::TooManyBitsForU16 => "Tried reading u16 from more than 16 bits",
 			BitReaderError::TooManyBitsForU32 => "Tried reading u32 from more than 32 bits",
-			BitReaderError::EOF => "EOF",
+			BitReaderError::Eof => "EOF",
 			_ => "Generic error",
 		}
 	}
@@ -365,6 +466,17 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn should_report_eof_at_end_of_stream() {
+		use super::*;
+		use std::io::Cursor;
+
+		let mut br = BitReader::new(Cursor::new(vec![0x1f]));
+
+		assert_eq!(Ok(0x1f), br.read_u8());
+		assert_eq!(Err(BitReaderError::Eof), br.read_u8());
+	}
+
 	#[test]
 	fn should_read_one_set_bit() {
 		use super::*;
@@ -557,4 +669,25 @@ mod tests {
 			_ => panic!("Should have read 524527u32"),
 		}
 	}
+
+	#[test]
+	fn should_read_the_same_bits_again_after_reset() {
+		use super::*;
+		use std::io::Cursor;
+
+		let mut br = BitReader::new(Cursor::new(vec![0b1010_0110, 0x8b, 0x1f]));
+
+		let _ = br.read_u8_from_n_bits(3);
+		br.mark();
+
+		let first = (br.read_u8_from_n_bits(7), br.read_u8(), br.read_u8());
+
+		assert_eq!(2, br.len_since_mark());
+		assert_eq!(Err(BitReaderError::Unspecified), br.read_u8_from_n_bits(7));
+
+		br.reset();
+
+		assert_eq!(3, br.global_bit_pos());
+		assert_eq!(first, (br.read_u8_from_n_bits(7), br.read_u8(), br.read_u8()));
+	}
 }
@@ -0,0 +1,237 @@
+use std::cmp;
+use std::io;
+use std::io::Write;
+
+/// Wrapper for a Writer, providing convenience methods to write a stream bit-by-bit.
+/// Bits are packed into bytes starting at the least significant bit, the same order
+/// in which BitReader reads them.
+#[derive(Debug)]
+pub struct BitWriter<W: Write> {
+	inner: W,
+	/// number of bits of current_byte that are in use
+	bit_pos: u8,
+	current_byte: u8,
+}
+
+impl<W: Write> BitWriter<W> {
+	/// Creates a BitWriter from a Write.
+	pub fn new(inner: W) -> BitWriter<W> {
+		BitWriter{
+			inner,
+			bit_pos: 0,
+			current_byte: 0,
+		}
+	}
+
+	/// Unwraps this BitWriter, returning the underlying writer.
+	/// The bits of an incomplete last byte are lost, align() keeps them.
+	pub fn into_inner(self) -> W {
+		self.inner
+	}
+
+	/// Gets a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		&self.inner
+	}
+
+	/// Gets a mutable reference to the underlying writer.
+	/// Only complete bytes have been written to it.
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.inner
+	}
+
+	/// Writes the n least significant bits of value, least significant bit first.
+	pub fn write_bits(&mut self, value: u32, n: usize) -> io::Result<()> {
+		assert!(n <= 32);
+
+		let mut value = u64::from(value) & ((1 << n) - 1);
+		let mut n = n;
+
+		while n > 0 {
+			let len = cmp::min(8 - self.bit_pos as usize, n);
+
+			self.current_byte |= ((value & ((1 << len) - 1)) << self.bit_pos) as u8;
+			self.bit_pos += len as u8;
+			value >>= len;
+			n -= len;
+
+			if self.bit_pos == 8 {
+				self.inner.write_all(&[self.current_byte])?;
+				self.current_byte = 0;
+				self.bit_pos = 0;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Writes the n least significant nibbles of value, least significant nibble first.
+	pub fn write_nibbles(&mut self, value: u32, n: usize) -> io::Result<()> {
+		assert!(n <= 8);
+
+		self.write_bits(value, 4 * n)
+	}
+
+	/// Writes bytes, which are passed through to the underlying writer if the stream is
+	/// at a byte boundary, and are split across byte boundaries otherwise.
+	pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+		if self.bit_pos == 0 {
+			return self.inner.write_all(bytes);
+		}
+
+		for &byte in bytes {
+			self.write_bits(u32::from(byte), 8)?;
+		}
+
+		Ok(())
+	}
+
+	/// Writes a single bit.
+	pub fn write_bit(&mut self, bit: bool) -> io::Result<()> {
+		self.write_bits(bit as u32, 1)
+	}
+
+	/// Returns whether the stream is at a byte boundary.
+	pub fn is_aligned(&self) -> bool {
+		self.bit_pos == 0
+	}
+
+	/// Fills the rest of the current byte with zero bits.
+	pub fn align(&mut self) -> io::Result<()> {
+		match self.bit_pos {
+			0 => Ok(()),
+			bit_pos => self.write_bits(0, 8 - bit_pos as usize),
+		}
+	}
+}
+
+mod tests {
+	#[test]
+	fn should_write_bits_lsb_first() {
+		use super::*;
+
+		let mut bw = BitWriter::new(Vec::new());
+
+		bw.write_bits(0b1, 1).unwrap();
+		bw.write_bits(0b10, 2).unwrap();
+		bw.write_bits(0b11111, 5).unwrap();
+		bw.write_bits(0x1234, 16).unwrap();
+
+		assert_eq!(&vec![0b1111_1101, 0x34, 0x12], bw.get_ref());
+	}
+
+	#[test]
+	fn should_align_with_zero_bits() {
+		use super::*;
+
+		let mut bw = BitWriter::new(Vec::new());
+
+		bw.write_bit(true).unwrap();
+		bw.align().unwrap();
+		bw.align().unwrap();
+		bw.write_bits(0b11, 2).unwrap();
+		bw.align().unwrap();
+
+		assert_eq!(&vec![0b0000_0001, 0b0000_0011], bw.get_ref());
+	}
+
+	#[test]
+	fn should_write_bytes_across_byte_boundaries() {
+		use super::*;
+
+		let mut bw = BitWriter::new(Vec::new());
+
+		bw.write_bytes(&[0xab]).unwrap();
+		bw.write_nibbles(0x5, 1).unwrap();
+		bw.write_bytes(&[0xcd, 0xef]).unwrap();
+		bw.write_nibbles(0x21, 2).unwrap();
+		bw.align().unwrap();
+
+		assert_eq!(&vec![0xab, 0xd5, 0xfc, 0x1e, 0x02], bw.get_ref());
+	}
+
+	#[test]
+	fn should_write_what_bit_reader_reads() {
+		use super::*;
+		use ::bitreader::BitReader;
+
+		#[derive(Debug)]
+		enum Op {
+			U8(u8),
+			Nibble(u8),
+			U32FromNBits(u32, usize),
+			U32FromNNibbles(u32, usize),
+			Bit(bool),
+			BitAsUsize(usize),
+			U8FromNBits(u8, usize),
+			U8FromByteTail(u8),
+			U16FromNBits(u16, usize),
+			FixedLengthString(Vec<u8>),
+		}
+
+		let mut random = ::testing::random(0x2545_f491_4f6c_dd1d);
+
+		for _ in 0..500 {
+			let mut bw = BitWriter::new(Vec::new());
+			let mut ops = Vec::new();
+
+			for _ in 0..random(40) {
+				let n = random(33) as usize;
+				let value = (random(1 << 32) as u32) & (((1u64 << n) - 1) as u32);
+
+				let op = match random(10) {
+					0 => Op::U8(value as u8),
+					1 => Op::Nibble(value as u8 & 0x0f),
+					2 => Op::U32FromNBits(value, n),
+					3 => Op::U32FromNNibbles(value & (((1u64 << (4 * (n / 4))) - 1) as u32), n / 4),
+					4 => Op::Bit(value & 1 == 1),
+					5 => Op::BitAsUsize(value as usize & 1),
+					6 => Op::U8FromNBits(value as u8 & ((1u16 << (n % 9)) - 1) as u8, n % 9),
+					7 => Op::U8FromByteTail(value as u8 & ((1u16 << ((8 - bw.bit_pos) % 8)) - 1) as u8),
+					8 => Op::U16FromNBits(value as u16 & ((1u32 << (n % 17)) - 1) as u16, n % 17),
+					_ => Op::FixedLengthString((0..n).map(|i| (value as usize + 37 * i) as u8).collect()),
+				};
+
+				match op {
+					Op::U8(v) => bw.write_bytes(&[v]),
+					Op::Nibble(v) => bw.write_nibbles(u32::from(v), 1),
+					Op::U32FromNBits(v, n) => bw.write_bits(v, n),
+					Op::U32FromNNibbles(v, n) => bw.write_nibbles(v, n),
+					Op::Bit(v) => bw.write_bit(v),
+					Op::BitAsUsize(v) => bw.write_bits(v as u32, 1),
+					Op::U8FromNBits(v, n) => bw.write_bits(u32::from(v), n),
+					Op::U8FromByteTail(v) => {
+						let n = (8 - bw.bit_pos as usize) % 8;
+						bw.write_bits(u32::from(v), n)
+					},
+					Op::U16FromNBits(v, n) => bw.write_bits(u32::from(v), n),
+					Op::FixedLengthString(ref v) => bw.write_bytes(v),
+				}.unwrap();
+
+				ops.push(op);
+			}
+
+			bw.align().unwrap();
+
+			let mut br = BitReader::new(&bw.get_ref()[..]);
+
+			for op in &ops {
+				match *op {
+					Op::U8(v) => assert_eq!(Ok(v), br.read_u8()),
+					Op::Nibble(v) => assert_eq!(Ok(v), br.read_u8_from_nibble()),
+					Op::U32FromNBits(v, n) => assert_eq!(Ok(v), br.read_u32_from_n_bits(n)),
+					Op::U32FromNNibbles(v, n) => assert_eq!(Ok(v), br.read_u32_from_n_nibbles(n)),
+					Op::Bit(v) => assert_eq!(Ok(v), br.read_bit()),
+					Op::BitAsUsize(v) => assert_eq!(Ok(v), br.read_bit_as_usize()),
+					Op::U8FromNBits(v, n) => assert_eq!(Ok(v), br.read_u8_from_n_bits(n)),
+					Op::U8FromByteTail(v) => assert_eq!(Ok(v), br.read_u8_from_byte_tail()),
+					Op::U16FromNBits(v, n) => assert_eq!(Ok(v), br.read_u16_from_n_bits(n)),
+					Op::FixedLengthString(ref v) => assert_eq!(Ok(v.clone()), br.read_fixed_length_string(v.len())),
+				}
+			}
+
+			assert_eq!(Ok(0), br.read_u8_from_byte_tail());
+			assert!(br.read_bit().is_err());
+		}
+	}
+}
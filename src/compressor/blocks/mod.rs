@@ -0,0 +1,867 @@
+use super::{ NUM_INSERT_AND_COPY_LENGTH_SYMBOLS, NUM_LITERAL_SYMBOLS, bit_costs, log2_floor };
+use ::bitwriter::BitWriter;
+use ::huffman::PrefixCode;
+use ::lookuptable::{ LUT_0, LUT_1, LUT_2 };
+
+use std::cmp;
+use std::f32;
+use std::io;
+use std::io::Write;
+
+pub const NUM_LITERAL_CONTEXTS: usize = 64;
+pub const NUM_DISTANCE_CONTEXTS: usize = 4;
+/// LSB6, MSB6, UTF8, Signed
+const NUM_CONTEXT_MODES: usize = 4;
+/// Maximum number of block types per category, and of prefix codes per context map.
+const MAX_TYPES: usize = 256;
+/// (base, number of extra bits) per block count code
+const BLOCK_COUNT_CODES: [(usize, usize); 26] = [
+	(1, 2), (5, 2), (9, 2), (13, 2), (17, 3), (25, 3), (33, 3), (41, 3),
+	(49, 4), (65, 4), (81, 4), (97, 4), (113, 5), (145, 5), (177, 5), (209, 5),
+	(241, 6), (305, 6), (369, 7), (497, 8), (753, 9), (1265, 10), (2289, 11), (4337, 12),
+	(8433, 13), (16625, 24)];
+/// Maximum number of block types that the symbols of a category are split into at first.
+const MAX_INITIAL_TYPES: usize = 16;
+/// Number of times that the symbols are reassigned to the block types.
+const SPLIT_ITERATIONS: usize = 3;
+/// Histograms are clustered in batches of this many, before their clusters are clustered together.
+const CLUSTER_BATCH_LEN: usize = 64;
+/// The longest run of zeros in a context map that a single run length code covers is 2^17 - 1.
+const MAX_RLEMAX: usize = 16;
+
+/// The block types and context maps of a meta-block, and the histograms of the prefix codes they refer to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockLayout {
+	pub literal_split: BlockSplit,
+	pub insert_and_copy_length_split: BlockSplit,
+	pub distance_split: BlockSplit,
+	/// context mode of every literal block type
+	pub context_modes: Vec<usize>,
+	/// prefix code of every literal block type and context
+	pub literal_context_map: Vec<usize>,
+	/// prefix code of every distance block type and context
+	pub distance_context_map: Vec<usize>,
+	pub literal_histograms: Vec<Vec<u32>>,
+	pub insert_and_copy_length_histograms: Vec<Vec<u32>>,
+	pub distance_histograms: Vec<Vec<u32>>,
+}
+
+impl BlockLayout {
+	/// Lays out the symbols of a meta-block, with the literals as (literal, p1, p2), and the distance
+	/// codes as (distance code, copy length of the command). Without block splitting, every category
+	/// has a single block type, and without context modeling, every block type a single prefix code.
+	pub fn new(literals: &[(u8, u8, u8)], insert_and_copy_lengths: &[usize], distances: &[(usize, usize)], num_distance_symbols: usize,
+		block_splitting: bool, context_modeling: bool) -> BlockLayout {
+		let split = |symbols: &[usize], alphabet_size: usize, params: &SplitParams| match block_splitting {
+			true => BlockSplit::new(symbols, alphabet_size, params),
+			false => BlockSplit::single(symbols.len()),
+		};
+		let literal_split = split(&literals.iter().map(|&(literal, _, _)| literal as usize).collect::<Vec<_>>(), NUM_LITERAL_SYMBOLS, &LITERAL_SPLIT);
+		let insert_and_copy_length_split = split(insert_and_copy_lengths, NUM_INSERT_AND_COPY_LENGTH_SYMBOLS, &INSERT_AND_COPY_LENGTH_SPLIT);
+		let distance_split = split(&distances.iter().map(|&(code, _)| code).collect::<Vec<_>>(), num_distance_symbols, &DISTANCE_SPLIT);
+
+		let literal_types = literal_split.types();
+		let mut literals_by_type = vec![Vec::new(); literal_split.num_types];
+
+		for (&literal, &block_type) in literals.iter().zip(&literal_types) {
+			literals_by_type[block_type].push(literal);
+		}
+
+		let context_modes = literals_by_type.iter().map(|literals| match context_modeling {
+			true => choose_context_mode(literals),
+			false => 0,
+		}).collect::<Vec<_>>();
+		let literal_contexts = literals.iter().zip(&literal_types).map(|(&(literal, p1, p2), &block_type)| {
+			(literal as usize, block_type * NUM_LITERAL_CONTEXTS + literal_context(context_modes[block_type], p1, p2))
+		}).collect::<Vec<_>>();
+		let distance_contexts = distances.iter().zip(&distance_split.types()).map(|(&(code, copy_len), &block_type)| {
+			(code, block_type * NUM_DISTANCE_CONTEXTS + distance_context(copy_len))
+		}).collect::<Vec<_>>();
+
+		let (literal_context_map, literal_histograms) = context_map(&literal_contexts, literal_split.num_types, NUM_LITERAL_CONTEXTS,
+			NUM_LITERAL_SYMBOLS, context_modeling);
+		let (distance_context_map, distance_histograms) = context_map(&distance_contexts, distance_split.num_types, NUM_DISTANCE_CONTEXTS,
+			num_distance_symbols, context_modeling);
+		let insert_and_copy_length_histograms = histograms(insert_and_copy_lengths, &insert_and_copy_length_split.types(),
+			insert_and_copy_length_split.num_types, NUM_INSERT_AND_COPY_LENGTH_SYMBOLS);
+
+		BlockLayout{
+			literal_split,
+			insert_and_copy_length_split,
+			distance_split,
+			context_modes,
+			literal_context_map,
+			distance_context_map,
+			literal_histograms,
+			insert_and_copy_length_histograms,
+			distance_histograms,
+		}
+	}
+}
+
+/// Returns the context map for symbols that are given as (symbol, block type * num_contexts + context),
+/// and the histograms of the prefix codes it refers to. With context modeling, the histograms of the
+/// contexts are clustered, without it, every block type has its own prefix code.
+fn context_map(symbols: &[(usize, usize)], num_types: usize, num_contexts: usize, alphabet_size: usize, context_modeling: bool) -> (Vec<usize>, Vec<Vec<u32>>) {
+	let context_map = match context_modeling {
+		true => {
+			let mut histograms = vec![vec![0; alphabet_size]; num_types * num_contexts];
+
+			for &(symbol, context) in symbols {
+				histograms[context][symbol] += 1;
+			}
+
+			cluster(&histograms, MAX_TYPES)
+		},
+		false => (0..num_types * num_contexts).map(|context| context / num_contexts).collect(),
+	};
+	let mut histograms = vec![vec![0; alphabet_size]; context_map.iter().max().map_or(1, |&max| max + 1)];
+
+	for &(symbol, context) in symbols {
+		histograms[context_map[context]][symbol] += 1;
+	}
+
+	(context_map, histograms)
+}
+
+/// How the symbols of a category are split into blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplitParams {
+	/// number of symbols per block type to start from
+	symbols_per_type: usize,
+	/// estimated number of bits of a block switch command
+	switch_cost: f32,
+}
+
+pub const LITERAL_SPLIT: SplitParams = SplitParams{
+	symbols_per_type: 544,
+	switch_cost: 28.1,
+};
+
+pub const INSERT_AND_COPY_LENGTH_SPLIT: SplitParams = SplitParams{
+	symbols_per_type: 530,
+	switch_cost: 13.5,
+};
+
+pub const DISTANCE_SPLIT: SplitParams = SplitParams{
+	symbols_per_type: 544,
+	switch_cost: 14.6,
+};
+
+/// The blocks of a category of symbols, as (block type, number of symbols), in order.
+/// The first block is of block type 0, which is the one the decoder starts with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSplit {
+	pub num_types: usize,
+	pub blocks: Vec<(usize, usize)>,
+}
+
+impl BlockSplit {
+	/// Returns a single block of len symbols.
+	pub fn single(len: usize) -> BlockSplit {
+		BlockSplit{
+			num_types: 1,
+			blocks: vec![(0, len)],
+		}
+	}
+
+	/// Splits the symbols into blocks, so that the block types have histograms that encode
+	/// the symbols of their blocks with fewer bits than a single histogram would, including
+	/// the bits of the block switch commands.
+	///
+	/// The symbols are first split into equal parts, the histograms of which are the initial
+	/// block types. Then every symbol is assigned to the block type whose histogram encodes
+	/// it best, unless a block switch does not pay off, and the histograms are recomputed from
+	/// the new blocks, a few times over. In the end, the block types that encode their symbols
+	/// with fewer bits together are merged.
+	pub fn new(symbols: &[usize], alphabet_size: usize, params: &SplitParams) -> BlockSplit {
+		let num_types = cmp::min(MAX_INITIAL_TYPES, symbols.len() / params.symbols_per_type);
+
+		if num_types < 2 {
+			return BlockSplit::single(symbols.len());
+		}
+
+		let mut types = (0..symbols.len()).map(|i| i * num_types / symbols.len()).collect::<Vec<_>>();
+
+		for _ in 0..SPLIT_ITERATIONS {
+			types = find_blocks(symbols, &histograms(symbols, &types, num_types, alphabet_size), params.switch_cost);
+		}
+
+		let clusters = cluster(&histograms(symbols, &types, num_types, alphabet_size), MAX_TYPES);
+
+		BlockSplit::from_types(types.iter().map(|&block_type| clusters[block_type]))
+	}
+
+	/// Returns the blocks of runs of the same block type, with the block types numbered by their first appearance.
+	fn from_types<I: Iterator<Item = usize>>(types: I) -> BlockSplit {
+		let mut numbers = Vec::<Option<usize>>::new();
+		let mut split = BlockSplit{
+			num_types: 0,
+			blocks: Vec::new(),
+		};
+
+		for block_type in types {
+			if block_type >= numbers.len() {
+				numbers.resize(block_type + 1, None);
+			}
+
+			let block_type = match numbers[block_type] {
+				Some(number) => number,
+				None => {
+					numbers[block_type] = Some(split.num_types);
+					split.num_types += 1;
+
+					split.num_types - 1
+				},
+			};
+
+			match split.blocks.last_mut() {
+				Some(&mut (last_type, ref mut len)) if last_type == block_type => *len += 1,
+				_ => split.blocks.push((block_type, 1)),
+			}
+		}
+
+		match split.num_types {
+			0 => BlockSplit::single(0),
+			_ => split,
+		}
+	}
+
+	/// Returns the block type of every symbol.
+	pub fn types(&self) -> Vec<usize> {
+		self.blocks.iter().flat_map(|&(block_type, len)| (0..len).map(move |_| block_type)).collect()
+	}
+}
+
+/// Returns the histogram of every block type.
+fn histograms(symbols: &[usize], types: &[usize], num_types: usize, alphabet_size: usize) -> Vec<Vec<u32>> {
+	let mut histograms = vec![vec![0; alphabet_size]; num_types];
+
+	for (&symbol, &block_type) in symbols.iter().zip(types) {
+		histograms[block_type][symbol] += 1;
+	}
+
+	histograms
+}
+
+/// Assigns every symbol to a block type, the one that encodes it with the fewest bits, unless switching
+/// to it costs more than it saves. Returns the block type of every symbol.
+fn find_blocks(symbols: &[usize], histograms: &[Vec<u32>], switch_cost: f32) -> Vec<usize> {
+	let num_types = histograms.len();
+	// block types without symbols are left behind
+	let symbol_costs = histograms.iter().map(|histogram| match total(histogram) {
+		0 => vec![f32::INFINITY; histogram.len()],
+		_ => bit_costs(histogram),
+	}).collect::<Vec<_>>();
+	// the cost of every block type up to the current symbol, relative to the cheapest one
+	let mut costs = vec![0.0; num_types];
+	// whether a block type fell behind the cheapest one by the cost of a switch at a symbol
+	let mut switches = vec![false; symbols.len() * num_types];
+	let mut types = vec![0; symbols.len()];
+
+	for (i, &symbol) in symbols.iter().enumerate() {
+		let mut min_cost = f32::INFINITY;
+
+		for (block_type, cost) in costs.iter_mut().enumerate() {
+			*cost += symbol_costs[block_type][symbol];
+
+			if *cost < min_cost {
+				min_cost = *cost;
+				types[i] = block_type;
+			}
+		}
+
+		for (block_type, cost) in costs.iter_mut().enumerate() {
+			*cost -= min_cost;
+
+			if *cost >= switch_cost {
+				*cost = switch_cost;
+				switches[i * num_types + block_type] = true;
+			}
+		}
+	}
+
+	// trace back from the cheapest block type at the end, switching to the cheapest one where the current one fell behind
+	let mut current = types[symbols.len() - 1];
+
+	for i in (0..symbols.len() - 1).rev() {
+		if switches[i * num_types + current] {
+			current = types[i];
+		}
+
+		types[i] = current;
+	}
+
+	types
+}
+
+/// Estimates the number of bits of the symbols of a histogram with total symbols, and of the prefix code for them.
+pub fn histogram_cost<I: Iterator<Item = u32>>(counts: I, total: u32) -> f32 {
+	let mut n_used = 0;
+	let mut n_zero_runs = 0;
+	let mut last_count = 1;
+	let mut sum = 0.0;
+
+	for count in counts {
+		if count > 0 {
+			n_used += 1;
+			sum += count as f32 * (count as f32).log2();
+		} else if last_count > 0 {
+			n_zero_runs += 1;
+		}
+
+		last_count = count;
+	}
+
+	let total = total as f32;
+
+	match n_used {
+		0 => 0.0,
+		1 => 12.0,
+		n_used => {
+			// every symbol takes at least one bit, and a complex prefix code stores every code length and run of zeros
+			let bits = (total * total.log2() - sum).max(total);
+			let header_bits = if n_used <= 4 { 12.0 + 8.0 * n_used as f32 } else { 20.0 + 3.0 * n_used as f32 + 6.0 * n_zero_runs as f32 };
+
+			bits + header_bits
+		},
+	}
+}
+
+/// Clusters the histograms, merging the two clusters whose merged histogram saves the most bits, as long as
+/// that saves bits, or as long as there are more than max_clusters clusters. Returns the cluster of every
+/// histogram, with the clusters numbered by their first appearance. Empty histograms are in cluster 0.
+pub fn cluster(histograms: &[Vec<u32>], max_clusters: usize) -> Vec<usize> {
+	if histograms.len() <= CLUSTER_BATCH_LEN {
+		return cluster_batch(histograms, max_clusters);
+	}
+
+	// cluster batches, then their clusters
+	let mut batch_clusters = Vec::with_capacity(histograms.len());
+	let mut cluster_histograms = Vec::new();
+
+	for batch in histograms.chunks(CLUSTER_BATCH_LEN) {
+		let clusters = cluster_batch(batch, max_clusters);
+		let offset = cluster_histograms.len();
+
+		for (histogram, &cluster) in batch.iter().zip(&clusters) {
+			if offset + cluster == cluster_histograms.len() {
+				cluster_histograms.push(vec![0; histogram.len()]);
+			}
+
+			add_histogram(&mut cluster_histograms[offset + cluster], histogram);
+		}
+
+		batch_clusters.extend(clusters.iter().map(|&cluster| offset + cluster));
+	}
+
+	let clusters = cluster_batch(&cluster_histograms, max_clusters);
+
+	batch_clusters.iter().map(|&cluster| clusters[cluster]).collect()
+}
+
+fn cluster_batch(histograms: &[Vec<u32>], max_clusters: usize) -> Vec<usize> {
+	// clusters of the non-empty histograms as (members, histogram, cost), None once merged into another one
+	let mut clusters = histograms.iter()
+		.enumerate()
+		.filter(|&(_, histogram)| histogram.iter().any(|&count| count > 0))
+		.map(|(i, histogram)| Some((vec![i], histogram.clone(), histogram_cost(histogram.iter().cloned(), total(histogram)))))
+		.collect::<Vec<_>>();
+	// gains[i][j], i < j, is the number of bits saved by merging clusters i and j
+	let mut gains = vec![vec![0.0; clusters.len()]; clusters.len()];
+	let mut n_clusters = clusters.len();
+
+	for i in 0..clusters.len() {
+		for j in i + 1..clusters.len() {
+			gains[i][j] = merge_gain(&clusters[i], &clusters[j]);
+		}
+	}
+
+	while n_clusters > 1 {
+		let mut best = (f32::NEG_INFINITY, 0, 0);
+
+		for i in (0..clusters.len()).filter(|&i| clusters[i].is_some()) {
+			for j in (i + 1..clusters.len()).filter(|&j| clusters[j].is_some()) {
+				if gains[i][j] > best.0 {
+					best = (gains[i][j], i, j);
+				}
+			}
+		}
+
+		let (gain, i, j) = best;
+
+		if gain <= 0.0 && n_clusters <= max_clusters {
+			break;
+		}
+
+		// merge cluster j into cluster i
+		if let Some((members, histogram, _)) = clusters[j].take() {
+			if let Some((ref mut cluster_members, ref mut cluster_histogram, ref mut cost)) = clusters[i] {
+				cluster_members.extend(members);
+				add_histogram(cluster_histogram, &histogram);
+				*cost = histogram_cost(cluster_histogram.iter().cloned(), total(cluster_histogram));
+			}
+		}
+
+		n_clusters -= 1;
+
+		for k in (0..clusters.len()).filter(|&k| k != i && clusters[k].is_some()) {
+			gains[cmp::min(i, k)][cmp::max(i, k)] = merge_gain(&clusters[i], &clusters[k]);
+		}
+	}
+
+	let mut owners = vec![None; histograms.len()];
+
+	for (k, cluster) in clusters.iter().enumerate() {
+		if let Some((ref members, _, _)) = *cluster {
+			for &member in members {
+				owners[member] = Some(k);
+			}
+		}
+	}
+
+	let mut numbers = vec![None; clusters.len()];
+	let mut n_numbers = 0;
+
+	owners.iter().map(|&owner| match owner {
+		Some(k) => match numbers[k] {
+			Some(number) => number,
+			None => {
+				numbers[k] = Some(n_numbers);
+				n_numbers += 1;
+
+				n_numbers - 1
+			},
+		},
+		None => 0,
+	}).collect()
+}
+
+/// Returns the number of bits saved by merging two clusters.
+fn merge_gain(a: &Option<(Vec<usize>, Vec<u32>, f32)>, b: &Option<(Vec<usize>, Vec<u32>, f32)>) -> f32 {
+	match (a, b) {
+		(&Some((_, ref a, cost_a)), &Some((_, ref b, cost_b))) => {
+			cost_a + cost_b - histogram_cost(a.iter().zip(b).map(|(&a, &b)| a + b), total(a) + total(b))
+		},
+		_ => f32::NEG_INFINITY,
+	}
+}
+
+fn total(histogram: &[u32]) -> u32 {
+	histogram.iter().sum()
+}
+
+fn add_histogram(histogram: &mut [u32], other: &[u32]) {
+	for (count, &other_count) in histogram.iter_mut().zip(other) {
+		*count += other_count;
+	}
+}
+
+/// Returns the context of a literal in a context mode, from the two bytes before it.
+pub fn literal_context(context_mode: usize, p1: u8, p2: u8) -> usize {
+	let (p1, p2) = (p1 as usize, p2 as usize);
+
+	match context_mode {
+		0 => p1 & 0x3f,
+		1 => p1 >> 2,
+		2 => LUT_0[p1] | LUT_1[p2],
+		_ => (LUT_2[p1] << 3) | LUT_2[p2],
+	}
+}
+
+/// Returns the context of a distance, from the copy length of its command.
+pub fn distance_context(copy_len: usize) -> usize {
+	match copy_len {
+		2..=4 => copy_len - 2,
+		_ => 3,
+	}
+}
+
+/// Chooses the context mode for literals, given as (literal, p1, p2), by which one separates them
+/// into the contexts whose histograms encode them with the fewest bits.
+pub fn choose_context_mode(literals: &[(u8, u8, u8)]) -> usize {
+	let mut best = (f32::INFINITY, 0);
+
+	for context_mode in 0..NUM_CONTEXT_MODES {
+		let mut histograms = vec![0; NUM_LITERAL_CONTEXTS << 8];
+
+		for &(literal, p1, p2) in literals {
+			histograms[literal_context(context_mode, p1, p2) << 8 | literal as usize] += 1;
+		}
+
+		let cost = histograms.chunks(1 << 8).map(|histogram| histogram_cost(histogram.iter().cloned(), total(histogram))).sum::<f32>();
+
+		if cost < best.0 {
+			best = (cost, context_mode);
+		}
+	}
+
+	best.1
+}
+
+/// Writes the number of block types of a category, or of prefix codes of a context map, 1 to 256,
+/// the way parse_n_bltypes() reads it.
+pub fn write_count<W: Write>(out: &mut BitWriter<W>, n: usize) -> io::Result<()> {
+	if n == 1 {
+		return out.write_bit(false);
+	}
+
+	let n_bits = log2_floor(n - 1);
+
+	out.write_bit(true)?;
+	out.write_bits(n_bits as u32, 3)?;
+	out.write_bits((n - 1 - (1 << n_bits)) as u32, n_bits)
+}
+
+/// Writes a context map the way parse_context_map_info() reads it: move-to-front transformed, with the
+/// runs of zeros run length encoded, by the codes up to the RLEMAX that takes the fewest bits.
+pub fn write_context_map<W: Write>(out: &mut BitWriter<W>, context_map: &[usize], n_trees: usize) -> io::Result<()> {
+	let values = move_to_front_transform(context_map);
+	let longest_run = values.split(|&value| value > 0).map(|run| run.len()).max().unwrap_or(0);
+	let mut best = (f32::INFINITY, 0, Vec::new());
+
+	for rlemax in 0..cmp::min(log2_floor(cmp::max(longest_run, 1)), MAX_RLEMAX) + 1 {
+		let tokens = run_length_encode(&values, rlemax);
+		let mut histogram = vec![0; rlemax + n_trees];
+
+		for &(symbol, _) in &tokens {
+			histogram[symbol] += 1;
+		}
+
+		let extra_bits = tokens.iter().map(|&(_, (_, n_bits))| n_bits).sum::<usize>();
+		let cost = histogram_cost(histogram.iter().cloned(), total(&histogram)) + extra_bits as f32;
+
+		if cost < best.0 {
+			best = (cost, rlemax, tokens);
+		}
+	}
+
+	let (_, rlemax, tokens) = best;
+	let mut histogram = vec![0; rlemax + n_trees];
+
+	for &(symbol, _) in &tokens {
+		histogram[symbol] += 1;
+	}
+
+	match rlemax {
+		0 => out.write_bit(false)?,
+		rlemax => {
+			out.write_bit(true)?;
+			out.write_bits(rlemax as u32 - 1, 4)?;
+		},
+	}
+
+	let code = PrefixCode::from_histogram(&histogram);
+
+	code.write(out)?;
+
+	for (symbol, (extra, n_bits)) in tokens {
+		code.write_symbol(out, symbol)?;
+		out.write_bits(extra, n_bits)?;
+	}
+
+	// IMTF
+	out.write_bit(true)
+}
+
+/// Returns the index of every value in a list of all values, which moves every value to its front.
+/// This is the inverse of inverse_move_to_front_transform().
+fn move_to_front_transform(values: &[usize]) -> Vec<usize> {
+	let mut mtf = (0..MAX_TYPES).collect::<Vec<_>>();
+
+	values.iter().map(|&value| {
+		let index = mtf.iter().position(|&other| other == value).unwrap_or(0);
+
+		mtf.remove(index);
+		mtf.insert(0, value);
+
+		index
+	}).collect()
+}
+
+/// Returns the symbols of the context map values, and their extra bits as (value, number of bits).
+/// Symbols 1 to rlemax are runs of zeros of 2^symbol and more, values are shifted past them.
+fn run_length_encode(values: &[usize], rlemax: usize) -> Vec<(usize, (u32, usize))> {
+	let mut tokens = Vec::new();
+	let mut i = 0;
+
+	while i < values.len() {
+		if values[i] > 0 {
+			tokens.push((values[i] + rlemax, (0, 0)));
+			i += 1;
+
+			continue;
+		}
+
+		let mut run = values[i..].iter().take_while(|&&value| value == 0).count();
+
+		i += run;
+
+		while run > 0 {
+			let code = cmp::min(log2_floor(run), rlemax);
+
+			if code == 0 {
+				tokens.push((0, (0, 0)));
+				run -= 1;
+
+				continue;
+			}
+
+			let len = cmp::min(run, (2 << code) - 1);
+
+			tokens.push((code, ((len - (1 << code)) as u32, code)));
+			run -= len;
+		}
+	}
+
+	tokens
+}
+
+/// Writes the block switch commands of a category in between its symbols, the way
+/// parse_block_switch_command() reads them.
+#[derive(Debug)]
+pub struct BlockSwitcher<'a> {
+	split: &'a BlockSplit,
+	/// block type code of every block after the first one
+	type_symbols: Vec<usize>,
+	type_code: PrefixCode,
+	count_code: PrefixCode,
+	block: usize,
+	/// number of symbols of the current block that are yet to be written
+	remaining: usize,
+}
+
+impl<'a> BlockSwitcher<'a> {
+	/// Creates a BlockSwitcher, with prefix codes for the block switch commands of the split.
+	pub fn new(split: &'a BlockSplit) -> BlockSwitcher<'a> {
+		let mut type_symbols = Vec::with_capacity(split.blocks.len());
+		let mut type_histogram = vec![0; split.num_types + 2];
+		let mut count_histogram = vec![0; BLOCK_COUNT_CODES.len()];
+		// the block type before the current one, and the current one, the way the decoder starts out
+		let (mut previous, mut current) = (1, 0);
+
+		for (i, &(block_type, len)) in split.blocks.iter().enumerate() {
+			if i > 0 {
+				let symbol = match block_type {
+					block_type if block_type == previous => 0,
+					block_type if block_type == (current + 1) % split.num_types => 1,
+					block_type => block_type + 2,
+				};
+
+				type_symbols.push(symbol);
+				type_histogram[symbol] += 1;
+			}
+
+			count_histogram[block_count_code(len)] += 1;
+			previous = current;
+			current = block_type;
+		}
+
+		BlockSwitcher{
+			split,
+			type_symbols,
+			type_code: PrefixCode::from_histogram(&type_histogram),
+			count_code: PrefixCode::from_histogram(&count_histogram),
+			block: 0,
+			remaining: split.blocks[0].1,
+		}
+	}
+
+	/// Writes NBLTYPES, and if there is more than one block type, the prefix codes for the block switch
+	/// commands, and the count of the first block.
+	pub fn write_header<W: Write>(&self, out: &mut BitWriter<W>) -> io::Result<()> {
+		write_count(out, self.split.num_types)?;
+
+		if self.split.num_types < 2 {
+			return Ok(());
+		}
+
+		self.type_code.write(out)?;
+		self.count_code.write(out)?;
+		self.write_block_count(out, self.split.blocks[0].1)
+	}
+
+	/// Writes a block switch command if the current block is complete. Returns the block type of the next symbol.
+	pub fn next<W: Write>(&mut self, out: &mut BitWriter<W>) -> io::Result<usize> {
+		if self.remaining == 0 {
+			self.block += 1;
+			self.remaining = self.split.blocks[self.block].1;
+
+			self.type_code.write_symbol(out, self.type_symbols[self.block - 1])?;
+			self.write_block_count(out, self.remaining)?;
+		}
+
+		self.remaining -= 1;
+
+		Ok(self.split.blocks[self.block].0)
+	}
+
+	fn write_block_count<W: Write>(&self, out: &mut BitWriter<W>, len: usize) -> io::Result<()> {
+		let code = block_count_code(len);
+		let (base, n_bits) = BLOCK_COUNT_CODES[code];
+
+		self.count_code.write_symbol(out, code)?;
+		out.write_bits((len - base) as u32, n_bits)
+	}
+}
+
+fn block_count_code(len: usize) -> usize {
+	BLOCK_COUNT_CODES.iter().rposition(|&(base, _)| base <= len).unwrap_or(0)
+}
+
+mod tests {
+	#[test]
+	fn should_split_symbols_with_different_statistics() {
+		use super::*;
+
+		let mut random = ::testing::random(0x2545_f491_4f6c_dd1d);
+
+		// text-like symbols, binary-like symbols, and text-like symbols again
+		let symbols = (0..30000).map(|i| match i {
+			10000..=19999 => random(256) as usize,
+			_ => b"etaoin shrdlu"[random(13) as usize] as usize,
+		}).collect::<Vec<_>>();
+
+		let split = BlockSplit::new(&symbols, 256, &LITERAL_SPLIT);
+
+		assert_eq!(2, split.num_types);
+		assert_eq!(vec![0, 1, 0], split.blocks.iter().map(|&(block_type, _)| block_type).collect::<Vec<_>>());
+		assert!(split.blocks.iter().all(|&(_, len)| len > 9900 && len < 10100));
+		assert_eq!(symbols.len(), split.types().len());
+
+		assert_eq!(BlockSplit::single(500), BlockSplit::new(&symbols[0..500], 256, &LITERAL_SPLIT));
+	}
+
+	#[test]
+	fn should_cluster_similar_histograms() {
+		use super::*;
+
+		let histograms = vec![
+			vec![0, 0, 0, 0],
+			vec![90, 10, 0, 0],
+			vec![0, 0, 50, 50],
+			vec![80, 20, 0, 0],
+			vec![0, 0, 0, 0],
+			vec![0, 0, 45, 55],
+		];
+
+		assert_eq!(vec![0, 0, 1, 0, 0, 1], cluster(&histograms, 256));
+		assert_eq!(vec![0, 0, 0, 0, 0, 0], cluster(&histograms, 1));
+
+		let histograms = (0..300).map(|i| (0..300).map(|j| if i == j { 100 } else { 0 }).collect()).collect::<Vec<Vec<u32>>>();
+
+		assert_eq!(256, cluster(&histograms, 256).iter().max().unwrap() + 1);
+	}
+
+	#[test]
+	fn should_write_counts_that_the_decoder_reads() {
+		use super::*;
+		use ::Decompressor;
+
+		let mut bw = BitWriter::new(Vec::new());
+
+		for n in 1..257 {
+			write_count(&mut bw, n).unwrap();
+		}
+
+		bw.align().unwrap();
+
+		let mut decompressor = Decompressor::new(&bw.get_ref()[..]);
+
+		for n in 1..257 {
+			assert_eq!(Ok(n), decompressor.parse_n_bltypes());
+		}
+	}
+
+	#[test]
+	fn should_write_context_maps_that_the_decoder_reads() {
+		use super::*;
+		use ::Decompressor;
+
+		let mut random = ::testing::random(0x9e37_79b9_7f4a_7c15);
+
+		let mut context_maps = vec![
+			(vec![0, 1], 2),
+			(vec![1; 64], 2),
+			(vec![0; 256 * 64], 2),
+			((0..256 * 64).map(|i| i % 256).collect(), 256),
+			((0..64 * 4).map(|i| i / 64).collect(), 4),
+		];
+
+		for _ in 0..20 {
+			let n_trees = 2 + random(50) as usize;
+			let mut context_map = Vec::new();
+
+			while context_map.len() < 64 * 16 {
+				let value = random(n_trees as u64) as usize;
+
+				for _ in 0..random(200) {
+					context_map.push(value);
+				}
+			}
+
+			context_maps.push((context_map, n_trees));
+		}
+
+		for (context_map, n_trees) in context_maps {
+			let mut bw = BitWriter::new(Vec::new());
+
+			write_context_map(&mut bw, &context_map, n_trees).unwrap();
+			bw.align().unwrap();
+
+			let mut decompressor = Decompressor::new(&bw.get_ref()[..]);
+
+			assert_eq!(Ok(context_map.iter().map(|&value| value as u8).collect()), decompressor.parse_context_map_info(n_trees as u16, context_map.len()).map(|info| info.map));
+		}
+	}
+
+	#[test]
+	fn should_write_block_switches_that_the_decoder_reads() {
+		use super::*;
+		use ::{ BlockCategory, Decompressor };
+
+		let splits = vec![
+			BlockSplit{
+				num_types: 2,
+				blocks: vec![(0, 1), (1, 2), (0, 3), (1, 4)],
+			},
+			BlockSplit{
+				num_types: 5,
+				blocks: vec![(0, 100), (1, 16625), (2, 5), (1, 1 << 20), (4, 1), (3, 7), (2, 12), (3, 8433), (0, 369)],
+			},
+		];
+
+		for split in &splits {
+			let mut switcher = BlockSwitcher::new(split);
+			let mut bw = BitWriter::new(Vec::new());
+
+			switcher.write_header(&mut bw).unwrap();
+
+			for &(block_type, len) in &split.blocks {
+				for _ in 0..len {
+					assert_eq!(block_type, switcher.next(&mut bw).unwrap());
+				}
+			}
+
+			bw.align().unwrap();
+
+			let mut decompressor = Decompressor::new(&bw.get_ref()[..]);
+			let n_bltypes = decompressor.parse_n_bltypes().unwrap();
+			let types_code = decompressor.parse_prefix_code_info(n_bltypes as usize + 2).unwrap().0;
+			let counts_code = decompressor.parse_prefix_code_info(BLOCK_COUNT_CODES.len()).unwrap().0;
+			let (mut btype, mut btype_prev) = (0, 1);
+
+			assert_eq!(split.num_types, n_bltypes as usize);
+			assert_eq!(Ok(split.blocks[0].1 as u32), decompressor.parse_block_count(&counts_code));
+
+			for &(block_type, len) in &split.blocks[1..] {
+				let (next_btype, count) = decompressor.parse_block_switch_command(BlockCategory::Literals, types_code.clone(), btype, btype_prev, n_bltypes, counts_code.clone()).unwrap();
+
+				assert_eq!((block_type, len), (next_btype as usize, count as usize));
+
+				btype_prev = btype;
+				btype = next_btype;
+			}
+		}
+	}
+}
@@ -0,0 +1,474 @@
+use super::match_len;
+
+use std::cmp;
+use std::fmt;
+
+const HASH_MUL: u32 = 0x1e35_a7bd;
+const MIN_HASH_BITS: u8 = 8;
+const MAX_HASH_BITS: u8 = 24;
+/// Hash chains and binary trees reach back at most 2^MAX_WINDOW_BITS bytes.
+const MAX_WINDOW_BITS: u8 = 20;
+/// Number of bytes that the binary tree orders its positions by.
+const MAX_TREE_COMP_LEN: usize = 128;
+
+/// Finds earlier occurrences of the input at a position, for the backward references of the Compressor.
+///
+/// Positions are stream offsets. The Compressor keeps the input from some stream offset base on,
+/// and passes it as data along with base. It indexes positions in increasing order, and asks for
+/// the matches at a position after it has indexed or skipped all positions before it. Matches may
+/// reach no further back than max_distance, which the Compressor derives from the window size, and
+/// no further back than base.
+///
+/// # Examples
+/// ```
+/// use std::io::{ Read, Write };
+/// use brotli::{ Compressor, Decompressor, MatchFinder };
+///
+/// /// Only looks for repetitions of the previous 16-byte record.
+/// #[derive(Debug)]
+/// struct Records;
+///
+/// impl MatchFinder for Records {
+///     fn insert_until(&mut self, _: &[u8], _: usize, _: usize) {}
+///
+///     fn skip_until(&mut self, _: usize) {}
+///
+///     fn find_matches(&self, data: &[u8], base: usize, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool) {
+///         if pos >= base + 16 && max_distance >= 16 {
+///             let len = data[pos - base..].iter().zip(&data[pos - base - 16..]).take(max_len).take_while(|&(a, b)| a == b).count();
+///
+///             if len >= 4 {
+///                 f(len, 16);
+///             }
+///         }
+///     }
+/// }
+///
+/// let mut compressor = Compressor::with_match_finder(Vec::new(), 5, 22, Records);
+/// compressor.write_all(&b"0123456789abcdef".repeat(100)).unwrap();
+/// let brotli_stream = compressor.finish().unwrap();
+///
+/// let mut decompressed = Vec::new();
+/// Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+///
+/// assert!(brotli_stream.len() < 100);
+/// assert_eq!(b"0123456789abcdef".repeat(100), decompressed);
+/// ```
+pub trait MatchFinder: fmt::Debug {
+	/// Indexes the positions before limit, where data holds the input from stream offset base on.
+	/// Positions that need more data after them than there is yet may be left for a later call.
+	fn insert_until(&mut self, data: &[u8], base: usize, limit: usize);
+
+	/// Moves on to limit, possibly without indexing the positions in between.
+	fn skip_until(&mut self, limit: usize);
+
+	/// Calls f with the length and distance of matches of at least four bytes for the input at pos,
+	/// from the closest one on, as long as f returns true. The input that a match covers ends within
+	/// data, its length is at most max_len, and its distance at most max_distance.
+	fn find_matches(&self, data: &[u8], base: usize, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool);
+}
+
+fn hash(bytes: &[u8], hash_shift: u32) -> usize {
+	let v = u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16 | u32::from(bytes[3]) << 24;
+
+	(v.wrapping_mul(HASH_MUL) >> hash_shift) as usize
+}
+
+fn hash_shift(hash_bits: u8) -> u32 {
+	32 - u32::from(hash_bits.clamp(MIN_HASH_BITS, MAX_HASH_BITS))
+}
+
+/// Remembers the last position per hash of the next four bytes, for the fastest quality levels.
+#[derive(Debug, Clone)]
+pub struct SingleHash {
+	/// last position + 1 per hash value, 0 for none
+	head: Vec<usize>,
+	hash_shift: u32,
+	/// all positions before this one have been hashed, or skipped
+	next_pos: usize,
+}
+
+impl SingleHash {
+	/// Creates a hash table with 2^hash_bits entries, where hash_bits is clamped to 8..24.
+	pub fn new(hash_bits: u8) -> SingleHash {
+		let hash_shift = hash_shift(hash_bits);
+
+		SingleHash{
+			head: vec![0; 1 << (32 - hash_shift)],
+			hash_shift,
+			next_pos: 0,
+		}
+	}
+}
+
+impl MatchFinder for SingleHash {
+	fn insert_until(&mut self, data: &[u8], base: usize, limit: usize) {
+		while self.next_pos < limit && self.next_pos + 4 <= base + data.len() {
+			self.head[hash(&data[self.next_pos - base..], self.hash_shift)] = self.next_pos + 1;
+			self.next_pos += 1;
+		}
+	}
+
+	fn skip_until(&mut self, limit: usize) {
+		self.next_pos = cmp::max(self.next_pos, limit);
+	}
+
+	fn find_matches(&self, data: &[u8], base: usize, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool) {
+		if max_len < 4 {
+			return;
+		}
+
+		match self.head[hash(&data[pos - base..], self.hash_shift)] {
+			0 => {},
+			candidate if candidate - 1 < base || pos - (candidate - 1) > max_distance => {},
+			candidate => {
+				let len = match_len(&data[candidate - 1 - base..], &data[pos - base..], max_len);
+
+				if len >= 4 {
+					f(len, pos - (candidate - 1));
+				}
+			},
+		}
+	}
+}
+
+/// Hash chains over the positions of the input, keyed by the next four bytes.
+#[derive(Debug, Clone)]
+pub struct HashChain {
+	/// last position + 1 per hash value, 0 for none
+	head: Vec<usize>,
+	/// distance to the previous position with the same hash, indexed by position & chain_mask, 0 for none
+	chain: Vec<u32>,
+	chain_mask: usize,
+	hash_shift: u32,
+	/// all positions before this one have been hashed, or skipped
+	next_pos: usize,
+	depth: usize,
+}
+
+impl HashChain {
+	/// Creates hash chains with 2^hash_bits heads, where hash_bits is clamped to 8..24, that reach
+	/// back up to 2^wbits bytes, but at most 1 MiB, and visit up to depth positions per search.
+	pub fn new(hash_bits: u8, wbits: u8, depth: usize) -> HashChain {
+		let hash_shift = hash_shift(hash_bits);
+		let chain_bits = if depth > 1 { cmp::min(wbits, MAX_WINDOW_BITS) } else { 0 };
+
+		HashChain{
+			head: vec![0; 1 << (32 - hash_shift)],
+			chain: vec![0; if chain_bits > 0 { 1 << chain_bits } else { 0 }],
+			chain_mask: (1 << chain_bits) - 1,
+			hash_shift,
+			next_pos: 0,
+			depth: cmp::max(depth, 1),
+		}
+	}
+}
+
+impl MatchFinder for HashChain {
+	fn insert_until(&mut self, data: &[u8], base: usize, limit: usize) {
+		while self.next_pos < limit && self.next_pos + 4 <= base + data.len() {
+			let pos = self.next_pos;
+			let h = hash(&data[pos - base..], self.hash_shift);
+
+			if !self.chain.is_empty() {
+				self.chain[pos & self.chain_mask] = match self.head[h] {
+					0 => 0,
+					prev if pos - (prev - 1) > self.chain_mask => 0,
+					prev => (pos - (prev - 1)) as u32,
+				};
+			}
+
+			self.head[h] = pos + 1;
+			self.next_pos += 1;
+		}
+	}
+
+	fn skip_until(&mut self, limit: usize) {
+		self.next_pos = cmp::max(self.next_pos, limit);
+	}
+
+	fn find_matches(&self, data: &[u8], base: usize, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool) {
+		if max_len < 4 {
+			return;
+		}
+
+		let mut candidate = self.head[hash(&data[pos - base..], self.hash_shift)];
+
+		for _ in 0..self.depth {
+			if candidate == 0 || candidate - 1 < base {
+				break;
+			}
+
+			let prev = candidate - 1;
+			let distance = pos - prev;
+
+			if distance > max_distance {
+				break;
+			}
+
+			let len = match_len(&data[prev - base..], &data[pos - base..], max_len);
+
+			if len >= 4 && !f(len, distance) {
+				break;
+			}
+
+			if self.chain.is_empty() || distance > self.chain_mask {
+				break;
+			}
+
+			candidate = match self.chain[prev & self.chain_mask] as usize {
+				0 => 0,
+				delta => prev - delta + 1,
+			};
+		}
+	}
+}
+
+/// Binary trees over the positions of the input, one per hash of the next four bytes, for the
+/// highest quality levels. Each tree orders its positions by the 128 bytes that follow them, with
+/// the latest position at the root, so that a search only visits the positions that share the
+/// longest prefixes with the input.
+#[derive(Debug, Clone)]
+pub struct BinaryTree {
+	/// root position + 1 per hash value, 0 for none
+	head: Vec<usize>,
+	/// distances to the children of every position, indexed by 2 * (position & window_mask),
+	/// + 1 for the right child, 0 for none
+	children: Vec<u32>,
+	window_mask: usize,
+	hash_shift: u32,
+	/// all positions before this one have been inserted, or skipped
+	next_pos: usize,
+	depth: usize,
+}
+
+impl BinaryTree {
+	/// Creates binary trees with 2^hash_bits roots, where hash_bits is clamped to 8..24, that reach
+	/// back up to 2^wbits bytes, but at most 1 MiB, and visit up to depth positions per search.
+	pub fn new(hash_bits: u8, wbits: u8, depth: usize) -> BinaryTree {
+		let hash_shift = hash_shift(hash_bits);
+		let window_bits = cmp::min(wbits, MAX_WINDOW_BITS);
+
+		BinaryTree{
+			head: vec![0; 1 << (32 - hash_shift)],
+			children: vec![0; 2 << window_bits],
+			window_mask: (1 << window_bits) - 1,
+			hash_shift,
+			next_pos: 0,
+			depth: cmp::max(depth, 1),
+		}
+	}
+
+	/// Returns the position that the child slot of owner points to, if it is within the window of pos.
+	fn child(&self, slot: usize, owner: usize, pos: usize) -> Option<usize> {
+		match self.children[slot] as usize {
+			0 => None,
+			delta if pos - (owner - delta) > self.window_mask => None,
+			delta => Some(owner - delta),
+		}
+	}
+
+	/// Makes pos the root of its tree, with the positions that are smaller than it in the left subtree,
+	/// and the others in the right subtree.
+	fn insert(&mut self, data: &[u8], base: usize, pos: usize) {
+		let h = hash(&data[pos - base..], self.hash_shift);
+		let mut candidate = match self.head[h] {
+			0 => None,
+			prev => Some(prev - 1),
+		};
+		// the slots for the next smaller and the next greater position, and the positions that own them
+		let (mut left, mut left_owner) = (2 * (pos & self.window_mask), pos);
+		let (mut right, mut right_owner) = (left + 1, pos);
+		let (mut left_len, mut right_len) = (0, 0);
+
+		self.head[h] = pos + 1;
+
+		for _ in 0..self.depth {
+			let prev = match candidate {
+				Some(prev) if prev >= base && pos - prev <= self.window_mask => prev,
+				_ => break,
+			};
+			let common = cmp::min(left_len, right_len);
+			let len = common + match_len(&data[prev - base + common..], &data[pos - base + common..], MAX_TREE_COMP_LEN - common);
+			let prev_slot = 2 * (prev & self.window_mask);
+
+			if len >= MAX_TREE_COMP_LEN {
+				// pos takes the place of prev, which drops out of the tree
+				self.children[left] = self.child(prev_slot, prev, pos).map_or(0, |child| (left_owner - child) as u32);
+				self.children[right] = self.child(prev_slot + 1, prev, pos).map_or(0, |child| (right_owner - child) as u32);
+
+				return;
+			}
+
+			if data[pos - base + len] > data[prev - base + len] {
+				self.children[left] = (left_owner - prev) as u32;
+				left_len = len;
+				left = prev_slot + 1;
+				left_owner = prev;
+				candidate = self.child(left, prev, pos);
+			} else {
+				self.children[right] = (right_owner - prev) as u32;
+				right_len = len;
+				right = prev_slot;
+				right_owner = prev;
+				candidate = self.child(right, prev, pos);
+			}
+		}
+
+		self.children[left] = 0;
+		self.children[right] = 0;
+	}
+}
+
+impl MatchFinder for BinaryTree {
+	/// Positions are inserted once there are 128 bytes of data after them.
+	fn insert_until(&mut self, data: &[u8], base: usize, limit: usize) {
+		while self.next_pos < limit && self.next_pos + MAX_TREE_COMP_LEN <= base + data.len() {
+			let pos = self.next_pos;
+
+			self.insert(data, base, pos);
+			self.next_pos += 1;
+		}
+	}
+
+	fn skip_until(&mut self, limit: usize) {
+		self.next_pos = cmp::max(self.next_pos, limit);
+	}
+
+	/// Also compares the input at pos to the positions that are still waiting for data to be inserted.
+	fn find_matches(&self, data: &[u8], base: usize, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool) {
+		if max_len < 4 {
+			return;
+		}
+
+		let max_distance = cmp::min(max_distance, self.window_mask);
+		let mut best_len = 0;
+
+		for prev in (cmp::max(self.next_pos, base)..pos).rev() {
+			if pos - prev > max_distance {
+				break;
+			}
+
+			let len = match_len(&data[prev - base..], &data[pos - base..], max_len);
+
+			if len >= 4 && len > best_len {
+				best_len = len;
+
+				if !f(len, pos - prev) {
+					return;
+				}
+			}
+		}
+
+		let mut candidate = match self.head[hash(&data[pos - base..], self.hash_shift)] {
+			0 => None,
+			prev => Some(prev - 1),
+		};
+		let max_comp_len = cmp::min(max_len, MAX_TREE_COMP_LEN);
+		let (mut left_len, mut right_len) = (0, 0);
+
+		for _ in 0..self.depth {
+			let prev = match candidate {
+				Some(prev) if prev >= base && pos - prev <= max_distance => prev,
+				_ => break,
+			};
+			let common = cmp::min(left_len, right_len);
+			let len = common + match_len(&data[prev - base + common..], &data[pos - base + common..], max_comp_len - common);
+
+			if len >= max_comp_len {
+				let len = len + match_len(&data[prev - base + len..], &data[pos - base + len..], max_len - len);
+
+				if len > best_len {
+					f(len, pos - prev);
+				}
+
+				return;
+			}
+
+			if len >= 4 && len > best_len {
+				best_len = len;
+
+				if !f(len, pos - prev) {
+					return;
+				}
+			}
+
+			let slot = 2 * (prev & self.window_mask);
+
+			candidate = if data[pos - base + len] > data[prev - base + len] {
+				left_len = len;
+				self.child(slot + 1, prev, pos)
+			} else {
+				right_len = len;
+				self.child(slot, prev, pos)
+			};
+		}
+	}
+}
+
+mod tests {
+	#[test]
+	fn should_find_real_matches_within_the_window() {
+		use super::*;
+
+		/// Checks that every match that finder reports for data is real, and within the window.
+		fn assert_matches<M: MatchFinder>(mut finder: M, data: &[u8], max_distance: usize) -> usize {
+			let mut found = 0;
+
+			for pos in 0..data.len() {
+				finder.insert_until(data, 0, pos);
+				finder.find_matches(data, 0, pos, data.len() - pos, cmp::min(pos, max_distance), &mut |len, distance| {
+					assert!(distance > 0 && distance <= cmp::min(pos, max_distance));
+					assert!(len >= 4 && pos + len <= data.len());
+					assert!(data[pos - distance..pos - distance + len] == data[pos..pos + len]);
+
+					found = cmp::max(found, len);
+
+					true
+				});
+			}
+
+			found
+		}
+
+		let mut data = Vec::new();
+		let mut x = 1u32;
+
+		// random bytes, alternating with random words from a small vocabulary
+		for i in 0..4000 {
+			x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+
+			if i % 600 < 300 {
+				data.push((x >> 24) as u8);
+			} else {
+				data.extend_from_slice(&b"alpha beta  gamma delta omega kappa "[(x >> 16) as usize % 6 * 6..][..6]);
+			}
+		}
+
+		for &max_distance in &[1000, 1 << 20] {
+			assert!(assert_matches(SingleHash::new(14), &data, max_distance) >= 4);
+			assert!(assert_matches(HashChain::new(14, 10, 16), &data, max_distance) >= 16);
+			assert!(assert_matches(BinaryTree::new(14, 10, 16), &data, max_distance) >= 16);
+		}
+	}
+
+	#[test]
+	fn should_find_the_longest_match_in_a_binary_tree() {
+		use super::*;
+
+		let data = b"0123456789_0123_0123456_0123456789abcdefghijklmnopqrstuvwxyz".repeat(10);
+		let mut tree = BinaryTree::new(12, 16, 64);
+		let pos = data.len() - 60;
+		let mut matches = Vec::new();
+
+		tree.insert_until(&data, 0, pos);
+		tree.find_matches(&data, 0, pos, 60, pos, &mut |len, distance| {
+			matches.push((len, distance));
+
+			true
+		});
+
+		assert_eq!(Some(&(60, 60)), matches.last());
+		assert!(matches.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+	}
+}
@@ -0,0 +1,1096 @@
+/// zopfli splits the input into commands by optimal parsing.
+mod zopfli;
+/// blocks splits the symbols of a meta-block into block types, and maps their contexts to prefix codes.
+mod blocks;
+/// matchfinder indexes the input for backward references.
+mod matchfinder;
+pub use self::matchfinder::{ BinaryTree, HashChain, MatchFinder, SingleHash };
+
+use self::blocks::{ BlockLayout, BlockSwitcher };
+use ::bitwriter::BitWriter;
+use ::dictionary::Matcher;
+use ::huffman::PrefixCode;
+
+use std::cmp;
+use std::io;
+use std::io::Write;
+
+/// Highest quality level, higher levels are clamped to it.
+const MAX_QUALITY: u32 = 11;
+/// WBITS used by Compressor::new().
+const DEFAULT_WBITS: u8 = 22;
+const MIN_WBITS: u8 = 10;
+const MAX_WBITS: u8 = 24;
+/// Maximum number of input bytes per meta-block.
+const META_BLOCK_LEN: usize = 1 << 18;
+
+const NUM_LITERAL_SYMBOLS: usize = 256;
+const NUM_INSERT_AND_COPY_LENGTH_SYMBOLS: usize = 704;
+const NUM_DISTANCE_SHORT_CODES: usize = 16;
+const INITIAL_DISTANCE_CACHE: [usize; 4] = [4, 11, 15, 16];
+const MAX_NPOSTFIX: usize = 3;
+/// Largest NDIRECT >> NPOSTFIX.
+const MAX_NDIRECT_SHIFTED: usize = 15;
+
+/// (base, number of extra bits) per insert length code
+const INSERT_LENGTH_CODES: [(usize, usize); 24] = [
+	(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (6, 1), (8, 1),
+	(10, 2), (14, 2), (18, 3), (26, 3), (34, 4), (50, 4), (66, 5), (98, 5),
+	(130, 6), (194, 7), (322, 8), (578, 9), (1090, 10), (2114, 12), (6210, 14), (22594, 24)];
+/// (base, number of extra bits) per copy length code
+const COPY_LENGTH_CODES: [(usize, usize); 24] = [
+	(2, 0), (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0),
+	(10, 1), (12, 1), (14, 2), (18, 2), (22, 3), (30, 3), (38, 4), (54, 4),
+	(70, 5), (102, 5), (134, 6), (198, 7), (326, 8), (582, 9), (1094, 10), (2118, 24)];
+
+// Scores for choosing between backward references, a literal is worth LITERAL_BYTE_SCORE,
+// every bit of distance costs DISTANCE_BIT_PENALTY.
+const SCORE_BASE: usize = 1920;
+const MIN_SCORE: usize = SCORE_BASE + 100;
+const LITERAL_BYTE_SCORE: usize = 135;
+const DISTANCE_BIT_PENALTY: usize = 30;
+/// Penalties for the distance cache entries, the last distance can even be implicit.
+const DISTANCE_CACHE_PENALTIES: [usize; 4] = [0, 54, 58, 58];
+/// Minimum score by which a match at the next position has to be better, to defer the current one.
+const LAZY_MATCH_MIN_GAIN: usize = 175;
+const MAX_LAZY_STEPS: usize = 4;
+
+/// The built-in MatchFinder of a quality level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Strategy {
+	SingleHash,
+	HashChain,
+	BinaryTree,
+}
+
+/// Settings derived from the quality level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Params {
+	strategy: Strategy,
+	/// maximum number of earlier positions that are compared per position
+	search_depth: usize,
+	/// a match of at least this length ends the search
+	nice_len: usize,
+	/// whether a match may be deferred in favor of a better one at the next position
+	lazy: bool,
+	/// whether the positions covered by a match are hashed, too
+	hash_match_positions: bool,
+	hash_bits: u8,
+	/// number of optimal parsing passes, 0 for greedy or lazy matching
+	optimal_passes: usize,
+	/// whether the symbols of a meta-block are split into several block types
+	block_splitting: bool,
+	/// whether literals and distances are encoded with prefix codes that depend on their context
+	context_modeling: bool,
+	/// whether NPOSTFIX and NDIRECT are chosen per meta-block, to fit its distances
+	distance_params: bool,
+}
+
+impl Params {
+	fn from_quality(quality: u32) -> Params {
+		let (search_depth, nice_len) = match quality {
+			0 => (1, 16),
+			1 => (1, 32),
+			2 => (4, 32),
+			3 => (8, 48),
+			4 => (16, 64),
+			5 => (24, 96),
+			6 => (32, 128),
+			7 => (64, 160),
+			8 => (96, 192),
+			9 => (128, 256),
+			10 => (192, 512),
+			_ => (256, 1024),
+		};
+
+		Params{
+			strategy: match quality {
+				0 | 1 => Strategy::SingleHash,
+				2..=9 => Strategy::HashChain,
+				_ => Strategy::BinaryTree,
+			},
+			search_depth,
+			nice_len,
+			lazy: quality >= 4,
+			hash_match_positions: quality >= 1,
+			hash_bits: if quality < 2 { 15 } else { 17 },
+			optimal_passes: if quality == MAX_QUALITY { 2 } else { 0 },
+			block_splitting: quality >= 10,
+			context_modeling: quality >= 5,
+			distance_params: quality >= 4,
+		}
+	}
+}
+
+/// A backward reference candidate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Match {
+	len: usize,
+	distance: usize,
+	score: usize,
+}
+
+impl Match {
+	fn none() -> Match {
+		Match{
+			len: 0,
+			distance: 0,
+			score: MIN_SCORE,
+		}
+	}
+}
+
+/// Insert literals, followed by a copy from distance bytes back, or from the static dictionary
+/// if the distance lies beyond the window. The last command of a meta-block may have no copy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Command {
+	insert_len: usize,
+	copy_len: usize,
+	distance: usize,
+	/// number of bytes the copy produces, which differs from copy_len for static dictionary references
+	len: usize,
+}
+
+/// The symbols and extra bits of a command, as (value, number of bits).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct EncodedCommand {
+	insert_and_copy_length: usize,
+	insert_extra: (u32, usize),
+	copy_extra: (u32, usize),
+	/// distance code and extra bits, None if the distance is implicit or there is no copy
+	distance: Option<(usize, (u32, usize))>,
+}
+
+/// Encoded commands, and the frequencies of the symbols they use.
+#[derive(Debug, Clone, PartialEq)]
+struct EncodedCommands {
+	commands: Vec<EncodedCommand>,
+	literal_histogram: Vec<u32>,
+	insert_and_copy_length_histogram: Vec<u32>,
+	distance_histogram: Vec<u32>,
+}
+
+fn match_len(a: &[u8], b: &[u8], max_len: usize) -> usize {
+	let max_len = cmp::min(max_len, cmp::min(a.len(), b.len()));
+	let mut len = 0;
+
+	while len < max_len && a[len] == b[len] {
+		len += 1;
+	}
+
+	len
+}
+
+/// Returns the information content of every symbol, and a bit more than that of a single
+/// occurrence for the symbols that did not occur.
+fn bit_costs(histogram: &[u32]) -> Vec<f32> {
+	let total = histogram.iter().map(|&count| count as f32).sum::<f32>();
+	let log2_total = cmp::max(1, total as u32) as f32;
+	let log2_total = log2_total.log2();
+
+	histogram.iter().map(|&count| match count {
+		0 => log2_total + 2.0,
+		count => log2_total - (count as f32).log2(),
+	}).collect()
+}
+
+fn log2_floor(n: usize) -> usize {
+	(usize::MAX.count_ones() - 1 - n.leading_zeros()) as usize
+}
+
+fn backward_reference_score(len: usize, distance: usize) -> usize {
+	SCORE_BASE + LITERAL_BYTE_SCORE * len - DISTANCE_BIT_PENALTY * log2_floor(distance)
+}
+
+fn distance_cache_score(len: usize, i: usize) -> usize {
+	SCORE_BASE + LITERAL_BYTE_SCORE * len + 15 - DISTANCE_CACHE_PENALTIES[i]
+}
+
+/// Returns the code for len among codes, which are sorted by base.
+fn length_code(codes: &[(usize, usize); 24], len: usize) -> usize {
+	codes.iter().rposition(|&(base, _)| base <= len).unwrap_or(0)
+}
+
+/// Returns the insert-and-copy length symbol for an insert length code and a copy length code.
+/// Only the first two cells imply distance code 0, if that is requested.
+fn insert_and_copy_length_symbol(insert_code: usize, copy_code: usize, implicit_distance: bool) -> usize {
+	let cell_offset = match (insert_code >> 3, copy_code >> 3) {
+		(0, 0) if implicit_distance => 0,
+		(0, 1) if implicit_distance => 64,
+		(0, 0) => 128,
+		(0, 1) => 192,
+		(1, 0) => 256,
+		(1, 1) => 320,
+		(0, 2) => 384,
+		(2, 0) => 448,
+		(1, 2) => 512,
+		(2, 1) => 576,
+		_ => 640,
+	};
+
+	cell_offset + ((insert_code & 7) << 3) + (copy_code & 7)
+}
+
+/// Returns the short distance code 0..15 that refers to distance, by way of the distance cache.
+fn distance_short_code(distance: usize, cache: &[usize; 4]) -> Option<usize> {
+	(0..NUM_DISTANCE_SHORT_CODES).find(|&code| short_code_distance(code, cache) == distance as isize)
+}
+
+/// Returns the distance that a short distance code refers to, which may be out of range.
+fn short_code_distance(code: usize, cache: &[usize; 4]) -> isize {
+	if code < 4 {
+		return cache[code] as isize;
+	}
+
+	let reference = if code < 10 { cache[0] } else { cache[1] };
+	let delta = ((code - if code < 10 { 2 } else { 8 }) >> 1) as isize;
+	let sign = if code & 1 == 0 { -1 } else { 1 };
+
+	reference as isize + sign * delta
+}
+
+/// Returns the distance code and extra bits for a distance that is not taken from the distance cache.
+fn distance_code(distance: usize, n_postfix: usize, n_direct: usize) -> (usize, (u32, usize)) {
+	if distance <= n_direct {
+		return (NUM_DISTANCE_SHORT_CODES - 1 + distance, (0, 0));
+	}
+
+	let dist = (1 << (n_postfix + 2)) + distance - n_direct - 1;
+	let bucket = log2_floor(dist) - 1;
+	let postfix = dist & ((1 << n_postfix) - 1);
+	let prefix = (dist >> bucket) & 1;
+	let offset = (2 + prefix) << bucket;
+	let n_bits = bucket - n_postfix;
+
+	let code = NUM_DISTANCE_SHORT_CODES + n_direct + ((2 * (n_bits - 1) + prefix) << n_postfix) + postfix;
+
+	(code, (((dist - offset) >> n_postfix) as u32, n_bits))
+}
+
+/// Returns the NPOSTFIX and NDIRECT with which the distances of the encoded commands take the fewest bits,
+/// estimated from the distance codes and their extra bits. The distances that come from the distance
+/// cache keep their short codes.
+fn choose_distance_params(commands: &[Command], encoded: &EncodedCommands) -> (usize, usize) {
+	let distances = commands.iter().zip(&encoded.commands).filter_map(|(command, encoded)| match encoded.distance {
+		Some((code, _)) if code >= NUM_DISTANCE_SHORT_CODES => Some(command.distance),
+		_ => None,
+	}).collect::<Vec<_>>();
+	let short_codes = &encoded.distance_histogram[..NUM_DISTANCE_SHORT_CODES];
+	let mut best = ((0, 0), f32::INFINITY);
+
+	for n_postfix in 0..=MAX_NPOSTFIX {
+		for n_direct in (0..=MAX_NDIRECT_SHIFTED).map(|n| n << n_postfix) {
+			let mut histogram = vec![0; n_direct + (48 << n_postfix)];
+			let mut extra_bits = 0;
+
+			for &distance in &distances {
+				let (code, (_, n_bits)) = distance_code(distance, n_postfix, n_direct);
+
+				histogram[code - NUM_DISTANCE_SHORT_CODES] += 1;
+				extra_bits += n_bits;
+			}
+
+			let counts = short_codes.iter().chain(&histogram).cloned();
+			let cost = blocks::histogram_cost(counts, short_codes.iter().sum::<u32>() + distances.len() as u32) + extra_bits as f32;
+
+			if cost < best.1 {
+				best = ((n_postfix, n_direct), cost);
+			}
+		}
+	}
+
+	best.0
+}
+
+/// Pushes the distance of a copy to the distance cache, the way the decoder does, unless the copy
+/// reuses the last distance, or is a static dictionary reference beyond max_distance.
+fn update_distance_cache(cache: &mut [usize; 4], distance: usize, max_distance: usize) {
+	if distance != cache[0] && distance <= max_distance {
+		*cache = [distance, cache[0], cache[1], cache[2]];
+	}
+}
+
+/// Encodes a command, updating the distance cache the same way the decoder will.
+/// max_distance is the largest backward distance at the start of the copy.
+fn encode_command(command: &Command, cache: &mut [usize; 4], n_postfix: usize, n_direct: usize, max_distance: usize) -> EncodedCommand {
+	let insert_code = length_code(&INSERT_LENGTH_CODES, command.insert_len);
+	let (insert_base, insert_n_bits) = INSERT_LENGTH_CODES[insert_code];
+	let copy_code = length_code(&COPY_LENGTH_CODES, command.copy_len);
+	let (copy_base, copy_n_bits) = COPY_LENGTH_CODES[copy_code];
+
+	let insert_extra = ((command.insert_len - insert_base) as u32, insert_n_bits);
+	let copy_extra = (command.copy_len.saturating_sub(copy_base) as u32, copy_n_bits);
+
+	if command.copy_len == 0 {
+		// the meta-block ends after the literals, neither copy length nor distance are used
+		return EncodedCommand{
+			insert_and_copy_length: insert_and_copy_length_symbol(insert_code, 0, true),
+			insert_extra,
+			copy_extra: (0, 0),
+			distance: None,
+		};
+	}
+
+	let distance = match distance_short_code(command.distance, cache) {
+		Some(0) if insert_code < 8 && copy_code < 16 => None,
+		Some(code) => Some((code, (0, 0))),
+		None => Some(distance_code(command.distance, n_postfix, n_direct)),
+	};
+
+	update_distance_cache(cache, command.distance, max_distance);
+
+	EncodedCommand{
+		insert_and_copy_length: insert_and_copy_length_symbol(insert_code, copy_code, distance.is_none()),
+		insert_extra,
+		copy_extra,
+		distance,
+	}
+}
+
+/// Wraps an output stream and compresses the data that is written to it into a brotli stream.
+///
+/// Input is collected into meta-blocks of up to 256 KiB, which are compressed with LZ77
+/// and prefix codes. Quality levels range from 0 (fastest) to 11 (densest), and determine
+/// how thoroughly earlier input is searched for matches. Quality 11 chooses its commands by
+/// optimal parsing, which also considers the distance cache and the static dictionary, and
+/// is many times slower than quality 10. From quality 5 on, the prefix codes of literals and
+/// distances depend on their context, and from quality 10 on, meta-blocks are split into blocks
+/// of symbols with different statistics. From quality 4 on, every meta-block picks the NPOSTFIX and
+/// NDIRECT that encode its distances best, which pays off for records with a fixed stride.
+/// flush() pushes out a meta-block with all the input written so far, byte-aligned, without ending the
+/// stream. The stream is completed by finish(), or, ignoring errors, when the Compressor is dropped.
+///
+/// # Examples
+/// ```
+/// use std::io::{ Read, Write };
+/// use brotli::{ Compressor, Decompressor };
+///
+/// let mut compressor = Compressor::new(Vec::new(), 9);
+/// compressor.write_all(b"Hello, Hello, Hello!").unwrap();
+/// let brotli_stream = compressor.finish().unwrap();
+///
+/// let mut decompressed = String::new();
+/// Decompressor::new(&brotli_stream[..]).read_to_string(&mut decompressed).unwrap();
+///
+/// assert_eq!("Hello, Hello, Hello!", decompressed);
+/// ```
+#[derive(Debug)]
+pub struct Compressor<W: Write> {
+	/// None once the stream has been finished
+	inner: Option<W>,
+	out: BitWriter<Vec<u8>>,
+	params: Params,
+	window_size: usize,
+	/// input from stream offset base on, covering the window and the input that has not been compressed yet
+	data: Vec<u8>,
+	base: usize,
+	/// stream offset of the first byte that has not been compressed yet
+	pos: usize,
+	match_finder: Box<dyn MatchFinder + Send>,
+	/// static dictionary index, for optimal parsing
+	dictionary: Option<Matcher>,
+	distance_cache: [usize; 4],
+	n_postfix: usize,
+	n_direct: usize,
+	/// length of the prefix dictionary at the start of the input, which the decoder does not take literal contexts from
+	prefix_len: usize,
+}
+
+impl<W: Write> Compressor<W> {
+	/// Creates a Compressor with the given quality level, and a window size of 4 MiB (WBITS = 22),
+	/// that writes the brotli stream to w.
+	pub fn new(w: W, quality: u32) -> Compressor<W> {
+		Compressor::with_wbits(w, quality, DEFAULT_WBITS)
+	}
+
+	/// Creates a Compressor with the given quality level and window size, where the window is
+	/// 2^wbits - 16 bytes. Quality is clamped to 0..11, and wbits to 10..24.
+	///
+	/// The quality level picks the MatchFinder: a SingleHash for levels 0 and 1, a HashChain
+	/// that searches deeper with every level for levels 2 to 9, and a BinaryTree for 10 and 11.
+	pub fn with_wbits(w: W, quality: u32, wbits: u8) -> Compressor<W> {
+		let params = Params::from_quality(cmp::min(quality, MAX_QUALITY));
+		let wbits = wbits.clamp(MIN_WBITS, MAX_WBITS);
+		let match_finder: Box<dyn MatchFinder + Send> = match params.strategy {
+			Strategy::SingleHash => Box::new(SingleHash::new(params.hash_bits)),
+			Strategy::HashChain => Box::new(HashChain::new(params.hash_bits, wbits, params.search_depth)),
+			Strategy::BinaryTree => Box::new(BinaryTree::new(params.hash_bits, wbits, params.search_depth)),
+		};
+
+		Compressor::with_params(w, params, wbits, match_finder)
+	}
+
+	/// Creates a Compressor like with_wbits(), that finds its backward references with match_finder
+	/// instead of the MatchFinder of the quality level. Matches that reach beyond the window or the
+	/// input, or that do not repeat the input, are ignored.
+	pub fn with_match_finder<M: MatchFinder + Send + 'static>(w: W, quality: u32, wbits: u8, match_finder: M) -> Compressor<W> {
+		let params = Params::from_quality(cmp::min(quality, MAX_QUALITY));
+
+		Compressor::with_params(w, params, wbits.clamp(MIN_WBITS, MAX_WBITS), Box::new(match_finder))
+	}
+
+	fn with_params(w: W, params: Params, wbits: u8, match_finder: Box<dyn MatchFinder + Send>) -> Compressor<W> {
+		let mut out = BitWriter::new(Vec::new());
+
+		match write_wbits(&mut out, wbits) {
+			Ok(()) => {},
+			Err(_) => unreachable!(), // confirmed unreachable, writing to a Vec does not fail
+		}
+
+		Compressor{
+			inner: Some(w),
+			out,
+			params,
+			window_size: (1 << wbits) - 16,
+			data: Vec::new(),
+			base: 0,
+			pos: 0,
+			match_finder,
+			dictionary: if params.optimal_passes > 0 { Some(Matcher::new()) } else { None },
+			distance_cache: INITIAL_DISTANCE_CACHE,
+			n_postfix: 0,
+			n_direct: 0,
+			prefix_len: 0,
+		}
+	}
+
+	/// Creates a Compressor like with_wbits(), whose backward references may also reach into dictionary,
+	/// as if it preceded the input. The brotli stream decompresses with Decompressor::with_prefix_dictionary()
+	/// and the same dictionary.
+	pub fn with_prefix_dictionary(w: W, quality: u32, wbits: u8, dictionary: &[u8]) -> Compressor<W> {
+		let mut compressor = Compressor::with_wbits(w, quality, wbits);
+
+		compressor.data = dictionary.to_vec();
+		compressor.pos = dictionary.len();
+		compressor.prefix_len = dictionary.len();
+
+		compressor
+	}
+
+	/// Gets a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		match self.inner {
+			Some(ref w) => w,
+			None => unreachable!(), // confirmed unreachable, inner is only taken by finish(), which consumes self
+		}
+	}
+
+	/// Compresses the remaining input, and completes the brotli stream with an ISLAST meta-block.
+	/// Returns the underlying writer, after flushing it.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.finish_stream()?;
+
+		match self.inner.take() {
+			Some(w) => Ok(w),
+			None => unreachable!(), // confirmed unreachable, see get_ref()
+		}
+	}
+
+	fn finish_stream(&mut self) -> io::Result<()> {
+		match self.base + self.data.len() - self.pos {
+			0 => {
+				// ISLAST, ISLASTEMPTY
+				self.out.write_bits(0b11, 2)?;
+			},
+			len => self.write_meta_block(len, true)?,
+		}
+
+		self.out.align()?;
+		self.write_out()?;
+
+		match self.inner {
+			Some(ref mut w) => w.flush(),
+			None => Ok(()),
+		}
+	}
+
+	/// Moves the complete bytes of the brotli stream to the underlying writer.
+	fn write_out(&mut self) -> io::Result<()> {
+		if let Some(ref mut w) = self.inner {
+			w.write_all(self.out.get_ref())?;
+			self.out.get_mut().clear();
+		}
+
+		Ok(())
+	}
+
+	/// Compresses the next len bytes of input into a meta-block.
+	fn write_meta_block(&mut self, len: usize, is_last: bool) -> io::Result<()> {
+		let start = self.pos;
+		let commands = match self.params.optimal_passes {
+			0 => self.create_commands(start, start + len),
+			_ => zopfli::create_commands(self, start, start + len),
+		};
+
+		self.store_meta_block(start, &commands, is_last)?;
+		self.pos += len;
+
+		// drop input that has moved out of the window, once that frees a substantial amount
+		let window_start = self.pos.saturating_sub(self.window_size);
+
+		if window_start - self.base >= cmp::max(self.window_size, META_BLOCK_LEN) {
+			self.data.drain(0..window_start - self.base);
+			self.base = window_start;
+		}
+
+		self.write_out()
+	}
+
+	/// Returns the best backward reference for the input at pos, after indexing all positions before it.
+	fn find_match(&mut self, pos: usize, end: usize, cache: &[usize; 4]) -> Match {
+		self.match_finder.insert_until(&self.data, self.base, pos);
+
+		let data = &self.data[pos - self.base..];
+		let max_len = end - pos;
+		let max_distance = cmp::min(pos, self.window_size);
+		let mut best = Match::none();
+
+		for (i, &distance) in cache.iter().enumerate() {
+			if distance > max_distance {
+				continue;
+			}
+
+			let len = match_len(&self.data[pos - distance - self.base..], data, max_len);
+
+			if len >= 2 {
+				let score = distance_cache_score(len, i);
+
+				if score > best.score {
+					best = Match{
+						len,
+						distance,
+						score,
+					};
+				}
+			}
+		}
+
+		if best.len < self.params.nice_len {
+			let nice_len = self.params.nice_len;
+
+			self.find_matches(pos, max_len, max_distance, &mut |len, distance| {
+				let score = backward_reference_score(len, distance);
+
+				if score > best.score {
+					best = Match{
+						len,
+						distance,
+						score,
+					};
+
+					return len < nice_len;
+				}
+
+				true
+			});
+		}
+
+		best
+	}
+
+	/// Calls f with the matches of the MatchFinder for the input at pos, like MatchFinder::find_matches(),
+	/// but leaves out those that break its contract, so that a faulty MatchFinder cannot corrupt the stream.
+	fn find_matches(&self, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool) {
+		let data = &self.data;
+		let base = self.base;
+		let max_distance = cmp::min(max_distance, pos - base);
+
+		self.match_finder.find_matches(data, base, pos, max_len, max_distance, &mut |len, distance| {
+			let valid = (1..=max_distance).contains(&distance) && (4..=max_len).contains(&len)
+				&& data[pos - base..pos - base + len] == data[pos - distance - base..pos - distance - base + len];
+
+			!valid || f(len, distance)
+		});
+	}
+
+	/// Splits the input from start to end into commands, by greedy or lazy matching.
+	fn create_commands(&mut self, start: usize, end: usize) -> Vec<Command> {
+		let mut commands = Vec::new();
+		let mut cache = self.distance_cache;
+		let mut insert_start = start;
+		let mut pos = start;
+
+		while pos < end {
+			let mut best = self.find_match(pos, end, &cache);
+
+			if best.len == 0 {
+				pos += 1;
+				continue;
+			}
+
+			if self.params.lazy {
+				for _ in 0..MAX_LAZY_STEPS {
+					if pos + 1 >= end {
+						break;
+					}
+
+					let next = self.find_match(pos + 1, end, &cache);
+
+					if next.score < best.score + LAZY_MATCH_MIN_GAIN {
+						break;
+					}
+
+					pos += 1;
+					best = next;
+				}
+			}
+
+			commands.push(Command{
+				insert_len: pos - insert_start,
+				copy_len: best.len,
+				distance: best.distance,
+				len: best.len,
+			});
+
+			update_distance_cache(&mut cache, best.distance, cmp::min(pos, self.window_size));
+
+			pos += best.len;
+			insert_start = pos;
+
+			if self.params.hash_match_positions {
+				self.match_finder.insert_until(&self.data, self.base, pos);
+			} else {
+				self.match_finder.skip_until(pos);
+			}
+		}
+
+		if insert_start < end {
+			commands.push(Command{
+				insert_len: end - insert_start,
+				copy_len: 0,
+				distance: 0,
+				len: 0,
+			});
+		}
+
+		commands
+	}
+
+	/// Writes a compressed meta-block for the commands, which cover the input from start on.
+	fn store_meta_block(&mut self, start: usize, commands: &[Command], is_last: bool) -> io::Result<()> {
+		let mut cache = self.distance_cache;
+		let mut encoded = self.encode_commands(start, commands, &mut cache);
+
+		if self.params.distance_params {
+			let distance_params = choose_distance_params(commands, &encoded);
+
+			if distance_params != (self.n_postfix, self.n_direct) {
+				let (n_postfix, n_direct) = distance_params;
+
+				self.n_postfix = n_postfix;
+				self.n_direct = n_direct;
+				cache = self.distance_cache;
+				encoded = self.encode_commands(start, commands, &mut cache);
+			}
+		}
+
+		let len = commands.iter().map(|command| command.insert_len + command.len).sum();
+		let literals = self.literals(start, commands);
+		let insert_and_copy_lengths = encoded.commands.iter().map(|encoded| encoded.insert_and_copy_length).collect::<Vec<_>>();
+		let distances = commands.iter().zip(&encoded.commands)
+			.filter_map(|(command, encoded)| encoded.distance.map(|(code, _)| (code, command.copy_len)))
+			.collect::<Vec<_>>();
+		let layout = BlockLayout::new(&literals, &insert_and_copy_lengths, &distances, encoded.distance_histogram.len(),
+			self.params.block_splitting, self.params.context_modeling);
+		let literal_codes = layout.literal_histograms.iter().map(|histogram| PrefixCode::from_histogram(histogram)).collect::<Vec<_>>();
+		let insert_and_copy_length_codes = layout.insert_and_copy_length_histograms.iter().map(|histogram| PrefixCode::from_histogram(histogram)).collect::<Vec<_>>();
+		let distance_codes = layout.distance_histograms.iter().map(|histogram| PrefixCode::from_histogram(histogram)).collect::<Vec<_>>();
+		let mut literal_switcher = BlockSwitcher::new(&layout.literal_split);
+		let mut insert_and_copy_length_switcher = BlockSwitcher::new(&layout.insert_and_copy_length_split);
+		let mut distance_switcher = BlockSwitcher::new(&layout.distance_split);
+
+		self.distance_cache = cache;
+
+		let out = &mut self.out;
+
+		write_meta_block_header(out, len, is_last, false)?;
+
+		literal_switcher.write_header(out)?;
+		insert_and_copy_length_switcher.write_header(out)?;
+		distance_switcher.write_header(out)?;
+		out.write_bits(self.n_postfix as u32, 2)?;
+		out.write_bits((self.n_direct >> self.n_postfix) as u32, 4)?;
+
+		for &context_mode in &layout.context_modes {
+			out.write_bits(context_mode as u32, 2)?;
+		}
+
+		blocks::write_count(out, literal_codes.len())?;
+
+		if literal_codes.len() > 1 {
+			blocks::write_context_map(out, &layout.literal_context_map, literal_codes.len())?;
+		}
+
+		blocks::write_count(out, distance_codes.len())?;
+
+		if distance_codes.len() > 1 {
+			blocks::write_context_map(out, &layout.distance_context_map, distance_codes.len())?;
+		}
+
+		for code in literal_codes.iter().chain(&insert_and_copy_length_codes).chain(&distance_codes) {
+			code.write(out)?;
+		}
+
+		let mut literals = literals.iter();
+
+		for (command, encoded) in commands.iter().zip(&encoded.commands) {
+			let block_type = insert_and_copy_length_switcher.next(out)?;
+
+			insert_and_copy_length_codes[block_type].write_symbol(out, encoded.insert_and_copy_length)?;
+			out.write_bits(encoded.insert_extra.0, encoded.insert_extra.1)?;
+			out.write_bits(encoded.copy_extra.0, encoded.copy_extra.1)?;
+
+			for &(literal, p1, p2) in literals.by_ref().take(command.insert_len) {
+				let block_type = literal_switcher.next(out)?;
+				let context = blocks::literal_context(layout.context_modes[block_type], p1, p2);
+				let code = layout.literal_context_map[block_type * blocks::NUM_LITERAL_CONTEXTS + context];
+
+				literal_codes[code].write_symbol(out, literal as usize)?;
+			}
+
+			if let Some((code, (extra, n_bits))) = encoded.distance {
+				let block_type = distance_switcher.next(out)?;
+				let context = blocks::distance_context(command.copy_len);
+
+				distance_codes[layout.distance_context_map[block_type * blocks::NUM_DISTANCE_CONTEXTS + context]].write_symbol(out, code)?;
+				out.write_bits(extra, n_bits)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns the literals of the commands, which cover the input from start on, as (literal, p1, p2),
+	/// where p1 and p2 are the two bytes before the literal, which its context depends on.
+	fn literals(&self, start: usize, commands: &[Command]) -> Vec<(u8, u8, u8)> {
+		let byte = |pos: usize| match pos {
+			pos if pos <= self.prefix_len => 0,
+			pos => self.data[pos - 1 - self.base],
+		};
+		let mut literals = Vec::new();
+		let mut pos = start;
+
+		for command in commands {
+			for pos in pos..pos + command.insert_len {
+				literals.push((self.data[pos - self.base], byte(pos), byte(pos.saturating_sub(1))));
+			}
+
+			pos += command.insert_len + command.len;
+		}
+
+		literals
+	}
+
+	/// Encodes the commands, which cover the input from start on, and counts the symbols they use.
+	fn encode_commands(&self, start: usize, commands: &[Command], cache: &mut [usize; 4]) -> EncodedCommands {
+		let num_distance_symbols = NUM_DISTANCE_SHORT_CODES + self.n_direct + (48 << self.n_postfix);
+		let mut encoded = EncodedCommands{
+			commands: Vec::with_capacity(commands.len()),
+			literal_histogram: vec![0; NUM_LITERAL_SYMBOLS],
+			insert_and_copy_length_histogram: vec![0; NUM_INSERT_AND_COPY_LENGTH_SYMBOLS],
+			distance_histogram: vec![0; num_distance_symbols],
+		};
+		let mut pos = start;
+
+		for command in commands {
+			for &literal in &self.data[pos - self.base..pos - self.base + command.insert_len] {
+				encoded.literal_histogram[literal as usize] += 1;
+			}
+
+			let max_distance = cmp::min(pos + command.insert_len, self.window_size);
+			let encoded_command = encode_command(command, cache, self.n_postfix, self.n_direct, max_distance);
+
+			encoded.insert_and_copy_length_histogram[encoded_command.insert_and_copy_length] += 1;
+
+			if let Some((code, _)) = encoded_command.distance {
+				encoded.distance_histogram[code] += 1;
+			}
+
+			encoded.commands.push(encoded_command);
+			pos += command.insert_len + command.len;
+		}
+
+		encoded
+	}
+}
+
+/// Writes ISLAST, MNIBBLES, MLEN and ISUNCOMPRESSED of a meta-block with len bytes, 0 < len <= 2^24.
+/// The last meta-block cannot be uncompressed.
+pub fn write_meta_block_header<W: Write>(out: &mut BitWriter<W>, len: usize, is_last: bool, is_uncompressed: bool) -> io::Result<()> {
+	debug_assert!(!(is_last && is_uncompressed));
+
+	out.write_bit(is_last)?;
+
+	if is_last {
+		// ISLASTEMPTY
+		out.write_bit(false)?;
+	}
+
+	let m_len = (len - 1) as u32;
+	let m_nibbles = cmp::max(4, (32 - m_len.leading_zeros() as usize).div_ceil(4));
+
+	out.write_bits(m_nibbles as u32 - 4, 2)?;
+	out.write_nibbles(m_len, m_nibbles)?;
+
+	if !is_last {
+		out.write_bit(is_uncompressed)?;
+	}
+
+	Ok(())
+}
+
+/// Writes a metadata meta-block without metadata, which ends at a byte boundary.
+fn write_empty_metadata_block<W: Write>(out: &mut BitWriter<W>) -> io::Result<()> {
+	// ISLAST
+	out.write_bit(false)?;
+	// MNIBBLES, reserved, MSKIPBYTES
+	out.write_bits(0b11, 2)?;
+	out.write_bit(false)?;
+	out.write_bits(0, 2)?;
+
+	out.align()
+}
+
+/// Writes the WBITS field of the stream header.
+pub fn write_wbits<W: Write>(out: &mut BitWriter<W>, wbits: u8) -> io::Result<()> {
+	let wbits = u32::from(wbits);
+
+	match wbits {
+		16 => out.write_bits(0, 1),
+		17 => out.write_bits(0b000_0001, 7),
+		18..=24 => out.write_bits(((wbits - 17) << 1) | 1, 4),
+		_ => out.write_bits(((wbits - 8) << 4) | 1, 7),
+	}
+}
+
+impl<W: Write> Write for Compressor<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		for chunk in buf.chunks(META_BLOCK_LEN) {
+			self.data.extend_from_slice(chunk);
+
+			// the last meta-block is written by finish(), so that it can be marked ISLAST
+			while self.base + self.data.len() - self.pos > META_BLOCK_LEN {
+				self.write_meta_block(META_BLOCK_LEN, false)?;
+			}
+		}
+
+		Ok(buf.len())
+	}
+
+	/// Compresses the buffered input into a meta-block, and byte-aligns the brotli stream with an empty
+	/// metadata block if needed, so that a decoder can decode all the input written so far from the
+	/// bytes written so far. Then flushes the underlying writer. The stream goes on afterwards.
+	fn flush(&mut self) -> io::Result<()> {
+		match self.base + self.data.len() - self.pos {
+			0 => {},
+			len => self.write_meta_block(len, false)?,
+		}
+
+		if !self.out.is_aligned() {
+			write_empty_metadata_block(&mut self.out)?;
+		}
+
+		self.write_out()?;
+
+		match self.inner {
+			Some(ref mut w) => w.flush(),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<W: Write> Drop for Compressor<W> {
+	fn drop(&mut self) {
+		if self.inner.is_some() {
+			let _ = self.finish_stream();
+		}
+	}
+}
+
+/// Compresses input into a brotli stream, with a quality level from 0 (fastest) to 11 (densest).
+///
+/// # Examples
+/// ```
+/// let brotli_stream = brotli::compress(&[b'X'; 64], 5);
+///
+/// assert!(brotli_stream.len() < 64);
+/// ```
+pub fn compress(input: &[u8], quality: u32) -> Vec<u8> {
+	let mut compressor = Compressor::new(Vec::new(), quality);
+
+	match compressor.write_all(input).and_then(|_| compressor.finish()) {
+		Ok(brotli_stream) => brotli_stream,
+		Err(_) => unreachable!(), // confirmed unreachable, writing to a Vec does not fail
+	}
+}
+
+mod tests {
+	#[test]
+	fn should_agree_with_decoder_on_insert_and_copy_lengths() {
+		use super::*;
+		use ::lookuptable::INSERT_LENGTHS_AND_COPY_LENGTHS;
+
+		for (insert_code, &insert_length_code) in INSERT_LENGTH_CODES.iter().enumerate() {
+			for (copy_code, &copy_length_code) in COPY_LENGTH_CODES.iter().enumerate() {
+				for &implicit_distance in &[false, true] {
+					let symbol = insert_and_copy_length_symbol(insert_code, copy_code, implicit_distance);
+					let ((insert_base, insert_n_bits), (copy_base, copy_n_bits)) = INSERT_LENGTHS_AND_COPY_LENGTHS[symbol];
+
+					assert_eq!(insert_length_code, (insert_base as usize, insert_n_bits));
+					assert_eq!(copy_length_code, (copy_base as usize, copy_n_bits));
+					assert_eq!(implicit_distance && insert_code < 8 && copy_code < 16, symbol < 128);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn should_fit_distance_params_to_strided_data() {
+		use super::*;
+		use ::{ Decompressor, StreamStructure };
+		use std::io::Read;
+
+		for &(stride, min_n_postfix) in &[(4, 2), (8, 3), (16, 3)] {
+			// records of a counter, a field with few values, and a field that drifts slowly
+			let mut input = Vec::new();
+			let (mut x, mut drift) = (1u32, 1000u32);
+
+			for i in 0..30000 / stride {
+				x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+				drift = drift.wrapping_add((x >> 30) & 1).wrapping_sub((x >> 29) & 1);
+
+				let mut record = vec![i as u8, [3, 5, 7, 11][(x >> 16) as usize & 3], (drift >> 8) as u8, drift as u8];
+
+				record.resize(stride, 0);
+				input.extend_from_slice(&record);
+			}
+
+			let mut brotli_streams = Vec::new();
+
+			// quality 3 keeps NPOSTFIX and NDIRECT at 0, quality 4 is the first to fit them
+			for &quality in &[3, 4] {
+				let brotli_stream = compress(&input, quality);
+				let structure = StreamStructure::parse(&brotli_stream[..]).unwrap();
+				let distance_params = structure.meta_blocks.iter()
+					.filter_map(|meta_block| meta_block.n_postfix.as_ref().map(|n_postfix| (n_postfix.value, meta_block.n_direct.as_ref().unwrap().value)))
+					.collect::<Vec<_>>();
+
+				assert!(!distance_params.is_empty(), "stride {}", stride);
+
+				for (n_postfix, n_direct) in distance_params {
+					if quality > 3 {
+						assert!(n_postfix as usize >= min_n_postfix, "stride {}", stride);
+					} else {
+						assert_eq!((0, 0), (n_postfix, n_direct), "stride {}", stride);
+					}
+				}
+
+				let mut decompressed = Vec::new();
+
+				Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+				assert!(input == decompressed, "stride {}", stride);
+
+				brotli_streams.push(brotli_stream);
+			}
+
+			assert!(brotli_streams[1].len() < brotli_streams[0].len(), "stride {}", stride);
+		}
+	}
+
+	#[test]
+	fn should_reach_into_the_prefix_dictionary_of_the_decoder() {
+		use super::*;
+		use ::Decompressor;
+		use std::io::Read;
+
+		let mut text = Vec::new();
+		let _ = std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut text);
+		let (dictionary, input) = (&text[..50000], &text[50000..70000]);
+
+		for &(quality, wbits) in &[(0, 22), (4, 22), (5, 22), (11, 22), (11, 15)] {
+			let mut compressor = Compressor::with_prefix_dictionary(Vec::new(), quality, wbits, dictionary);
+
+			compressor.write_all(input).unwrap();
+
+			let brotli_stream = compressor.finish().unwrap();
+			let mut decompressed = Vec::new();
+
+			Decompressor::with_prefix_dictionary(&brotli_stream[..], dictionary).read_to_end(&mut decompressed).unwrap();
+
+			assert!(input == &decompressed[..], "quality {}, WBITS {}", quality, wbits);
+			assert!(brotli_stream.len() < compress(input, quality).len(), "quality {}, WBITS {}", quality, wbits);
+		}
+	}
+
+	#[test]
+	fn should_split_mixed_data_into_blocks() {
+		use super::*;
+		use ::{ Decompressor, StreamStructure };
+		use std::io::Read;
+
+		// alternating runs of random letters from two alphabets that overlap, so that the letters before
+		// a letter tell little about the run it belongs to, and few backward references cover them
+		let mut input = Vec::new();
+		let mut x = 1u32;
+
+		for i in 0..24000 {
+			x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+
+			let letter = (x >> 16) as usize % 8;
+
+			input.push(if i / 3000 % 2 == 0 { b"abcdefgh"[letter] } else { b"efghijkl"[letter] });
+		}
+
+		let mut brotli_streams = Vec::new();
+
+		// quality 9 encodes every meta-block with a single block type per category, quality 10 is the first to split
+		for &quality in &[9, 10] {
+			let brotli_stream = compress(&input, quality);
+			let structure = StreamStructure::parse(&brotli_stream[..]).unwrap();
+			let n_bltypes = structure.meta_blocks.iter()
+				.filter_map(|meta_block| meta_block.literal_block_types.as_ref())
+				.map(|block_types| block_types.n_bltypes.value)
+				.max();
+
+			assert_eq!(Some(quality > 9), n_bltypes.map(|n_bltypes| n_bltypes > 1), "quality {}", quality);
+
+			let mut decompressed = Vec::new();
+
+			Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+			assert!(input == decompressed, "quality {}", quality);
+
+			brotli_streams.push(brotli_stream);
+		}
+
+		assert!(brotli_streams[1].len() < brotli_streams[0].len());
+	}
+
+	#[test]
+	fn should_encode_distances_the_way_the_decoder_reads_them() {
+		use super::*;
+
+		for &(n_postfix, n_direct) in &[(0, 0), (1, 4), (3, 120)] {
+			for distance in (1..100_000).chain(vec![(1 << 24) - 16]) {
+				let (code, (extra, n_bits)) = distance_code(distance, n_postfix, n_direct);
+
+				let decoded = if code < NUM_DISTANCE_SHORT_CODES + n_direct {
+					code + 1 - NUM_DISTANCE_SHORT_CODES
+				} else {
+					let n_dist_bits = 1 + ((code - n_direct - 16) >> (n_postfix + 1));
+					let h_code = (code - n_direct - 16) >> n_postfix;
+					let l_code = (code - n_direct - 16) & ((1 << n_postfix) - 1);
+					let offset = ((2 + (h_code & 1)) << n_dist_bits) - 4;
+
+					assert_eq!(n_dist_bits, n_bits);
+
+					((offset + extra as usize) << n_postfix) + l_code + n_direct + 1
+				};
+
+				assert_eq!(distance, decoded);
+				assert!(code < NUM_DISTANCE_SHORT_CODES + n_direct + (48 << n_postfix));
+			}
+		}
+	}
+}
@@ -0,0 +1,405 @@
+use super::{ Command, Compressor, EncodedCommands, COPY_LENGTH_CODES, INSERT_LENGTH_CODES, NUM_DISTANCE_SHORT_CODES,
+	NUM_INSERT_AND_COPY_LENGTH_SYMBOLS, NUM_LITERAL_SYMBOLS, bit_costs, distance_code, distance_short_code, insert_and_copy_length_symbol,
+	length_code, match_len, short_code_distance, update_distance_cache };
+
+use std::cmp;
+use std::f32;
+use std::io::Write;
+
+/// Number of command start positions that a copy is tried from, the cheapest ones so far.
+const MAX_STARTS: usize = 2;
+/// Only the full length of longer matches is considered, and the positions they cover are skipped.
+const MAX_ZOPFLI_LEN: usize = 325;
+
+/// The cheapest known way to reach a position of the input with a command that ends there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Node {
+	/// estimated number of bits of the commands up to the position
+	cost: f32,
+	insert_len: usize,
+	copy_len: usize,
+	distance: usize,
+	/// number of bytes the copy produces
+	len: usize,
+	/// distance cache after the command
+	distance_cache: [usize; 4],
+}
+
+/// A copy that the input at some position can start with, besides those from the distance cache.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+	len: usize,
+	copy_len: usize,
+	distance: usize,
+}
+
+/// Estimated number of bits per symbol.
+#[derive(Debug, Clone, PartialEq)]
+struct CostModel {
+	insert_and_copy_length: Vec<f32>,
+	distance: Vec<f32>,
+	/// literal_prefix[i] is the cost of the literals of the block before position i
+	literal_prefix: Vec<f32>,
+}
+
+impl CostModel {
+	/// Estimates the costs for the first pass, from the literals of the block, and from the
+	/// assumption that short lengths and distance codes are common.
+	fn from_literals(literals: &[u8], num_distance_symbols: usize) -> CostModel {
+		let mut literal_histogram = vec![0; NUM_LITERAL_SYMBOLS];
+
+		for &literal in literals {
+			literal_histogram[literal as usize] += 1;
+		}
+
+		CostModel{
+			insert_and_copy_length: (0..NUM_INSERT_AND_COPY_LENGTH_SYMBOLS).map(|symbol| (symbol as f32 + 11.0).log2()).collect(),
+			distance: (0..num_distance_symbols).map(|symbol| (symbol as f32 + 20.0).log2()).collect(),
+			literal_prefix: literal_prefix(literals, &bit_costs(&literal_histogram)),
+		}
+	}
+
+	/// Estimates the costs from the symbols of the commands of a previous pass.
+	fn from_commands(literals: &[u8], encoded: &EncodedCommands) -> CostModel {
+		CostModel{
+			insert_and_copy_length: bit_costs(&encoded.insert_and_copy_length_histogram),
+			distance: bit_costs(&encoded.distance_histogram),
+			literal_prefix: literal_prefix(literals, &bit_costs(&encoded.literal_histogram)),
+		}
+	}
+
+	fn literals(&self, from: usize, to: usize) -> f32 {
+		self.literal_prefix[to] - self.literal_prefix[from]
+	}
+
+	/// Returns the cost of a command without a copy, apart from its literals.
+	fn insert(&self, insert_code: usize) -> f32 {
+		self.insert_and_copy_length[insert_and_copy_length_symbol(insert_code, 0, true)] + INSERT_LENGTH_CODES[insert_code].1 as f32
+	}
+
+	/// Returns the cost of a command, apart from its literals. distance is the distance code
+	/// and the number of its extra bits.
+	fn command(&self, insert_code: usize, copy_code: usize, distance: (usize, usize)) -> f32 {
+		let n_extra_bits = INSERT_LENGTH_CODES[insert_code].1 + COPY_LENGTH_CODES[copy_code].1;
+
+		match distance {
+			(0, _) if insert_code < 8 && copy_code < 16 => {
+				self.insert_and_copy_length[insert_and_copy_length_symbol(insert_code, copy_code, true)] + n_extra_bits as f32
+			},
+			(code, n_distance_extra_bits) => {
+				self.insert_and_copy_length[insert_and_copy_length_symbol(insert_code, copy_code, false)]
+					+ self.distance[code] + (n_extra_bits + n_distance_extra_bits) as f32
+			},
+		}
+	}
+}
+
+fn literal_prefix(literals: &[u8], costs: &[f32]) -> Vec<f32> {
+	let mut prefix = Vec::with_capacity(literals.len() + 1);
+	let mut sum = 0.0;
+
+	prefix.push(sum);
+
+	for &literal in literals {
+		sum += costs[literal as usize];
+		prefix.push(sum);
+	}
+
+	prefix
+}
+
+/// Splits the input from start to end into the commands that are cheapest to encode, by finding
+/// the shortest path through the positions of the input, where every command is an edge that is
+/// weighed by a cost model. Every pass after the first one takes its cost model from the
+/// commands that the pass before it chose.
+pub fn create_commands<W: Write>(compressor: &mut Compressor<W>, start: usize, end: usize) -> Vec<Command> {
+	let (candidates, offsets) = find_candidates(compressor, start, end);
+	let literals = &compressor.data[start - compressor.base..end - compressor.base];
+	let num_distance_symbols = NUM_DISTANCE_SHORT_CODES + compressor.n_direct + (48 << compressor.n_postfix);
+	let mut model = CostModel::from_literals(literals, num_distance_symbols);
+	let mut commands = Vec::new();
+
+	for pass in 0..compressor.params.optimal_passes {
+		if pass > 0 {
+			let mut cache = compressor.distance_cache;
+
+			model = CostModel::from_commands(literals, &compressor.encode_commands(start, &commands, &mut cache));
+		}
+
+		commands = shortest_path(compressor, start, end, &candidates, &offsets, &model);
+	}
+
+	commands
+}
+
+/// Collects the matches per position, for the shortest path passes to choose from.
+/// Returns them along with the offsets of the matches of every position among them.
+fn find_candidates<W: Write>(compressor: &mut Compressor<W>, start: usize, end: usize) -> (Vec<Candidate>, Vec<usize>) {
+	let mut candidates = Vec::new();
+	let mut offsets = Vec::with_capacity(end - start + 1);
+	let mut matches = Vec::new();
+	let mut skip_until = start;
+	let nice_len = compressor.params.nice_len;
+
+	for pos in start..end {
+		offsets.push(candidates.len());
+
+		if pos < skip_until {
+			continue;
+		}
+
+		let max_distance = cmp::min(pos, compressor.window_size);
+		let max_len = end - pos;
+
+		matches.clear();
+		compressor.match_finder.insert_until(&compressor.data, compressor.base, pos);
+		compressor.find_matches(pos, max_len, max_distance, &mut |len, distance| {
+			// only the closest match of every length is worth trying
+			match matches.last() {
+				Some(&(last_len, _)) if len <= last_len => {},
+				_ => matches.push((len, distance)),
+			}
+
+			len < nice_len
+		});
+
+		for &(len, distance) in &matches {
+			candidates.push(Candidate{
+				len,
+				copy_len: len,
+				distance,
+			});
+		}
+
+		if let Some(ref dictionary) = compressor.dictionary {
+			let longest_match = matches.last().map_or(0, |&(len, _)| len);
+			let first = candidates.len();
+
+			// only the closest reference of every length that the backward references do not reach is worth trying
+			for m in dictionary.find_long(&compressor.data[pos - compressor.base..end - compressor.base], max_distance) {
+				let candidate = Candidate{
+					len: m.len,
+					copy_len: m.copy_len,
+					distance: m.distance,
+				};
+
+				if m.len <= longest_match {
+					continue;
+				}
+
+				match candidates[first..].iter().position(|other| other.len == m.len) {
+					Some(i) if candidates[first + i].distance <= m.distance => {},
+					Some(i) => candidates[first + i] = candidate,
+					None => candidates.push(candidate),
+				}
+			}
+		}
+
+		if let Some(&(len, _)) = matches.last() {
+			if len > MAX_ZOPFLI_LEN {
+				skip_until = pos + len;
+			}
+		}
+	}
+
+	offsets.push(candidates.len());
+	compressor.match_finder.insert_until(&compressor.data, compressor.base, end);
+
+	(candidates, offsets)
+}
+
+fn shortest_path<W: Write>(compressor: &Compressor<W>, start: usize, end: usize, candidates: &[Candidate], offsets: &[usize], model: &CostModel) -> Vec<Command> {
+	let n = end - start;
+	let unreachable = Node{
+		cost: f32::INFINITY,
+		insert_len: 0,
+		copy_len: 0,
+		distance: 0,
+		len: 0,
+		distance_cache: compressor.distance_cache,
+	};
+	let mut nodes = vec![unreachable; n + 1];
+	// command start positions, cheapest first, by the cost of the commands up to them,
+	// minus the cost of all literals before them, so that they compare at any later position
+	let mut starts = Vec::<(f32, usize)>::with_capacity(MAX_STARTS + 1);
+	let mut skip_until = 0;
+	let copy_codes = (0..MAX_ZOPFLI_LEN + 1).map(|len| length_code(&COPY_LENGTH_CODES, len)).collect::<Vec<_>>();
+
+	nodes[0].cost = 0.0;
+
+	for i in 0..n {
+		if nodes[i].cost < f32::INFINITY {
+			let key = nodes[i].cost - model.literal_prefix[i];
+			let rank = starts.iter().position(|&(other_key, _)| key < other_key).unwrap_or(starts.len());
+
+			starts.insert(rank, (key, i));
+			starts.truncate(MAX_STARTS);
+		}
+
+		if i < skip_until {
+			continue;
+		}
+
+		let pos = start + i;
+		let data = &compressor.data[pos - compressor.base..end - compressor.base];
+		let max_distance = cmp::min(pos, compressor.window_size);
+		let max_len = n - i;
+
+		for (rank, &(_, k)) in starts.iter().enumerate() {
+			let insert_len = i - k;
+			let insert_code = length_code(&INSERT_LENGTH_CODES, insert_len);
+			let cost = nodes[k].cost + model.literals(k, i);
+			let cache = nodes[k].distance_cache;
+			let mut update = |len: usize, copy_len: usize, distance: usize, distance_code: (usize, usize)| {
+				let copy_code = match copy_codes.get(copy_len) {
+					Some(&copy_code) => copy_code,
+					None => length_code(&COPY_LENGTH_CODES, copy_len),
+				};
+				let cost = cost + model.command(insert_code, copy_code, distance_code);
+				let node = &mut nodes[i + len];
+
+				if cost < node.cost {
+					let mut distance_cache = cache;
+					update_distance_cache(&mut distance_cache, distance, max_distance);
+
+					*node = Node{
+						cost,
+						insert_len,
+						copy_len,
+						distance,
+						len,
+						distance_cache,
+					};
+				}
+			};
+
+			let mut distances = [0; NUM_DISTANCE_SHORT_CODES];
+
+			for (code, distance) in distances.iter_mut().enumerate() {
+				*distance = short_code_distance(code, &cache);
+			}
+
+			for code in 0..NUM_DISTANCE_SHORT_CODES {
+				let distance = distances[code];
+
+				// codes that repeat the distance of an earlier code are never cheaper
+				if max_len < 2 || distance < 1 || distance as usize > max_distance || distances[0..code].contains(&distance) {
+					continue;
+				}
+
+				let distance = distance as usize;
+				let reference = &compressor.data[pos - distance - compressor.base..];
+
+				if reference[0] != data[0] || reference[1] != data[1] {
+					continue;
+				}
+
+				let len = match_len(reference, data, max_len);
+
+				if len > MAX_ZOPFLI_LEN {
+					update(len, len, distance, (code, 0));
+					skip_until = cmp::max(skip_until, i + len);
+					continue;
+				}
+
+				for len in 2..len + 1 {
+					update(len, len, distance, (code, 0));
+				}
+			}
+
+			// copies from farther back are only tried from the cheapest start
+			if rank > 0 {
+				continue;
+			}
+
+			let mut min_len = 4;
+
+			for candidate in &candidates[offsets[i]..offsets[i + 1]] {
+				let distance_code = match distance_short_code(candidate.distance, &cache) {
+					Some(code) => (code, 0),
+					None => {
+						let (code, (_, n_bits)) = distance_code(candidate.distance, compressor.n_postfix, compressor.n_direct);
+
+						(code, n_bits)
+					},
+				};
+
+				if candidate.distance > max_distance {
+					update(candidate.len, candidate.copy_len, candidate.distance, distance_code);
+				} else if candidate.len > MAX_ZOPFLI_LEN {
+					update(candidate.len, candidate.len, candidate.distance, distance_code);
+					skip_until = cmp::max(skip_until, i + candidate.len);
+				} else {
+					// every match covers the lengths that the closer ones before it do not
+					for len in min_len..candidate.len + 1 {
+						update(len, len, candidate.distance, distance_code);
+					}
+
+					min_len = candidate.len + 1;
+				}
+			}
+		}
+	}
+
+	// the block ends with the literals after the cheapest command start
+	let (_, mut k) = (0..n + 1)
+		.filter(|&k| nodes[k].cost < f32::INFINITY)
+		.map(|k| match k {
+			k if k == n => (nodes[k].cost, k),
+			k => (nodes[k].cost + model.literals(k, n) + model.insert(length_code(&INSERT_LENGTH_CODES, n - k)), k),
+		})
+		.fold((f32::INFINITY, 0), |best, candidate| if candidate.0 < best.0 { candidate } else { best });
+
+	let mut commands = Vec::new();
+
+	if k < n {
+		commands.push(Command{
+			insert_len: n - k,
+			copy_len: 0,
+			distance: 0,
+			len: 0,
+		});
+	}
+
+	while k > 0 {
+		let node = nodes[k];
+
+		commands.push(Command{
+			insert_len: node.insert_len,
+			copy_len: node.copy_len,
+			distance: node.distance,
+			len: node.len,
+		});
+
+		k -= node.insert_len + node.len;
+	}
+
+	commands.reverse();
+	commands
+}
+
+mod tests {
+	#[test]
+	fn should_parse_text_that_the_decoder_reproduces() {
+		use ::{ compress, Decompressor };
+		use std::io::Read;
+
+		let mut random = ::testing::random(0x2545_f491_4f6c_dd1d);
+
+		// words the static dictionary has, in some random order, and random bytes that it does not have
+		let words = [&b"the "[..], b"Time", b"of day", b" and", b"WORLD", b"compression", b"\n", b", ", b"\x00\xff", b"\x8f\x01\x8f"];
+		let mut input = Vec::new();
+
+		while input.len() < 20000 {
+			input.extend_from_slice(words[random(words.len() as u64) as usize]);
+		}
+
+		let brotli_stream = compress(&input, 11);
+		let mut decompressed = Vec::new();
+
+		Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+		assert!(input == decompressed);
+		assert!(brotli_stream.len() < compress(&input, 10).len());
+	}
+}
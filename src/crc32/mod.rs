@@ -0,0 +1,38 @@
+/// CRC of every byte value, for the reflected polynomial 0xedb88320.
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+	let mut table = [0; 256];
+	let mut i = 0;
+
+	while i < 256 {
+		let mut crc = i as u32;
+		let mut k = 0;
+
+		while k < 8 {
+			crc = if crc & 1 == 1 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+			k += 1;
+		}
+
+		table[i] = crc;
+		i += 1;
+	}
+
+	table
+}
+
+/// Returns the CRC-32 of bytes, as used by zlib, gzip and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+	!bytes.iter().fold(!0u32, |crc, &byte| TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+mod tests {
+	#[test]
+	fn should_compute_check_values() {
+		use super::*;
+
+		assert_eq!(0, crc32(b""));
+		assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+		assert_eq!(0x414f_a339, crc32(b"The quick brown fox jumps over the lazy dog"));
+	}
+}
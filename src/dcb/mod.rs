@@ -0,0 +1,105 @@
+use ::{ Decompressor, DecompressorError };
+use ::sha256::sha256;
+
+use std::io;
+use std::io::Read;
+
+/// Identifies a dictionary-compressed brotli stream, "\xffDCB".
+const MAGIC: [u8; 4] = [0xff, 0x44, 0x43, 0x42];
+const HEADER_LEN: usize = 36;
+
+/// Looks up compression dictionaries by their SHA-256 hash, for a DcbDecoder.
+pub trait DictionaryResolver {
+	/// Returns the dictionary whose SHA-256 hash is hash, or None if it is not available.
+	fn resolve(&mut self, hash: &[u8; 32]) -> Option<Vec<u8>>;
+}
+
+impl<F: FnMut(&[u8; 32]) -> Option<Vec<u8>>> DictionaryResolver for F {
+	fn resolve(&mut self, hash: &[u8; 32]) -> Option<Vec<u8>> {
+		self(hash)
+	}
+}
+
+/// Wraps a dictionary-compressed brotli stream, as sent with Content-Encoding: dcb
+/// in Compression Dictionary Transport (RFC 9842), and provides the decompressed stream through Read.
+///
+/// The stream starts with a 36-byte header, the magic number 0xff 0x44 0x43 0x42 followed by
+/// the SHA-256 hash of the dictionary. The brotli stream that follows has been compressed against
+/// the dictionary as a prefix, see Decompressor::with_prefix_dictionary().
+///
+/// # Examples
+/// ```
+/// use std::io::Read;
+/// use brotli::DcbDecoder;
+///
+/// let dictionary = b"Hello, World!".to_vec();
+///
+/// let mut dcb_stream = vec![0xff, 0x44, 0x43, 0x42];
+/// // SHA-256 of "Hello, World!"
+/// dcb_stream.extend_from_slice(&[
+///     0xdf, 0xfd, 0x60, 0x21, 0xbb, 0x2b, 0xd5, 0xb0, 0xaf, 0x67, 0x62, 0x90, 0x80, 0x9e, 0xc3, 0xa5,
+///     0x31, 0x91, 0xdd, 0x81, 0xc7, 0xf7, 0x0a, 0x4b, 0x28, 0x68, 0x8a, 0x36, 0x21, 0x82, 0x98, 0x6f,
+/// ]);
+/// // a single copy of 5 bytes from 6 bytes back, which reaches into the dictionary
+/// dcb_stream.extend_from_slice(&[0x1b, 0x04, 0x00, 0x00, 0x20, 0x00, 0x62, 0x90, 0x38, 0x00]);
+///
+/// let mut resolver = |_: &[u8; 32]| Some(dictionary.clone());
+/// let mut decoder = DcbDecoder::new(&dcb_stream[..], &mut resolver).unwrap();
+///
+/// let mut decompressed = String::new();
+/// decoder.read_to_string(&mut decompressed).unwrap();
+///
+/// assert_eq!("World", decompressed);
+/// ```
+#[derive(Debug)]
+pub struct DcbDecoder<R: Read> {
+	decompressor: Decompressor<R>,
+	dictionary_hash: [u8; 32],
+}
+
+impl<R: Read> DcbDecoder<R> {
+	/// Creates a DcbDecoder from a Read that is positioned at the beginning of a dcb stream.
+	/// Reads the header, resolves the dictionary through resolver, and verifies its hash.
+	pub fn new<D: DictionaryResolver>(mut r: R, resolver: &mut D) -> io::Result<DcbDecoder<R>> {
+		let mut header = [0; HEADER_LEN];
+
+		if let Err(e) = r.read_exact(&mut header) {
+			return match e.kind() {
+				io::ErrorKind::UnexpectedEof => Err(io::Error::new(io::ErrorKind::UnexpectedEof, DecompressorError::UnexpectedEOF.to_string())),
+				_ => Err(e),
+			};
+		}
+
+		if header[..MAGIC.len()] != MAGIC {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, DecompressorError::InvalidDcbHeader.to_string()));
+		}
+
+		let mut dictionary_hash = [0; 32];
+		dictionary_hash.copy_from_slice(&header[MAGIC.len()..]);
+
+		let dictionary = match resolver.resolve(&dictionary_hash) {
+			Some(dictionary) => dictionary,
+			None => return Err(io::Error::new(io::ErrorKind::NotFound, DecompressorError::UnknownDictionary.to_string())),
+		};
+
+		if sha256(&dictionary) != dictionary_hash {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, DecompressorError::DictionaryHashMismatch.to_string()));
+		}
+
+		Ok(DcbDecoder{
+			decompressor: Decompressor::with_prefix_dictionary(r, &dictionary),
+			dictionary_hash,
+		})
+	}
+
+	/// Returns the SHA-256 hash of the dictionary, as given in the header.
+	pub fn dictionary_hash(&self) -> &[u8; 32] {
+		&self.dictionary_hash
+	}
+}
+
+impl<R: Read> Read for DcbDecoder<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.decompressor.read(buf)
+	}
+}
@@ -0,0 +1,403 @@
+use ::dictionary::{ BROTLI_DICTIONARY, BROTLI_DICTIONARY_OFFSETS_BY_LENGTH, BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH };
+use ::transformation::{ transformation, uppercase_all, uppercase_first };
+
+use std::borrow::Cow;
+use std::cmp;
+
+const MIN_WORD_LEN: usize = 4;
+const MAX_WORD_LEN: usize = 24;
+const NUM_TRANSFORMS: usize = 121;
+/// Omit transforms remove up to this many bytes from either end of a word.
+const MAX_OMIT: usize = 9;
+/// Identity and OmitLast share a family, then UppercaseFirst, UppercaseAll, and OmitFirst1..9.
+const NUM_FAMILIES: usize = 3 + MAX_OMIT;
+/// Number of leading bytes of a transformed word that the index is keyed by.
+const KEY_LEN: usize = 4;
+const INDEX_BITS: u32 = 16;
+const INDEX_HASH_MUL: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// What a transform does to the word itself, between its prefix and suffix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WordTransform {
+	Identity,
+	OmitLast(usize),
+	OmitFirst(usize),
+	UppercaseFirst,
+	UppercaseAll,
+}
+
+impl WordTransform {
+	/// Word transforms of the same family only differ by how much they omit from the end of the word.
+	fn family(self) -> usize {
+		match self {
+			WordTransform::Identity | WordTransform::OmitLast(_) => 0,
+			WordTransform::UppercaseFirst => 1,
+			WordTransform::UppercaseAll => 2,
+			WordTransform::OmitFirst(n) => 2 + n,
+		}
+	}
+
+	/// Returns the number of bytes omitted from the end of the word.
+	fn omit_last(self) -> usize {
+		match self {
+			WordTransform::OmitLast(n) => n,
+			_ => 0,
+		}
+	}
+}
+
+/// A transform, split into the prefix and suffix it adds, and what it does to the word.
+#[derive(Debug, Clone)]
+struct Transform {
+	id: usize,
+	prefix: Vec<u8>,
+	word_transform: WordTransform,
+	suffix: Vec<u8>,
+}
+
+/// Transforms with the same prefix, which are looked up together.
+#[derive(Debug, Clone)]
+struct TransformGroup {
+	prefix: Vec<u8>,
+	/// transforms by word transform family, and by the number of bytes they omit from the end of the word
+	transforms: Vec<Vec<Vec<Transform>>>,
+}
+
+/// A reference into the static dictionary, that produces the first len bytes of some input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DictionaryMatch {
+	/// number of bytes the reference produces
+	pub len: usize,
+	/// copy length of the reference, which is the length of the dictionary word
+	pub copy_len: usize,
+	/// distance of the reference, beyond the maximum backward distance
+	pub distance: usize,
+	/// index of the word among the words of its length
+	pub word_id: usize,
+	/// transform that is applied to the word, 0 through 120
+	pub transform_id: usize,
+}
+
+/// Finds the static dictionary references that produce a prefix of some input, i.e. the words
+/// of the dictionary, transformed by any of the 121 transforms, the way that the decoder expands them.
+///
+/// Every word is indexed by the first bytes of its transformed forms, so that a lookup only
+/// compares the words that start the right way. Building the index takes a moment, a Matcher
+/// is meant to be reused.
+///
+/// # Examples
+/// ```
+/// use brotli::Matcher;
+///
+/// let matcher = Matcher::new();
+/// let matches = matcher.find_all(b"time of day", 0);
+///
+/// assert!(matches.iter().any(|m| m.len == 8 && m.copy_len == 4));
+/// ```
+#[derive(Debug)]
+pub struct Matcher {
+	groups: Vec<TransformGroup>,
+	/// start of the entries per bucket, followed by the end of the last bucket
+	buckets: Vec<u32>,
+	/// (key, family << 21 | copy length << 16 | word id), sorted by bucket
+	entries: Vec<(u64, u32)>,
+}
+
+impl Matcher {
+	/// Creates a Matcher, indexing the words of the static dictionary.
+	pub fn new() -> Matcher {
+		let mut groups = Vec::<TransformGroup>::new();
+
+		for transform in transforms() {
+			let i = match groups.iter().position(|group| group.prefix == transform.prefix) {
+				Some(i) => i,
+				None => {
+					groups.push(TransformGroup{
+						prefix: transform.prefix.clone(),
+						transforms: vec![vec![Vec::new(); MAX_OMIT + 1]; NUM_FAMILIES],
+					});
+
+					groups.len() - 1
+				},
+			};
+
+			groups[i].transforms[transform.word_transform.family()][transform.word_transform.omit_last()].push(transform);
+		}
+
+		let mut entries = Vec::new();
+
+		for (copy_len, &size_bits) in BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH.iter().enumerate().skip(MIN_WORD_LEN) {
+			for word_id in 0..1 << size_bits {
+				for family in 0..NUM_FAMILIES {
+					let word = family_word(family, dictionary_word(copy_len, word_id));
+
+					// OmitLast leaves words of fewer than KEY_LEN bytes, which are keyed by all of their bytes
+					let min_key_len = match family {
+						0 => cmp::max(1, copy_len.saturating_sub(MAX_OMIT)),
+						_ => word.len(),
+					};
+
+					for key_len in cmp::min(KEY_LEN, min_key_len)..cmp::min(KEY_LEN, word.len()) + 1 {
+						entries.push((key(&word[0..key_len]), (family << 21 | copy_len << 16 | word_id) as u32));
+					}
+				}
+			}
+		}
+
+		entries.sort_by_key(|&(key, word)| (bucket(key), key, word));
+
+		let mut buckets = vec![0; (1 << INDEX_BITS) + 1];
+
+		for &(key, _) in &entries {
+			buckets[bucket(key) + 1] += 1;
+		}
+
+		for i in 1..buckets.len() {
+			buckets[i] += buckets[i - 1];
+		}
+
+		Matcher{
+			groups,
+			buckets,
+			entries,
+		}
+	}
+
+	/// Returns every reference into the static dictionary that produces a prefix of input.
+	/// max_distance is the largest backward distance at the position of input, i.e. the minimum
+	/// of the window size and the number of bytes before it, which dictionary references lie beyond.
+	/// Words that a transform omits completely are left out.
+	pub fn find_all(&self, input: &[u8], max_distance: usize) -> Vec<DictionaryMatch> {
+		self.find(input, max_distance, 1)
+	}
+
+	/// Returns the references into the static dictionary that produce a prefix of input, like find_all(),
+	/// except for those that keep fewer than four bytes of the word. These are rarely worth their long
+	/// distance, and looking them up is where most of the time of find_all() goes.
+	pub fn find_long(&self, input: &[u8], max_distance: usize) -> Vec<DictionaryMatch> {
+		self.find(input, max_distance, KEY_LEN)
+	}
+
+	fn find(&self, input: &[u8], max_distance: usize, min_key_len: usize) -> Vec<DictionaryMatch> {
+		let mut matches = Vec::new();
+
+		for group in &self.groups {
+			if !input.starts_with(&group.prefix) {
+				continue;
+			}
+
+			let rest = &input[group.prefix.len()..];
+
+			for key_len in min_key_len..cmp::min(KEY_LEN, rest.len()) + 1 {
+				let key = key(&rest[0..key_len]);
+				let bucket = bucket(key);
+
+				for &(entry_key, entry) in &self.entries[self.buckets[bucket] as usize..self.buckets[bucket + 1] as usize] {
+					let family = (entry >> 21) as usize;
+					let transforms = &group.transforms[family];
+
+					if entry_key != key || transforms.iter().all(|transforms| transforms.is_empty()) {
+						continue;
+					}
+
+					let copy_len = (entry >> 16 & 0x1f) as usize;
+					let word_id = (entry & 0xffff) as usize;
+					let word = family_word(family, dictionary_word(copy_len, word_id));
+
+					// every transformed word is found by exactly one of its keys, which is all of it if it is short,
+					// and the longer ones are kept up to the first byte where they differ from the input
+					let omit_last = match key_len {
+						KEY_LEN => {
+							let common = word.iter().zip(rest).take_while(|&(a, b)| a == b).count();
+
+							word.len() - common..cmp::min(word.len() - KEY_LEN, MAX_OMIT) + 1
+						},
+						_ => word.len() - key_len..word.len() - key_len + 1,
+					};
+
+					for omit_last in omit_last {
+						let word = &word[0..word.len() - omit_last];
+
+						for transform in transforms[omit_last].iter().filter(|transform| rest[word.len()..].starts_with(&transform.suffix)) {
+							matches.push(DictionaryMatch{
+								len: group.prefix.len() + word.len() + transform.suffix.len(),
+								copy_len,
+								distance: max_distance + 1 + word_id + (transform.id << BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH[copy_len]),
+								word_id,
+								transform_id: transform.id,
+							});
+						}
+					}
+				}
+			}
+		}
+
+		matches
+	}
+}
+
+impl Default for Matcher {
+	fn default() -> Matcher {
+		Matcher::new()
+	}
+}
+
+fn dictionary_word(copy_len: usize, word_id: usize) -> &'static [u8] {
+	let offset = BROTLI_DICTIONARY_OFFSETS_BY_LENGTH[copy_len] + word_id * copy_len;
+
+	&BROTLI_DICTIONARY[offset..offset + copy_len]
+}
+
+/// Returns the word, transformed the way that all transforms of the family transform it,
+/// before OmitLast shortens it.
+fn family_word<'a>(family: usize, word: &'a [u8]) -> Cow<'a, [u8]> {
+	match family {
+		0 => Cow::Borrowed(word),
+		1 => Cow::Owned(uppercase_first(word)),
+		2 => Cow::Owned(uppercase_all(word)),
+		// the decoder keeps at least the last byte of the word
+		_ => Cow::Borrowed(&word[cmp::min(family - 2, word.len() - 1)..]),
+	}
+}
+
+fn key(bytes: &[u8]) -> u64 {
+	let value = bytes.iter().rev().fold(0, |acc, &byte| acc << 8 | u64::from(byte));
+
+	value | (bytes.len() as u64) << 32
+}
+
+fn bucket(key: u64) -> usize {
+	(key.wrapping_mul(INDEX_HASH_MUL) >> (64 - INDEX_BITS)) as usize
+}
+
+/// Splits the transforms into prefix, word transform and suffix, by transforming two probe words.
+/// The probe words have no byte in common, and the bytes of the second one do not occur in any
+/// prefix or suffix, so that the transformed words only agree on the prefix and the suffix.
+fn transforms() -> Vec<Transform> {
+	let probe = (b'a'..b'a' + MAX_WORD_LEN as u8).collect::<Vec<_>>();
+	let other_probe = (0x80..0x80 + MAX_WORD_LEN as u8).collect::<Vec<_>>();
+
+	(0..NUM_TRANSFORMS).map(|id| {
+		let transformed = transformation(id, &probe);
+		let other_transformed = transformation(id, &other_probe);
+		let prefix_len = transformed.iter().zip(&other_transformed).take_while(|&(a, b)| a == b).count();
+		let suffix_len = transformed.iter().rev().zip(other_transformed.iter().rev()).take_while(|&(a, b)| a == b).count();
+		let word = &transformed[prefix_len..transformed.len() - suffix_len];
+
+		let word_transform = if word == &probe[..] {
+			WordTransform::Identity
+		} else if word == &uppercase_first(&probe)[..] {
+			WordTransform::UppercaseFirst
+		} else if word == &uppercase_all(&probe)[..] {
+			WordTransform::UppercaseAll
+		} else if probe.ends_with(word) {
+			WordTransform::OmitFirst(MAX_WORD_LEN - word.len())
+		} else {
+			WordTransform::OmitLast(MAX_WORD_LEN - word.len())
+		};
+
+		Transform{
+			id,
+			prefix: Vec::from(&transformed[0..prefix_len]),
+			word_transform,
+			suffix: Vec::from(&transformed[transformed.len() - suffix_len..]),
+		}
+	}).collect()
+}
+
+mod tests {
+	#[test]
+	fn should_split_transforms() {
+		use super::*;
+
+		let transforms = transforms();
+
+		assert_eq!((vec![], WordTransform::Identity, vec![]), (transforms[0].prefix.clone(), transforms[0].word_transform, transforms[0].suffix.clone()));
+		assert_eq!((vec![], WordTransform::OmitFirst(2), vec![]), (transforms[11].prefix.clone(), transforms[11].word_transform, transforms[11].suffix.clone()));
+		assert_eq!((vec![], WordTransform::OmitLast(1), b"ing ".to_vec()), (transforms[49].prefix.clone(), transforms[49].word_transform, transforms[49].suffix.clone()));
+		assert_eq!((b" ".to_vec(), WordTransform::UppercaseAll, b"='".to_vec()), (transforms[119].prefix.clone(), transforms[119].word_transform, transforms[119].suffix.clone()));
+
+		for transform in &transforms {
+			let word = b"wordy";
+			let expected = transformation(transform.id, word);
+			let family_word = family_word(transform.word_transform.family(), word);
+			let word = match transform.word_transform {
+				WordTransform::OmitLast(n) => &family_word[0..word.len().saturating_sub(n)],
+				_ => &family_word[..],
+			};
+
+			assert_eq!(expected, [&transform.prefix[..], word, &transform.suffix[..]].concat());
+		}
+	}
+
+	#[test]
+	fn should_find_every_transformed_word() {
+		use super::*;
+
+		let mut random = ::testing::random(0x2545_f491_4f6c_dd1d);
+
+		let matcher = Matcher::new();
+		let transforms = transforms();
+
+		for _ in 0..2000 {
+			let copy_len = MIN_WORD_LEN + random((MAX_WORD_LEN - MIN_WORD_LEN + 1) as u64) as usize;
+			let word_id = random(1 << BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH[copy_len]) as usize;
+			let transform_id = random(NUM_TRANSFORMS as u64) as usize;
+			let transformed = transformation(transform_id, dictionary_word(copy_len, word_id));
+			let input = [&transformed[..], b"tail"].concat();
+
+			let is_it = |m: &DictionaryMatch| m.copy_len == copy_len && m.word_id == word_id && m.transform_id == transform_id && m.len == transformed.len();
+			let word_len = transformed.len() - transforms[transform_id].prefix.len() - transforms[transform_id].suffix.len();
+
+			assert_eq!(word_len > 0, matcher.find_all(&input, 100).iter().any(&is_it), "{} {} {}", copy_len, word_id, transform_id);
+			assert_eq!(word_len >= 4, matcher.find_long(&input, 100).iter().any(&is_it), "{} {} {}", copy_len, word_id, transform_id);
+		}
+	}
+
+	#[test]
+	fn should_find_matches_that_the_decoder_copies() {
+		use super::*;
+		use ::{ Decompressor, State };
+		use ::ringbuffer::RingBuffer;
+
+		let mut random = ::testing::random(0x9e37_79b9_7f4a_7c15);
+
+		// random text, made of transformed dictionary words, and random bytes in between
+		let mut text = Vec::new();
+
+		while text.len() < 1000 {
+			let copy_len = MIN_WORD_LEN + random((MAX_WORD_LEN - MIN_WORD_LEN + 1) as u64) as usize;
+			let word_id = random(1 << BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH[copy_len]) as usize;
+
+			text.extend(transformation(random(NUM_TRANSFORMS as u64) as usize, dictionary_word(copy_len, word_id)));
+
+			for _ in 0..random(4) {
+				text.push(b" .,\nat"[random(6) as usize]);
+			}
+		}
+
+		let matcher = Matcher::new();
+		let window_size = (1 << 16) - 16;
+		let mut decompressor = Decompressor::new(&[][..]);
+		let mut n_matches = 0;
+
+		decompressor.header.window_size = Some(window_size);
+		decompressor.output_window = Some(RingBuffer::with_capacity(window_size));
+
+		for pos in 0..text.len() {
+			for m in matcher.find_all(&text[pos..], cmp::min(pos, window_size)) {
+				decompressor.meta_block.copy_length = Some(m.copy_len as u32);
+				decompressor.meta_block.distance = Some(m.distance as u32);
+
+				assert_eq!(Ok(State::CopyLiterals(text[pos..pos + m.len].to_vec())), decompressor.copy_literals());
+
+				n_matches += 1;
+			}
+
+			decompressor.output_window.as_mut().unwrap().push(text[pos]);
+			decompressor.count_output += 1;
+		}
+
+		assert!(n_matches > 100000);
+	}
+}
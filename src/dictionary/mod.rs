@@ -1,3 +1,10 @@
+/// matcher finds the references into the static dictionary that produce a given input.
+mod matcher;
+pub use self::matcher::{ DictionaryMatch, Matcher };
+/// shared parses serialized shared dictionaries, which replace the static dictionary.
+mod shared;
+pub use self::shared::{ SharedDictionary, SharedDictionaryError };
+
 pub const BROTLI_DICTIONARY_OFFSETS_BY_LENGTH: [usize; 25] = [
 	     0,      0,      0,      0,      0,   4096,   9216,  21504,  35840,  44032,
 	 53248,  63488 , 74752,  87040,  93696, 100864, 104704, 106752, 108928, 113536,
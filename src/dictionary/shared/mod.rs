@@ -0,0 +1,632 @@
+use ::dictionary::{ BROTLI_DICTIONARY, BROTLI_DICTIONARY_OFFSETS_BY_LENGTH, BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH };
+use ::transformation::{ transformation, uppercase_all, uppercase_first };
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{ Display, Formatter };
+
+/// Identifies a serialized shared dictionary.
+const MAGIC: [u8; 2] = [0x91, 0x00];
+const MIN_WORD_LEN: usize = 4;
+const MAX_WORD_LEN: usize = 31;
+const MAX_SIZE_BITS: u8 = 15;
+/// Number of literal contexts, and maximum number of word lists, transform lists and dictionaries.
+const NUM_CONTEXTS: usize = 64;
+const MAX_STRINGLETS: usize = 256;
+/// Number of transforms of the built-in transform list.
+const NUM_BUILT_IN_TRANSFORMS: usize = 121;
+
+const TRANSFORM_OMIT_LAST_9: u8 = 9;
+const TRANSFORM_UPPERCASE_FIRST: u8 = 10;
+const TRANSFORM_UPPERCASE_ALL: u8 = 11;
+const TRANSFORM_OMIT_FIRST_1: u8 = 12;
+const TRANSFORM_OMIT_FIRST_9: u8 = 20;
+const TRANSFORM_SHIFT_FIRST: u8 = 21;
+const TRANSFORM_SHIFT_ALL: u8 = 22;
+const NUM_TRANSFORM_TYPES: u8 = 23;
+
+/// Words of the same length, packed per length.
+#[derive(Debug, Clone, PartialEq)]
+struct WordList {
+	/// log2 of the number of words per length, 0 for none
+	size_bits_by_length: [u8; MAX_WORD_LEN + 1],
+	offsets_by_length: [usize; MAX_WORD_LEN + 1],
+	data: Vec<u8>,
+}
+
+/// A word transform between a prefix and a suffix.
+#[derive(Debug, Clone, PartialEq)]
+struct Transform {
+	prefix: Vec<u8>,
+	transform_type: u8,
+	suffix: Vec<u8>,
+	/// scalar that shift transforms add to code points
+	parameter: u16,
+}
+
+/// A dictionary for references beyond the LZ77 window, made of a word list and a transform list.
+/// None refers to the built-in lists of RFC 7932.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Dictionary {
+	words: Option<usize>,
+	transforms: Option<usize>,
+}
+
+/// A shared dictionary in the serialized format of RFC 9841, Shared Brotli.
+///
+/// It consists of an LZ77 dictionary, which precedes the output like the prefix dictionary of
+/// Decompressor::with_prefix_dictionary(), and of up to 64 dictionaries made of custom word lists
+/// and custom transform lists, or the built-in ones, that replace the static dictionary of RFC 7932.
+/// With more than one of them, either the literal context before a reference selects one, or
+/// references that are out of range for the first dictionary continue into the next ones.
+///
+/// # Examples
+/// ```
+/// use std::io::Read;
+/// use brotli::{ Decompressor, SharedDictionary };
+///
+/// // a static dictionary reference to word 5 of length 5
+/// let brotli_stream = [0x1b, 0x04, 0x00, 0x00, 0x20, 0x00, 0x62, 0x90, 0x38, 0x00];
+///
+/// // no LZ77 dictionary, one word list with 2^3 words of length 5, and the built-in transforms
+/// let mut serialized = vec![0x91, 0x00, 0x00, 0x01, 0x00, 0x03];
+/// serialized.extend_from_slice(&[0; 26]);
+/// serialized.extend_from_slice(b"zero_one__two__threefour_five_six__seven");
+/// serialized.push(0x00);
+///
+/// let dictionary = SharedDictionary::from_bytes(&serialized).unwrap();
+///
+/// let mut decompressed = String::new();
+/// Decompressor::with_shared_dictionary(&brotli_stream[..], &dictionary).read_to_string(&mut decompressed).unwrap();
+///
+/// assert_eq!("five_", decompressed);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedDictionary {
+	bytes: Vec<u8>,
+	prefix: Vec<u8>,
+	word_lists: Vec<WordList>,
+	transform_lists: Vec<Vec<Transform>>,
+	dictionaries: Vec<Dictionary>,
+	/// dictionary per literal context, if the context selects the dictionary
+	context_map: Option<Vec<u8>>,
+}
+
+impl SharedDictionary {
+	/// Parses a serialized shared dictionary.
+	pub fn from_bytes(bytes: &[u8]) -> Result<SharedDictionary, SharedDictionaryError> {
+		if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+			return Err(SharedDictionaryError::InvalidMagic);
+		}
+
+		let mut r = Reader{
+			buf: bytes,
+			pos: MAGIC.len(),
+		};
+
+		let prefix_len = r.read_varint()?;
+		let prefix = r.take(prefix_len)?.to_vec();
+
+		let num_word_lists = r.read_count()?;
+		let mut word_lists = Vec::with_capacity(num_word_lists);
+
+		for _ in 0..num_word_lists {
+			word_lists.push(r.read_word_list()?);
+		}
+
+		let num_transform_lists = r.read_count()?;
+		let mut transform_lists = Vec::with_capacity(num_transform_lists);
+
+		for _ in 0..num_transform_lists {
+			transform_lists.push(r.read_transform_list()?);
+		}
+
+		let index = |i: u8, len: usize| match i as usize {
+			i if i < len => Ok(Some(i)),
+			i if i == len => Ok(None),
+			_ => Err(SharedDictionaryError::InvalidValue),
+		};
+
+		let dictionaries = if num_word_lists > 1 || num_transform_lists > 1 {
+			let num_dictionaries = r.read_count()?;
+			let mut dictionaries = Vec::with_capacity(num_dictionaries);
+
+			if num_dictionaries == 0 {
+				return Err(SharedDictionaryError::InvalidValue);
+			}
+
+			for _ in 0..num_dictionaries {
+				dictionaries.push(Dictionary{
+					words: index(r.read_u8()?, num_word_lists)?,
+					transforms: index(r.read_u8()?, num_transform_lists)?,
+				});
+			}
+
+			dictionaries
+		} else {
+			vec![Dictionary{
+				words: index(0, num_word_lists)?,
+				transforms: index(0, num_transform_lists)?,
+			}]
+		};
+
+		let context_map = if dictionaries.len() > 1 && r.read_bool()? {
+			let context_map = r.take(NUM_CONTEXTS)?.to_vec();
+
+			if context_map.iter().any(|&i| i as usize >= dictionaries.len()) {
+				return Err(SharedDictionaryError::InvalidValue);
+			}
+
+			Some(context_map)
+		} else {
+			None
+		};
+
+		if r.pos != bytes.len() {
+			return Err(SharedDictionaryError::TrailingBytes);
+		}
+
+		Ok(SharedDictionary{
+			bytes: bytes.to_vec(),
+			prefix,
+			word_lists,
+			transform_lists,
+			dictionaries,
+			context_map,
+		})
+	}
+
+	/// Returns the serialized shared dictionary.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	/// Returns the LZ77 dictionary, which may be empty.
+	pub fn prefix(&self) -> &[u8] {
+		&self.prefix
+	}
+
+	/// Resolves a reference to a word of length len, at address distance - max_distance - 1, after the
+	/// literal context, see locate().
+	/// Returns None if the address is beyond all dictionaries.
+	pub fn word(&self, len: usize, address: usize, context: usize) -> Option<Vec<u8>> {
+		let (i, index, transform_id) = self.locate(len, address, context)?;
+		let dictionary = self.dictionaries[i];
+
+		Some(self.transform(dictionary.transforms, transform_id, self.word_at(dictionary.words, len, index)))
+	}
+
+	/// Returns the number of the dictionary, the index among its words of length len, and the transform id,
+	/// that a reference at address resolves to, after the literal context. The dictionary that the context
+	/// selects, or else the first one, comes first, and addresses beyond its words continue through the others,
+	/// in order.
+	/// Returns None if the address is beyond all of them.
+	pub fn locate(&self, len: usize, address: usize, context: usize) -> Option<(usize, usize, usize)> {
+		if !(MIN_WORD_LEN..=MAX_WORD_LEN).contains(&len) {
+			return None;
+		}
+
+		let first = match self.context_map {
+			Some(ref context_map) => context_map[context] as usize,
+			None => 0,
+		};
+		let mut address = address;
+
+		let order = Some(first).into_iter().chain((0..self.dictionaries.len()).filter(|&i| i != first));
+
+		for i in order {
+			let dictionary = self.dictionaries[i];
+			let size_bits = self.size_bits(dictionary.words, len);
+			// a single word counts as none, like in the reference decoder
+			let num_words = (1 << size_bits) & !1;
+			let num_references = num_words * self.num_transforms(dictionary.transforms);
+
+			if i != first && num_words == 0 {
+				continue;
+			}
+
+			if address < num_references {
+				return Some((i, address & (num_words - 1), address >> size_bits));
+			}
+
+			address -= num_references;
+		}
+
+		None
+	}
+
+	fn size_bits(&self, words: Option<usize>, len: usize) -> usize {
+		match words {
+			Some(i) => self.word_lists[i].size_bits_by_length[len] as usize,
+			None if len < BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH.len() => BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH[len],
+			None => 0,
+		}
+	}
+
+	fn word_at(&self, words: Option<usize>, len: usize, index: usize) -> &[u8] {
+		let (data, offset): (&[u8], usize) = match words {
+			Some(i) => (&self.word_lists[i].data, self.word_lists[i].offsets_by_length[len]),
+			None => (&BROTLI_DICTIONARY, BROTLI_DICTIONARY_OFFSETS_BY_LENGTH[len]),
+		};
+
+		&data[offset + index * len..offset + (index + 1) * len]
+	}
+
+	fn num_transforms(&self, transforms: Option<usize>) -> usize {
+		match transforms {
+			Some(i) => self.transform_lists[i].len(),
+			None => NUM_BUILT_IN_TRANSFORMS,
+		}
+	}
+
+	fn transform(&self, transforms: Option<usize>, id: usize, word: &[u8]) -> Vec<u8> {
+		let transform = match transforms {
+			Some(i) => &self.transform_lists[i][id],
+			None => return transformation(id, word),
+		};
+		let t = transform.transform_type;
+		let word = if t <= TRANSFORM_OMIT_LAST_9 {
+			&word[..word.len().saturating_sub(t as usize)]
+		} else if (TRANSFORM_OMIT_FIRST_1..=TRANSFORM_OMIT_FIRST_9).contains(&t) {
+			&word[word.len().min((t - TRANSFORM_OMIT_FIRST_1) as usize + 1)..]
+		} else {
+			word
+		};
+		let word = match t {
+			TRANSFORM_UPPERCASE_FIRST => uppercase_first(word),
+			TRANSFORM_UPPERCASE_ALL => uppercase_all(word),
+			TRANSFORM_SHIFT_FIRST => {
+				let mut word = word.to_vec();
+
+				shift(&mut word, transform.parameter);
+
+				word
+			},
+			TRANSFORM_SHIFT_ALL => {
+				let mut word = word.to_vec();
+				let mut i = 0;
+
+				while i < word.len() {
+					i += shift(&mut word[i..], transform.parameter);
+				}
+
+				word
+			},
+			_ => word.to_vec(),
+		};
+
+		[&transform.prefix[..], &word, &transform.suffix].concat()
+	}
+}
+
+/// Adds the sign-extended parameter to the scalar value of the UTF-8 sequence at the start of word,
+/// keeping the length of the sequence. Returns the number of bytes it covers.
+fn shift(word: &mut [u8], parameter: u16) -> usize {
+	let scalar = u32::from(parameter & 0x7fff).wrapping_add(0x0100_0000 - u32::from(parameter & 0x8000));
+
+	match word[0] {
+		0x00..=0x7f => {
+			word[0] = (scalar.wrapping_add(u32::from(word[0])) & 0x7f) as u8;
+
+			1
+		},
+		0x80..=0xbf => 1,
+		0xc0..=0xdf => {
+			if word.len() < 2 {
+				return 1;
+			}
+
+			let scalar = scalar.wrapping_add(u32::from(word[1] & 0x3f) | u32::from(word[0] & 0x1f) << 6);
+
+			word[0] = 0xc0 | (scalar >> 6 & 0x1f) as u8;
+			word[1] = (word[1] & 0xc0) | (scalar & 0x3f) as u8;
+
+			2
+		},
+		0xe0..=0xef => {
+			if word.len() < 3 {
+				return word.len();
+			}
+
+			let scalar = scalar.wrapping_add(u32::from(word[2] & 0x3f) | u32::from(word[1] & 0x3f) << 6 | u32::from(word[0] & 0x0f) << 12);
+
+			word[0] = 0xe0 | (scalar >> 12 & 0x0f) as u8;
+			word[1] = (word[1] & 0xc0) | (scalar >> 6 & 0x3f) as u8;
+			word[2] = (word[2] & 0xc0) | (scalar & 0x3f) as u8;
+
+			3
+		},
+		0xf0..=0xf7 => {
+			if word.len() < 4 {
+				return word.len();
+			}
+
+			let scalar = scalar.wrapping_add(u32::from(word[3] & 0x3f) | u32::from(word[2] & 0x3f) << 6 | u32::from(word[1] & 0x3f) << 12 | u32::from(word[0] & 0x07) << 18);
+
+			word[0] = 0xf0 | (scalar >> 18 & 0x07) as u8;
+			word[1] = (word[1] & 0xc0) | (scalar >> 12 & 0x3f) as u8;
+			word[2] = (word[2] & 0xc0) | (scalar >> 6 & 0x3f) as u8;
+			word[3] = (word[3] & 0xc0) | (scalar & 0x3f) as u8;
+
+			4
+		},
+		_ => 1,
+	}
+}
+
+/// Reads the fields of a serialized shared dictionary.
+struct Reader<'a> {
+	buf: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, n: usize) -> Result<&'a [u8], SharedDictionaryError> {
+		if self.buf.len() - self.pos < n {
+			return Err(SharedDictionaryError::Truncated);
+		}
+
+		self.pos += n;
+
+		Ok(&self.buf[self.pos - n..self.pos])
+	}
+
+	fn read_u8(&mut self) -> Result<u8, SharedDictionaryError> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn read_u16(&mut self) -> Result<u16, SharedDictionaryError> {
+		let bytes = self.take(2)?;
+
+		Ok(u16::from(bytes[0]) | u16::from(bytes[1]) << 8)
+	}
+
+	fn read_bool(&mut self) -> Result<bool, SharedDictionaryError> {
+		match self.read_u8()? {
+			0 => Ok(false),
+			1 => Ok(true),
+			_ => Err(SharedDictionaryError::InvalidValue),
+		}
+	}
+
+	/// Reads a number of word lists, transform lists or dictionaries.
+	fn read_count(&mut self) -> Result<usize, SharedDictionaryError> {
+		match self.read_u8()? as usize {
+			n if n <= NUM_CONTEXTS => Ok(n),
+			_ => Err(SharedDictionaryError::InvalidValue),
+		}
+	}
+
+	/// Reads a little-endian base 128 number of up to 32 bits.
+	fn read_varint(&mut self) -> Result<usize, SharedDictionaryError> {
+		let mut value = 0;
+
+		for i in 0..5 {
+			let byte = self.read_u8()?;
+
+			if i == 4 && byte > 0x0f {
+				return Err(SharedDictionaryError::InvalidValue);
+			}
+
+			value |= ((byte & 0x7f) as usize) << (7 * i);
+
+			if byte < 0x80 {
+				break;
+			}
+		}
+
+		Ok(value)
+	}
+
+	fn read_word_list(&mut self) -> Result<WordList, SharedDictionaryError> {
+		let mut size_bits_by_length = [0; MAX_WORD_LEN + 1];
+		let mut offsets_by_length = [0; MAX_WORD_LEN + 1];
+		let mut len = 0;
+
+		size_bits_by_length[MIN_WORD_LEN..].copy_from_slice(self.take(MAX_WORD_LEN + 1 - MIN_WORD_LEN)?);
+
+		for word_len in MIN_WORD_LEN..=MAX_WORD_LEN {
+			let size_bits = size_bits_by_length[word_len];
+
+			if size_bits > MAX_SIZE_BITS {
+				return Err(SharedDictionaryError::InvalidValue);
+			}
+
+			offsets_by_length[word_len] = len;
+
+			if size_bits > 0 {
+				len += word_len << size_bits;
+			}
+		}
+
+		Ok(WordList{
+			size_bits_by_length,
+			offsets_by_length,
+			data: self.take(len)?.to_vec(),
+		})
+	}
+
+	fn read_transform_list(&mut self) -> Result<Vec<Transform>, SharedDictionaryError> {
+		// prefixes and suffixes, each one a length byte followed by as many bytes
+		let len = self.read_u16()? as usize;
+		let mut stringlets = Reader{
+			buf: self.take(len)?,
+			pos: 0,
+		};
+		let mut prefix_suffix = Vec::new();
+
+		while stringlets.pos < len {
+			if prefix_suffix.len() == MAX_STRINGLETS {
+				return Err(SharedDictionaryError::InvalidValue);
+			}
+
+			let stringlet_len = stringlets.read_u8()? as usize;
+
+			prefix_suffix.push(stringlets.take(stringlet_len)?.to_vec());
+		}
+
+		let num_transforms = self.read_u8()? as usize;
+		let triplets = self.take(3 * num_transforms)?;
+		let mut transforms = Vec::with_capacity(num_transforms);
+
+		for triplet in triplets.chunks(3) {
+			let (prefix_id, transform_type, suffix_id) = (triplet[0] as usize, triplet[1], triplet[2] as usize);
+
+			if prefix_id >= prefix_suffix.len() || suffix_id >= prefix_suffix.len() || transform_type >= NUM_TRANSFORM_TYPES {
+				return Err(SharedDictionaryError::InvalidValue);
+			}
+
+			transforms.push(Transform{
+				prefix: prefix_suffix[prefix_id].clone(),
+				transform_type,
+				suffix: prefix_suffix[suffix_id].clone(),
+				parameter: 0,
+			});
+		}
+
+		let is_shift = |transform: &Transform| transform.transform_type == TRANSFORM_SHIFT_FIRST || transform.transform_type == TRANSFORM_SHIFT_ALL;
+
+		// parameters follow only if there are shift transforms, and only those may use them
+		if transforms.iter().any(is_shift) {
+			for transform in &mut transforms {
+				transform.parameter = self.read_u16()?;
+
+				if transform.parameter != 0 && !is_shift(transform) {
+					return Err(SharedDictionaryError::InvalidValue);
+				}
+			}
+		}
+
+		Ok(transforms)
+	}
+}
+
+/// Error types that can be returned while parsing a shared dictionary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SharedDictionaryError {
+	/// The bytes do not start with the magic number of a serialized shared dictionary.
+	InvalidMagic,
+	/// The shared dictionary ended before all fields could be read.
+	Truncated,
+	/// The shared dictionary contained a value that is out of range.
+	InvalidValue,
+	/// The shared dictionary was followed by more bytes.
+	TrailingBytes,
+}
+
+impl Display for SharedDictionaryError {
+	fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+		fmt.write_str(match *self {
+			SharedDictionaryError::InvalidMagic => "Shared dictionary did not start with the expected magic number",
+			SharedDictionaryError::Truncated => "Shared dictionary ended prematurely",
+			SharedDictionaryError::InvalidValue => "Shared dictionary contained an invalid value",
+			SharedDictionaryError::TrailingBytes => "Shared dictionary was followed by unexpected bytes",
+		})
+	}
+}
+
+impl Error for SharedDictionaryError {}
+
+mod tests {
+	#[test]
+	fn should_parse_lz77_dictionary_and_built_in_lists() {
+		use super::*;
+
+		let dictionary = SharedDictionary::from_bytes(&[0x91, 0x00, 0x05, b'h', b'e', b'l', b'l', b'o', 0x00, 0x00]).unwrap();
+
+		assert_eq!(b"hello", dictionary.prefix());
+		assert_eq!(Some(b"time".to_vec()), dictionary.word(4, 0, 0));
+		assert_eq!(Some(b"Time ".to_vec()), dictionary.word(4, 4 << 10, 0));
+		assert_eq!(None, dictionary.word(4, 121 << 10, 0));
+		assert_eq!(None, dictionary.word(25, 0, 0));
+	}
+
+	#[test]
+	fn should_apply_custom_transforms_to_custom_words() {
+		use super::*;
+
+		let mut bytes = vec![0x91, 0x00, 0x00, 0x01];
+
+		// two words of length 6, none of the other lengths
+		bytes.extend_from_slice(&[0, 0, 1]);
+		bytes.extend_from_slice(&[0; 25]);
+		bytes.extend_from_slice("brötl".as_bytes());
+		bytes.extend_from_slice(b"stream");
+
+		// stringlets "", "<", ">", then identity, uppercase all with "<" and ">", omit first 2,
+		// omit last 3 with ">", and shift first by 1
+		bytes.push(0x01);
+		bytes.extend_from_slice(&[5, 0, 0, 1, b'<', 1, b'>']);
+		bytes.push(5);
+		bytes.extend_from_slice(&[0, 0, 0, 1, 11, 2, 0, 13, 0, 0, 3, 2, 0, 21, 0]);
+		bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 0]);
+
+		let dictionary = SharedDictionary::from_bytes(&bytes).unwrap();
+
+		assert_eq!(Some("brötl".as_bytes().to_vec()), dictionary.word(6, 0, 0));
+		assert_eq!(Some(b"stream".to_vec()), dictionary.word(6, 1, 0));
+		assert_eq!(Some(b"<STREAM>".to_vec()), dictionary.word(6, 3, 0));
+		assert_eq!(Some("ötl".as_bytes().to_vec()), dictionary.word(6, 4, 0));
+		assert_eq!(Some(b"str>".to_vec()), dictionary.word(6, 7, 0));
+		assert_eq!(Some("crötl".as_bytes().to_vec()), dictionary.word(6, 8, 0));
+		assert_eq!(None, dictionary.word(6, 10, 0));
+		assert_eq!(None, dictionary.word(5, 0, 0));
+	}
+
+	#[test]
+	fn should_select_dictionaries_by_context_and_by_address() {
+		use super::*;
+
+		let mut bytes = vec![0x91, 0x00, 0x00, 0x02];
+
+		// word lists with two words of length 4 each, and the built-in transforms
+		for words in &[b"abcdefgh", b"ijklmnop"] {
+			bytes.push(1);
+			bytes.extend_from_slice(&[0; 27]);
+			bytes.extend_from_slice(&words[..]);
+		}
+
+		bytes.push(0x00);
+
+		let mut by_address = bytes.clone();
+
+		// dictionaries of the second word list and of the built-in lists, selected by context
+		bytes.extend_from_slice(&[2, 1, 0, 2, 0, 1]);
+		bytes.extend_from_slice(&[0; 63]);
+		bytes.push(1);
+
+		let dictionary = SharedDictionary::from_bytes(&bytes).unwrap();
+
+		assert_eq!(Some(b"ijkl".to_vec()), dictionary.word(4, 0, 0));
+		assert_eq!(Some(b"time".to_vec()), dictionary.word(4, 0, 63));
+		assert_eq!(Some(b"mnop".to_vec()), dictionary.word(4, 1, 0));
+		assert_eq!(Some(b"ijkl ".to_vec()), dictionary.word(4, 2, 0));
+		// beyond the two words of the second word list, with all of the transforms
+		assert_eq!(Some(b"time".to_vec()), dictionary.word(4, 2 * 121, 0));
+		assert_eq!(Some(b"down".to_vec()), dictionary.word(4, 2 * 121 + 1, 0));
+
+		// the first and the second word list, in order
+		by_address.extend_from_slice(&[2, 0, 0, 1, 0, 0]);
+
+		let dictionary = SharedDictionary::from_bytes(&by_address).unwrap();
+
+		assert_eq!(Some(b"efgh".to_vec()), dictionary.word(4, 1, 0));
+		assert_eq!(Some(b"ijkl".to_vec()), dictionary.word(4, 2 * 121, 5));
+	}
+
+	#[test]
+	fn should_reject_malformed_dictionaries() {
+		use super::*;
+
+		assert_eq!(Err(SharedDictionaryError::InvalidMagic), SharedDictionary::from_bytes(b"BR"));
+		assert_eq!(Err(SharedDictionaryError::Truncated), SharedDictionary::from_bytes(&[0x91, 0x00, 0x05, b'h']));
+		assert_eq!(Err(SharedDictionaryError::InvalidValue), SharedDictionary::from_bytes(&[0x91, 0x00, 0x00, 65]));
+		assert_eq!(Err(SharedDictionaryError::TrailingBytes), SharedDictionary::from_bytes(&[0x91, 0x00, 0x00, 0x00, 0x00, 0x00]));
+
+		// a word list with 2^16 words of length 4
+		let mut bytes = vec![0x91, 0x00, 0x00, 0x01, 16];
+		bytes.extend_from_slice(&[0; 27]);
+
+		assert_eq!(Err(SharedDictionaryError::InvalidValue), SharedDictionary::from_bytes(&bytes));
+	}
+}
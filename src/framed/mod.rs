@@ -0,0 +1,389 @@
+use ::{ compress, Decompressor };
+use ::crc32::crc32;
+
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{ Display, Formatter };
+use std::io;
+use std::io::{ Read, Seek, SeekFrom, Write };
+
+const MAGIC: &[u8; 4] = b"BRFR";
+const VERSION: u8 = 1;
+const INDEX_MAGIC: &[u8; 4] = b"BRFI";
+/// Compressed length, decompressed length and CRC-32 of a chunk.
+const CHUNK_HEADER_LEN: u64 = 12;
+/// Offset of the index, and the index magic number.
+const FOOTER_LEN: u64 = 12;
+const INDEX_ENTRY_LEN: u64 = 20;
+/// Default number of decompressed bytes per chunk.
+const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+/// Chunk lengths are stored in 32 bits.
+const MAX_CHUNK_SIZE: usize = 1 << 30;
+
+/// Location, lengths and checksum of a chunk of a framed file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkInfo {
+	/// Offset of the brotli stream of the chunk within the framed file.
+	pub offset: u64,
+	/// Length of the brotli stream of the chunk.
+	pub compressed_len: u32,
+	/// Number of bytes that the brotli stream decompresses to.
+	pub decompressed_len: u32,
+	/// CRC-32 of the decompressed bytes.
+	pub crc32: u32,
+}
+
+impl ChunkInfo {
+	fn write_header(&self, buf: &mut Vec<u8>) {
+		buf.extend_from_slice(&self.compressed_len.to_le_bytes());
+		buf.extend_from_slice(&self.decompressed_len.to_le_bytes());
+		buf.extend_from_slice(&self.crc32.to_le_bytes());
+	}
+}
+
+/// Wraps an output stream and writes the data that is written to it as a framed file,
+/// a sequence of independently compressed chunks with checksums, followed by an index of the chunks.
+///
+/// The format is the magic number "BRFR" and a version byte, then for every chunk its compressed
+/// length, decompressed length and CRC-32 of the decompressed bytes as 32-bit little-endian numbers,
+/// followed by its brotli stream. A chunk header of zeros ends the chunks. The index that follows
+/// holds the number of chunks, and for every chunk the 64-bit offset of its brotli stream and the
+/// fields of its header. The file ends with the 64-bit offset of the index and the magic number "BRFI".
+///
+/// Every chunk holds chunk_size bytes of input, except for the last one, and for those that are ended
+/// early by flush(). The file is completed by finish(), or, ignoring errors, when the FramedWriter is dropped.
+///
+/// # Examples
+/// ```
+/// use std::io::{ Cursor, Read, Write };
+/// use brotli::{ FramedReader, FramedWriter };
+///
+/// let mut writer = FramedWriter::with_chunk_size(Vec::new(), 5, 16);
+/// writer.write_all(b"chunked into independent brotli streams").unwrap();
+/// let framed = writer.finish().unwrap();
+///
+/// let mut reader = FramedReader::new(Cursor::new(framed)).unwrap();
+///
+/// assert_eq!(3, reader.chunks().len());
+/// assert_eq!(b"ependent brotli ".to_vec(), reader.read_chunk(1).unwrap());
+///
+/// let mut decompressed = String::new();
+/// reader.read_to_string(&mut decompressed).unwrap();
+///
+/// assert_eq!("chunked into independent brotli streams", decompressed);
+/// ```
+#[derive(Debug)]
+pub struct FramedWriter<W: Write> {
+	/// None once the file has been finished
+	inner: Option<W>,
+	quality: u32,
+	chunk_size: usize,
+	buf: Vec<u8>,
+	/// number of bytes written to inner
+	offset: u64,
+	chunks: Vec<ChunkInfo>,
+}
+
+impl<W: Write> FramedWriter<W> {
+	/// Creates a FramedWriter that compresses chunks of 1 MiB with quality from 0 (fastest) to 11 (densest).
+	pub fn new(w: W, quality: u32) -> FramedWriter<W> {
+		FramedWriter::with_chunk_size(w, quality, DEFAULT_CHUNK_SIZE)
+	}
+
+	/// Creates a FramedWriter that compresses chunks of chunk_size bytes, clamped to 1..=2^30, with quality
+	/// from 0 (fastest) to 11 (densest).
+	pub fn with_chunk_size(w: W, quality: u32, chunk_size: usize) -> FramedWriter<W> {
+		FramedWriter{
+			inner: Some(w),
+			quality,
+			chunk_size: chunk_size.clamp(1, MAX_CHUNK_SIZE),
+			buf: Vec::new(),
+			offset: 0,
+			chunks: Vec::new(),
+		}
+	}
+
+	/// Gets a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		match self.inner {
+			Some(ref w) => w,
+			None => unreachable!(), // confirmed unreachable, inner is only taken by finish(), which consumes self
+		}
+	}
+
+	/// Compresses the buffered data into a last chunk, and completes the file with the index.
+	/// Returns the underlying writer, after flushing it.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.finish_file()?;
+
+		match self.inner.take() {
+			Some(w) => Ok(w),
+			None => unreachable!(), // confirmed unreachable, see get_ref()
+		}
+	}
+
+	fn finish_file(&mut self) -> io::Result<()> {
+		self.write_chunk()?;
+
+		let index_offset = self.offset + CHUNK_HEADER_LEN;
+		let mut buf = vec![0; CHUNK_HEADER_LEN as usize];
+
+		buf.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+
+		for chunk in &self.chunks {
+			buf.extend_from_slice(&chunk.offset.to_le_bytes());
+			chunk.write_header(&mut buf);
+		}
+
+		buf.extend_from_slice(&index_offset.to_le_bytes());
+		buf.extend_from_slice(INDEX_MAGIC);
+
+		self.write_out(&buf)?;
+
+		match self.inner {
+			Some(ref mut w) => w.flush(),
+			None => Ok(()),
+		}
+	}
+
+	/// Compresses the buffered data into a chunk, if there is any.
+	fn write_chunk(&mut self) -> io::Result<()> {
+		if self.offset == 0 {
+			self.write_out(&[&MAGIC[..], &[VERSION]].concat())?;
+		}
+
+		if self.buf.is_empty() {
+			return Ok(());
+		}
+
+		let brotli_stream = compress(&self.buf, self.quality);
+		let chunk = ChunkInfo{
+			offset: self.offset + CHUNK_HEADER_LEN,
+			compressed_len: brotli_stream.len() as u32,
+			decompressed_len: self.buf.len() as u32,
+			crc32: crc32(&self.buf),
+		};
+		let mut buf = Vec::with_capacity(CHUNK_HEADER_LEN as usize + brotli_stream.len());
+
+		chunk.write_header(&mut buf);
+		buf.extend_from_slice(&brotli_stream);
+
+		self.write_out(&buf)?;
+		self.chunks.push(chunk);
+		self.buf.clear();
+
+		Ok(())
+	}
+
+	fn write_out(&mut self, buf: &[u8]) -> io::Result<()> {
+		if let Some(ref mut w) = self.inner {
+			w.write_all(buf)?;
+			self.offset += buf.len() as u64;
+		}
+
+		Ok(())
+	}
+}
+
+impl<W: Write> Write for FramedWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = cmp::min(buf.len(), self.chunk_size - self.buf.len());
+
+		self.buf.extend_from_slice(&buf[..n]);
+
+		if self.buf.len() == self.chunk_size {
+			self.write_chunk()?;
+		}
+
+		Ok(n)
+	}
+
+	/// Ends the current chunk, and flushes the underlying writer.
+	fn flush(&mut self) -> io::Result<()> {
+		self.write_chunk()?;
+
+		match self.inner {
+			Some(ref mut w) => w.flush(),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<W: Write> Drop for FramedWriter<W> {
+	fn drop(&mut self) {
+		if self.inner.is_some() {
+			let _ = self.finish_file();
+		}
+	}
+}
+
+/// Wraps a framed file, as written by FramedWriter, and provides random access to its chunks,
+/// and the decompressed stream of all of them through Read.
+///
+/// Every chunk is verified against its length and CRC-32 when it is read. Errors for corrupt
+/// chunks wrap a FramedError::CorruptChunk with the number of the chunk.
+#[derive(Debug)]
+pub struct FramedReader<R: Read + Seek> {
+	inner: R,
+	chunks: Vec<ChunkInfo>,
+	/// next chunk to read through Read
+	next_chunk: usize,
+	buf: Vec<u8>,
+	buf_pos: usize,
+}
+
+impl<R: Read + Seek> FramedReader<R> {
+	/// Creates a FramedReader from a Read + Seek that holds a framed file, and reads its index.
+	pub fn new(mut r: R) -> io::Result<FramedReader<R>> {
+		let mut header = [0; 5];
+
+		r.seek(SeekFrom::Start(0))?;
+		r.read_exact(&mut header)?;
+
+		if &header[..4] != MAGIC || header[4] != VERSION {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, FramedError::InvalidHeader));
+		}
+
+		let len = r.seek(SeekFrom::End(0))?;
+		let mut footer = [0; FOOTER_LEN as usize];
+
+		if len < header.len() as u64 + CHUNK_HEADER_LEN + 4 + FOOTER_LEN {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, FramedError::InvalidIndex));
+		}
+
+		r.seek(SeekFrom::Start(len - FOOTER_LEN))?;
+		r.read_exact(&mut footer)?;
+
+		let index_offset = u64_at(&footer, 0);
+
+		if &footer[8..] != INDEX_MAGIC || index_offset < header.len() as u64 + CHUNK_HEADER_LEN || index_offset > len - FOOTER_LEN - 4 {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, FramedError::InvalidIndex));
+		}
+
+		let mut index = vec![0; (len - FOOTER_LEN - index_offset) as usize];
+
+		r.seek(SeekFrom::Start(index_offset))?;
+		r.read_exact(&mut index)?;
+
+		let num_chunks = u32_at(&index, 0) as u64;
+
+		if index.len() as u64 != 4 + num_chunks * INDEX_ENTRY_LEN {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, FramedError::InvalidIndex));
+		}
+
+		let chunks = index[4..].chunks(INDEX_ENTRY_LEN as usize).map(|entry| ChunkInfo{
+			offset: u64_at(entry, 0),
+			compressed_len: u32_at(entry, 8),
+			decompressed_len: u32_at(entry, 12),
+			crc32: u32_at(entry, 16),
+		}).collect::<Vec<_>>();
+
+		let within_chunks = |chunk: &ChunkInfo| chunk.offset >= header.len() as u64 + CHUNK_HEADER_LEN
+			&& chunk.offset.checked_add(u64::from(chunk.compressed_len)).is_some_and(|end| end <= index_offset - CHUNK_HEADER_LEN);
+
+		if !chunks.iter().all(within_chunks) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, FramedError::InvalidIndex));
+		}
+
+		Ok(FramedReader{
+			inner: r,
+			chunks,
+			next_chunk: 0,
+			buf: Vec::new(),
+			buf_pos: 0,
+		})
+	}
+
+	/// Returns the chunks of the file, as listed in its index.
+	pub fn chunks(&self) -> &[ChunkInfo] {
+		&self.chunks
+	}
+
+	/// Decompresses chunk i, and verifies it against its header and its CRC-32.
+	pub fn read_chunk(&mut self, i: usize) -> io::Result<Vec<u8>> {
+		let chunk = match self.chunks.get(i) {
+			Some(chunk) => *chunk,
+			None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk number beyond the last chunk")),
+		};
+		let corrupt = || io::Error::new(io::ErrorKind::InvalidData, FramedError::CorruptChunk(i));
+		let mut compressed = vec![0; (CHUNK_HEADER_LEN + u64::from(chunk.compressed_len)) as usize];
+
+		self.inner.seek(SeekFrom::Start(chunk.offset - CHUNK_HEADER_LEN))?;
+		self.inner.read_exact(&mut compressed)?;
+
+		let mut header = Vec::with_capacity(CHUNK_HEADER_LEN as usize);
+		chunk.write_header(&mut header);
+
+		if compressed[..CHUNK_HEADER_LEN as usize] != header[..] {
+			return Err(corrupt());
+		}
+
+		let mut decompressed = Vec::new();
+
+		match Decompressor::new(&compressed[CHUNK_HEADER_LEN as usize..]).take(u64::from(chunk.decompressed_len) + 1).read_to_end(&mut decompressed) {
+			Ok(len) if len == chunk.decompressed_len as usize && crc32(&decompressed) == chunk.crc32 => Ok(decompressed),
+			_ => Err(corrupt()),
+		}
+	}
+
+	/// Reads and verifies every chunk. Returns an error for the first corrupt chunk.
+	pub fn verify(&mut self) -> io::Result<()> {
+		for i in 0..self.chunks.len() {
+			self.read_chunk(i)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl<R: Read + Seek> Read for FramedReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		while self.buf_pos == self.buf.len() && self.next_chunk < self.chunks.len() {
+			let i = self.next_chunk;
+
+			self.buf = self.read_chunk(i)?;
+			self.buf_pos = 0;
+			self.next_chunk += 1;
+		}
+
+		let n = cmp::min(buf.len(), self.buf.len() - self.buf_pos);
+
+		buf[..n].copy_from_slice(&self.buf[self.buf_pos..self.buf_pos + n]);
+		self.buf_pos += n;
+
+		Ok(n)
+	}
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+	u64::from(u32_at(buf, offset)) | u64::from(u32_at(buf, offset + 4)) << 32
+}
+
+/// Error types that can be returned while reading a framed file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FramedError {
+	/// The file does not start with the magic number and version of a framed file.
+	InvalidHeader,
+	/// The index at the end of the file is missing or malformed.
+	InvalidIndex,
+	/// The chunk with this number does not match its index entry, or its brotli stream is invalid,
+	/// or does not match its length or CRC-32.
+	CorruptChunk(usize),
+}
+
+impl Display for FramedError {
+	fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+		match *self {
+			FramedError::InvalidHeader => fmt.write_str("Framed file did not start with the expected magic number and version"),
+			FramedError::InvalidIndex => fmt.write_str("Framed file contained an invalid index"),
+			FramedError::CorruptChunk(i) => write!(fmt, "Framed file contained corrupt chunk {}", i),
+		}
+	}
+}
+
+impl Error for FramedError {}
@@ -1,5 +1,22 @@
 pub mod tree;
 
+use ::bitwriter::BitWriter;
+
+use std::cmp;
+use std::io;
+use std::io::Write;
+
+/// Maximum length of a code in a brotli prefix code.
+pub const MAX_CODE_LENGTH: usize = 15;
+/// Maximum length of a code in the prefix code for code lengths.
+const MAX_CODE_LENGTH_CODE_LENGTH: usize = 5;
+/// Code length that code 16 repeats, before any non-zero code length has been seen.
+const INITIAL_REPEATED_CODE_LENGTH: usize = 8;
+/// Order in which the code lengths of the code length alphabet are stored.
+const CODE_LENGTH_CODE_ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+/// Fixed prefix code (bits, length) for the code lengths of the code length alphabet.
+const CODE_LENGTH_CODE_LENGTH_CODES: [(u32, usize); 6] = [(0b00, 2), (0b0111, 4), (0b011, 3), (0b10, 2), (0b01, 2), (0b1111, 4)];
+
 fn bit_string_from_code_and_length(code: usize, len: usize) -> Vec<bool> {
 	let mut bits = vec![false; len];
 
@@ -48,5 +65,459 @@ pub fn codes_from_lengths(lengths: &[usize]) -> tree::Tree {
 	codes_from_lengths_and_symbols(lengths, &symbols)
 }
 
+/// Returns optimal code lengths for the given symbol frequencies, under the constraint that
+/// no code is longer than max_len, using the package-merge algorithm.
+/// Unused symbols get length 0, and so does a single used one, which takes no bits to encode.
+pub fn code_lengths_from_histogram(histogram: &[u32], max_len: usize) -> Vec<usize> {
+	let mut lengths = vec![0; histogram.len()];
+	let mut symbols = (0..histogram.len()).filter(|&symbol| histogram[symbol] > 0).collect::<Vec<_>>();
+
+	if symbols.len() < 2 {
+		return lengths;
+	}
+
+	assert!(symbols.len() <= 1 << max_len);
+
+	symbols.sort_by_key(|&symbol| histogram[symbol]);
+
+	// every list holds (weight, leaf) items, where leaf is an index into symbols,
+	// or None for a package of two consecutive items of the previous list
+	let leaves = symbols.iter().enumerate().map(|(leaf, &symbol)| (u64::from(histogram[symbol]), Some(leaf))).collect::<Vec<_>>();
+	let mut lists = vec![leaves.clone()];
+
+	for _ in 1..max_len {
+		let packages = {
+			let previous = &lists[lists.len() - 1];
+
+			previous.chunks(2).filter(|pair| pair.len() == 2).map(|pair| (pair[0].0 + pair[1].0, None)).collect::<Vec<_>>()
+		};
+
+		let mut list = Vec::with_capacity(leaves.len() + packages.len());
+		let (mut i, mut j) = (0, 0);
+
+		while i < leaves.len() || j < packages.len() {
+			if j == packages.len() || (i < leaves.len() && leaves[i].0 <= packages[j].0) {
+				list.push(leaves[i]);
+				i += 1;
+			} else {
+				list.push(packages[j]);
+				j += 1;
+			}
+		}
+
+		lists.push(list);
+	}
+
+	// the first 2n - 2 items of the last list are selected, the packages among them select
+	// the first items of the list before, and so on. Every selection of a leaf adds one to its length.
+	let mut n_selected = 2 * symbols.len() - 2;
+
+	for list in lists.iter().rev() {
+		let mut n_packages = 0;
+
+		for &(_, leaf) in &list[0..n_selected] {
+			match leaf {
+				Some(leaf) => lengths[symbols[leaf]] += 1,
+				None => n_packages += 1,
+			}
+		}
+
+		n_selected = 2 * n_packages;
+	}
+
+	lengths
+}
+
+/// Returns the canonical codes for the given code lengths, i.e. the codes that codes_from_lengths()
+/// assigns to the symbols. Codes are read starting at their most significant bit.
+pub fn canonical_codes(lengths: &[usize]) -> Vec<u16> {
+	let max_length = lengths.iter().fold(0, |acc, &len| if len > acc { len } else { acc });
+	let mut bl_count = vec![0; max_length + 1];
+	for &len in lengths {
+		bl_count[len] += 1;
+	}
+
+	bl_count[0] = 0;
+
+	let mut code = 0;
+	let mut next_code = vec![0; max_length + 1];
+	for bits in 1..max_length + 1 {
+		code = (code + bl_count[bits - 1]) << 1;
+		next_code[bits] = code;
+	}
+
+	lengths.iter().map(|&len| {
+		if len == 0 {
+			return 0;
+		}
+
+		let code = next_code[len];
+		next_code[len] += 1;
+
+		code
+	}).collect()
+}
+
+/// Prefix code for writing the symbols of an alphabet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixCode {
+	alphabet_size: usize,
+	lengths: Vec<usize>,
+	/// canonical codes, bit-reversed, because the BitWriter writes the least significant bit first
+	codes: Vec<u16>,
+	/// used symbols, sorted by code length first, symbol second
+	symbols: Vec<usize>,
+}
+
+impl PrefixCode {
+	/// Builds the optimal prefix code for the given symbol frequencies, with codes of up to 15 bits.
+	/// The alphabet size is the length of the histogram.
+	pub fn from_histogram(histogram: &[u32]) -> PrefixCode {
+		PrefixCode::from_histogram_with_max_length(histogram, MAX_CODE_LENGTH)
+	}
+
+	fn from_histogram_with_max_length(histogram: &[u32], max_len: usize) -> PrefixCode {
+		let lengths = code_lengths_from_histogram(histogram, max_len);
+		let codes = canonical_codes(&lengths).iter().zip(&lengths)
+			.map(|(&code, &len)| if len == 0 { 0 } else { code.reverse_bits() >> (16 - len) })
+			.collect();
+		let mut symbols = (0..histogram.len()).filter(|&symbol| histogram[symbol] > 0).collect::<Vec<_>>();
+
+		symbols.sort_by_key(|&symbol| (lengths[symbol], symbol));
+
+		PrefixCode{
+			alphabet_size: histogram.len(),
+			lengths,
+			codes,
+			symbols,
+		}
+	}
+
+	/// Writes the code for symbol.
+	pub fn write_symbol<W: Write>(&self, out: &mut BitWriter<W>, symbol: usize) -> io::Result<()> {
+		out.write_bits(u32::from(self.codes[symbol]), self.lengths[symbol])
+	}
+
+	/// Writes the prefix code itself, as a simple prefix code if it has up to four symbols,
+	/// as a complex prefix code otherwise. This is the format that parse_prefix_code_info() reads.
+	pub fn write<W: Write>(&self, out: &mut BitWriter<W>) -> io::Result<()> {
+		if self.symbols.len() > 4 {
+			return self.write_complex(out);
+		}
+
+		// HSKIP == 1 marks a simple prefix code
+		out.write_bits(1, 2)?;
+
+		let n_sym = cmp::max(self.symbols.len(), 1);
+		let bit_width = 16 - (self.alphabet_size as u16 - 1).leading_zeros() as usize;
+
+		out.write_bits(n_sym as u32 - 1, 2)?;
+
+		if self.symbols.is_empty() {
+			out.write_bits(0, bit_width)?;
+		}
+
+		// the decoder assigns the codes in this order, sorting symbols of the same length
+		for &symbol in &self.symbols {
+			out.write_bits(symbol as u32, bit_width)?;
+		}
+
+		if n_sym == 4 {
+			// tree-select, lengths 1, 2, 3, 3 rather than 2, 2, 2, 2
+			out.write_bit(self.lengths[self.symbols[0]] == 1)?;
+		}
+
+		Ok(())
+	}
+
+	fn write_complex<W: Write>(&self, out: &mut BitWriter<W>) -> io::Result<()> {
+		let tokens = code_length_tokens(&self.lengths);
+		let mut histogram = [0u32; 18];
+
+		for &(token, _) in &tokens {
+			histogram[token] += 1;
+		}
+
+		let code_length_code = PrefixCode::from_histogram_with_max_length(&histogram, MAX_CODE_LENGTH_CODE_LENGTH);
+		let mut stored_lengths = code_length_code.lengths.clone();
 
+		// a single code length symbol takes zero bits, all 18 lengths are stored then
+		let n_stored = match code_length_code.symbols.len() {
+			1 => {
+				stored_lengths[code_length_code.symbols[0]] = 1;
 
+				CODE_LENGTH_CODE_ORDER.len()
+			},
+			_ => CODE_LENGTH_CODE_ORDER.iter().rposition(|&symbol| stored_lengths[symbol] > 0).unwrap_or(0) + 1,
+		};
+
+		let h_skip = match (stored_lengths[CODE_LENGTH_CODE_ORDER[0]], stored_lengths[CODE_LENGTH_CODE_ORDER[1]], stored_lengths[CODE_LENGTH_CODE_ORDER[2]]) {
+			(0, 0, 0) => 3,
+			(0, 0, _) => 2,
+			_ => 0,
+		};
+
+		out.write_bits(h_skip as u32, 2)?;
+
+		for &symbol in &CODE_LENGTH_CODE_ORDER[h_skip..n_stored] {
+			let (bits, len) = CODE_LENGTH_CODE_LENGTH_CODES[stored_lengths[symbol]];
+
+			out.write_bits(bits, len)?;
+		}
+
+		for &(token, extra) in &tokens {
+			code_length_code.write_symbol(out, token)?;
+
+			match token {
+				16 => out.write_bits(extra, 2)?,
+				17 => out.write_bits(extra, 3)?,
+				_ => {},
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Run-length encodes code lengths as (symbol, extra bits) of the code length alphabet,
+/// where 16 repeats the previous non-zero length, and 17 repeats zeros.
+/// Trailing zeros are left out, the decoder stops once the code is complete.
+fn code_length_tokens(lengths: &[usize]) -> Vec<(usize, u32)> {
+	let len = lengths.iter().rposition(|&len| len > 0).map_or(0, |i| i + 1);
+	let mut tokens = Vec::new();
+	let mut previous = INITIAL_REPEATED_CODE_LENGTH;
+	let mut i = 0;
+
+	while i < len {
+		let value = lengths[i];
+		let reps = lengths[i..len].iter().take_while(|&&len| len == value).count();
+
+		if value == 0 {
+			push_repeated_zeros(&mut tokens, reps);
+		} else {
+			push_repeated_code_length(&mut tokens, previous, value, reps);
+			previous = value;
+		}
+
+		i += reps;
+	}
+
+	tokens
+}
+
+fn push_repeated_code_length(tokens: &mut Vec<(usize, u32)>, previous: usize, value: usize, mut reps: usize) {
+	if previous != value {
+		tokens.push((value, 0));
+		reps -= 1;
+	}
+
+	if reps == 7 {
+		tokens.push((value, 0));
+		reps -= 1;
+	}
+
+	if reps < 3 {
+		for _ in 0..reps {
+			tokens.push((value, 0));
+		}
+	} else {
+		// successive repeat codes multiply, so the least significant digit goes last
+		let start = tokens.len();
+		reps -= 3;
+
+		loop {
+			tokens.push((16, (reps & 3) as u32));
+			reps >>= 2;
+
+			if reps == 0 {
+				break;
+			}
+
+			reps -= 1;
+		}
+
+		tokens[start..].reverse();
+	}
+}
+
+fn push_repeated_zeros(tokens: &mut Vec<(usize, u32)>, mut reps: usize) {
+	if reps == 11 {
+		tokens.push((0, 0));
+		reps -= 1;
+	}
+
+	if reps < 3 {
+		for _ in 0..reps {
+			tokens.push((0, 0));
+		}
+	} else {
+		let start = tokens.len();
+		reps -= 3;
+
+		loop {
+			tokens.push((17, (reps & 7) as u32));
+			reps >>= 3;
+
+			if reps == 0 {
+				break;
+			}
+
+			reps -= 1;
+		}
+
+		tokens[start..].reverse();
+	}
+}
+
+mod tests {
+	#[test]
+	fn should_build_optimal_code_lengths() {
+		use super::*;
+
+		assert_eq!(vec![0, 0, 0], code_lengths_from_histogram(&[0, 7, 0], 15));
+		assert_eq!(vec![1, 0, 1], code_lengths_from_histogram(&[1, 0, 1000], 15));
+		assert_eq!(vec![3, 3, 2, 1], code_lengths_from_histogram(&[1, 1, 2, 4], 15));
+		assert_eq!(vec![2, 2, 2, 2], code_lengths_from_histogram(&[1, 1, 2, 4], 2));
+		assert_eq!(vec![2, 2, 2, 3, 3], code_lengths_from_histogram(&[10, 10, 10, 1, 1], 15));
+	}
+
+	#[test]
+	fn should_limit_code_lengths() {
+		use super::*;
+
+		// Fibonacci frequencies make for the deepest trees
+		let mut histogram = vec![1u32, 1];
+		while histogram.len() < 30 {
+			let n = histogram.len();
+			histogram.push(histogram[n - 1] + histogram[n - 2]);
+		}
+
+		let mut previous_cost = usize::MAX;
+
+		for max_len in 5..MAX_CODE_LENGTH + 1 {
+			let lengths = code_lengths_from_histogram(&histogram, max_len);
+			let kraft_sum = lengths.iter().map(|&len| 1 << (max_len - len)).sum::<usize>();
+			let cost = lengths.iter().zip(&histogram).map(|(&len, &count)| len * count as usize).sum::<usize>();
+
+			assert_eq!(max_len, *lengths.iter().max().unwrap());
+			assert_eq!(1 << max_len, kraft_sum);
+			assert!(cost < previous_cost);
+
+			previous_cost = cost;
+		}
+	}
+
+	#[test]
+	fn should_match_the_cost_of_huffman_codes_within_the_limit() {
+		use super::*;
+		use std::cmp::Reverse;
+		use std::collections::BinaryHeap;
+
+		let mut random = ::testing::random(0x9e37_79b9_7f4a_7c15);
+
+		for _ in 0..100 {
+			// frequencies of similar magnitude keep the Huffman codes within the limit
+			let histogram = (0..random(300) + 2).map(|_| if random(4) == 0 { 0 } else { random(1000) as u32 + 16 }).collect::<Vec<_>>();
+			let lengths = code_lengths_from_histogram(&histogram, MAX_CODE_LENGTH);
+			let cost = lengths.iter().zip(&histogram).map(|(&len, &count)| len as u64 * u64::from(count)).sum::<u64>();
+
+			// the cost of a Huffman code is the sum of the weights of its inner nodes
+			let mut heap = histogram.iter().filter(|&&count| count > 0).map(|&count| Reverse(u64::from(count))).collect::<BinaryHeap<_>>();
+			let mut huffman_cost = 0;
+
+			while heap.len() > 1 {
+				let (Reverse(a), Reverse(b)) = (heap.pop().unwrap(), heap.pop().unwrap());
+
+				huffman_cost += a + b;
+				heap.push(Reverse(a + b));
+			}
+
+			assert_eq!(huffman_cost, cost);
+		}
+	}
+
+	#[test]
+	fn should_assign_the_codes_the_decoder_expects() {
+		use super::*;
+		use ::bitreader::BitReader;
+
+		let lengths = vec![3, 0, 2, 3, 4, 4, 2, 3];
+		let tree = codes_from_lengths(&lengths);
+		let codes = canonical_codes(&lengths);
+
+		for symbol in 0..lengths.len() {
+			if lengths[symbol] == 0 {
+				continue;
+			}
+
+			let mut bw = BitWriter::new(Vec::new());
+			bw.write_bits(u32::from(codes[symbol].reverse_bits() >> (16 - lengths[symbol])), lengths[symbol]).unwrap();
+			bw.align().unwrap();
+
+			let mut br = BitReader::new(&bw.get_ref()[..]);
+
+			assert_eq!(Ok(Some(symbol as u16)), tree.lookup_symbol(&mut br));
+		}
+	}
+
+	#[test]
+	fn should_run_length_encode_code_lengths() {
+		use super::*;
+
+		let lengths = [8, 8, 8, 8, 0, 0, 0, 0, 0, 0, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 0, 0];
+
+		assert_eq!(vec![(16, 1), (17, 3), (3, 0), (16, 0), (16, 2)], code_length_tokens(&lengths));
+	}
+
+	#[test]
+	fn should_write_prefix_codes_that_the_decoder_reads() {
+		use super::*;
+		use ::Decompressor;
+
+		let mut histograms = vec![
+			vec![0; 256],
+			vec![0, 0, 5, 0],
+			vec![3, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+			vec![1, 0, 2, 3, 0, 0],
+			vec![0, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+			vec![1, 2, 4, 8],
+			vec![1; 704],
+			(0..704).map(|i| if i % 3 == 0 { 0 } else { i as u32 % 17 }).collect(),
+			(0..256).map(|i| if (64..80).contains(&i) { 1 << (i % 11) } else { 0 }).collect(),
+			// lots of repeated code lengths, separated by runs of zeros
+			(0..704).map(|i| if (i / 40) % 2 == 0 { 100 } else { 0 }).collect(),
+		];
+
+		// Fibonacci frequencies, for codes of length 15
+		let mut fibonacci = vec![1u32, 1];
+		while fibonacci.len() < 40 {
+			let n = fibonacci.len();
+			fibonacci.push(fibonacci[n - 1] + fibonacci[n - 2]);
+		}
+		histograms.push(fibonacci);
+
+		for histogram in histograms {
+			let code = PrefixCode::from_histogram(&histogram);
+			let mut bw = BitWriter::new(Vec::new());
+
+			code.write(&mut bw).unwrap();
+
+			for (symbol, &count) in histogram.iter().enumerate() {
+				if count > 0 {
+					code.write_symbol(&mut bw, symbol).unwrap();
+				}
+			}
+
+			bw.align().unwrap();
+
+			let mut decompressor = Decompressor::new(&bw.get_ref()[..]);
+			let tree = decompressor.parse_prefix_code_info(histogram.len()).unwrap().0;
+
+			for (symbol, &count) in histogram.iter().enumerate() {
+				if count > 0 {
+					assert_eq!(Ok(Some(symbol as u16)), tree.lookup_symbol(&mut decompressor.in_stream), "{:?}", histogram);
+				}
+			}
+		}
+	}
+}
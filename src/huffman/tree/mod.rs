@@ -47,6 +47,11 @@ impl Tree {
 		}
 	}
 
+	/// Returns the parts that from_raw_data() takes to rebuild this tree.
+	pub fn raw_data(&self) -> (&[Option<Symbol>], usize, Option<Symbol>) {
+		(&self.buf, self.len, self.last_symbol)
+	}
+
 	pub fn insert(&mut self, code: &[bool], symbol: Symbol) {
 		self.len += 1;
 		self.last_symbol = Some(symbol);
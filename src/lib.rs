@@ -4,25 +4,77 @@
 //! This allows a consumer to wrap a Brotli-compressed Stream into a Decompressor,
 //! using the familiar methods provided by the Read trait for processing
 //! the uncompressed stream.
+//!
+//! In the other direction, a Compressor wraps a Write, and compresses everything
+//! that is written to it into a Brotli stream.
 
 /// bitreader wraps a Read to provide bit-oriented read access to a stream.
 mod bitreader;
+/// bitwriter wraps a Write to provide bit-oriented write access to a stream.
+mod bitwriter;
+/// huffman builds prefix codes, decode trees from code lengths, and code lengths from symbol frequencies.
 mod huffman;
 /// ringbuffer provides a data structure RingBuffer that uses a single, fixed-size buffer as if it were connected end-to-end.
 /// This structure lends itself easily to buffering data streams.
 mod ringbuffer;
+/// snapshot provides the building blocks for serializing the state of a Decompressor.
+mod snapshot;
+/// seekable provides SeekableDecompressor, which seeks within the decompressed stream by means of snapshots.
+mod seekable;
+pub use ::seekable::SeekableDecompressor;
+/// writer provides DecompressorWriter, which decompresses a stream that is pushed into it through Write.
+mod writer;
+pub use ::writer::DecompressorWriter;
+/// compressor provides Compressor, which encodes a brotli stream from the data that is written to it.
+mod compressor;
+pub use ::compressor::{ compress, BinaryTree, Compressor, HashChain, MatchFinder, SingleHash };
+/// stored provides StoredWriter, which writes a brotli stream of uncompressed meta-blocks.
+mod stored;
+pub use ::stored::StoredWriter;
+/// sha256 computes SHA-256 digests, which identify compression dictionaries.
+mod sha256;
+/// dcb provides DcbDecoder, which decodes dictionary-compressed brotli streams.
+mod dcb;
+pub use ::dcb::{ DcbDecoder, DictionaryResolver };
+/// woff2 converts WOFF2 web fonts into the TrueType or OpenType fonts they were encoded from.
+mod woff2;
+pub use ::woff2::{ woff2_to_sfnt, Woff2Error };
+/// crc32 computes CRC-32 checksums, which protect the chunks of framed files.
+mod crc32;
+/// framed provides FramedWriter and FramedReader, for files of independently compressed, checksummed chunks.
+mod framed;
+pub use ::framed::{ ChunkInfo, FramedError, FramedReader, FramedWriter };
+/// parallel provides ParallelDecoder, which decodes independent brotli streams on multiple threads.
+mod parallel;
+pub use ::parallel::ParallelDecoder;
+/// visitor provides CommandVisitor, which receives the commands of a stream while it is decoded.
+mod visitor;
+pub use ::visitor::{ BlockCategory, BlockSwitchEvent, CommandVisitor, CopyEvent, DictionaryWordEvent, InsertEvent };
+/// search finds a pattern in a brotli stream by following its commands, without rescanning copies.
+mod search;
+pub use ::search::search;
+
+/// structure records the header fields of a brotli stream, with the bit offsets they have been read from.
+mod structure;
+pub use ::structure::{ BlockTypesInfo, ContextMapInfo, Field, MetaBlockInfo, PrefixCodeInfo, StreamStructure };
 
 
 mod dictionary;
 use ::dictionary::{ BROTLI_DICTIONARY_OFFSETS_BY_LENGTH, BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH, BROTLI_DICTIONARY };
+pub use ::dictionary::{ DictionaryMatch, Matcher, SharedDictionary, SharedDictionaryError };
 mod lookuptable;
 use ::lookuptable::{ LUT_0, LUT_1, LUT_2, INSERT_LENGTHS_AND_COPY_LENGTHS };
 mod transformation;
 use ::transformation::transformation;
+/// testing provides helpers for the unit tests of the other modules.
+#[cfg(test)]
+mod testing;
 
 use ::bitreader::{ BitReader, BitReaderError };
 use ::huffman::tree::Tree;
 use ::ringbuffer::RingBuffer;
+use ::snapshot::{ SnapshotError, SnapshotReader, SnapshotWriter };
+use ::visitor::NoVisitor;
 
 use std::collections::VecDeque;
 use std::cmp;
@@ -30,7 +82,7 @@ use std::error::Error;
 use std::fmt;
 use std::fmt::{ Display, Formatter };
 use std::io;
-use std::io::Read;
+use std::io::{ BufRead, Read };
 
 type WBits = u8;
 type CodeLengths = Vec<usize>;
@@ -133,6 +185,25 @@ impl Header {
 			window_size: None,
 		}
 	}
+
+	fn write_snapshot(&self, w: &mut SnapshotWriter) {
+		w.write_option(&self.wbits, |w, wbits| w.write_u8(*wbits));
+	}
+
+	fn read_snapshot(r: &mut SnapshotReader) -> Result<Header, SnapshotError> {
+		let mut header = Header::new();
+
+		if let Some(wbits) = r.read_option(|r| r.read_u8())? {
+			if !(10..=24).contains(&wbits) {
+				return Err(SnapshotError::InvalidValue);
+			}
+
+			header.wbits = Some(wbits);
+			header.window_size = Some((1 << wbits) - 16);
+		}
+
+		Ok(header)
+	}
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -196,6 +267,81 @@ impl MetaBlock {
 			distance: None,
 		}
 	}
+
+	fn write_snapshot(&self, w: &mut SnapshotWriter) {
+		self.header.write_snapshot(w);
+		w.write_usize(self.count_output);
+		w.write_option(&self.context_modes_literals, |w, context_modes| {
+			w.write_usize(context_modes.len());
+
+			for context_mode in context_modes {
+				w.write_u16(*context_mode);
+			}
+		});
+		w.write_option(&self.prefix_tree_block_types_literals, |w, tree| w.write_tree(tree));
+		w.write_option(&self.prefix_tree_block_counts_literals, |w, tree| w.write_tree(tree));
+		w.write_option(&self.prefix_tree_block_types_insert_and_copy_lengths, |w, tree| w.write_tree(tree));
+		w.write_option(&self.prefix_tree_block_counts_insert_and_copy_lengths, |w, tree| w.write_tree(tree));
+		w.write_option(&self.prefix_tree_block_types_distances, |w, tree| w.write_tree(tree));
+		w.write_option(&self.prefix_tree_block_counts_distances, |w, tree| w.write_tree(tree));
+		w.write_option(&self.prefix_trees_literals, |w, trees| w.write_trees(trees));
+		w.write_option(&self.prefix_trees_insert_and_copy_lengths, |w, trees| w.write_trees(trees));
+		w.write_option(&self.prefix_trees_distances, |w, trees| w.write_trees(trees));
+		w.write_u16(self.btype_l);
+		w.write_u16(self.btype_l_prev);
+		w.write_option(&self.blen_l, |w, blen| w.write_u32(*blen));
+		w.write_u16(self.btype_i);
+		w.write_u16(self.btype_i_prev);
+		w.write_option(&self.blen_i, |w, blen| w.write_u32(*blen));
+		w.write_u16(self.btype_d);
+		w.write_u16(self.btype_d_prev);
+		w.write_option(&self.blen_d, |w, blen| w.write_u32(*blen));
+		w.write_option(&self.insert_and_copy_length, |w, symbol| w.write_u16(*symbol));
+		w.write_option(&self.insert_length, |w, insert_length| w.write_u32(*insert_length));
+		w.write_option(&self.copy_length, |w, copy_length| w.write_u32(*copy_length));
+		w.write_option(&self.distance_code, |w, distance_code| w.write_u32(*distance_code));
+		w.write_option(&self.distance, |w, distance| w.write_u32(*distance));
+	}
+
+	fn read_snapshot(r: &mut SnapshotReader) -> Result<MetaBlock, SnapshotError> {
+		Ok(MetaBlock{
+			header: MetaBlockHeader::read_snapshot(r)?,
+			count_output: r.read_usize()?,
+			context_modes_literals: r.read_option(|r| {
+				let len = r.read_count(2)?;
+				let mut context_modes = Vec::with_capacity(len);
+
+				for _ in 0..len {
+					context_modes.push(r.read_u16()?);
+				}
+
+				Ok(context_modes)
+			})?,
+			prefix_tree_block_types_literals: r.read_option(|r| r.read_tree())?,
+			prefix_tree_block_counts_literals: r.read_option(|r| r.read_tree())?,
+			prefix_tree_block_types_insert_and_copy_lengths: r.read_option(|r| r.read_tree())?,
+			prefix_tree_block_counts_insert_and_copy_lengths: r.read_option(|r| r.read_tree())?,
+			prefix_tree_block_types_distances: r.read_option(|r| r.read_tree())?,
+			prefix_tree_block_counts_distances: r.read_option(|r| r.read_tree())?,
+			prefix_trees_literals: r.read_option(|r| r.read_trees())?,
+			prefix_trees_insert_and_copy_lengths: r.read_option(|r| r.read_trees())?,
+			prefix_trees_distances: r.read_option(|r| r.read_trees())?,
+			btype_l: r.read_u16()?,
+			btype_l_prev: r.read_u16()?,
+			blen_l: r.read_option(|r| r.read_u32())?,
+			btype_i: r.read_u16()?,
+			btype_i_prev: r.read_u16()?,
+			blen_i: r.read_option(|r| r.read_u32())?,
+			btype_d: r.read_u16()?,
+			btype_d_prev: r.read_u16()?,
+			blen_d: r.read_option(|r| r.read_u32())?,
+			insert_and_copy_length: r.read_option(|r| r.read_u16())?,
+			insert_length: r.read_option(|r| r.read_u32())?,
+			copy_length: r.read_option(|r| r.read_u32())?,
+			distance_code: r.read_option(|r| r.read_u32())?,
+			distance: r.read_option(|r| r.read_u32())?,
+		})
+	}
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -239,6 +385,46 @@ impl MetaBlockHeader {
 			c_map_l: None,
 		}
 	}
+
+	fn write_snapshot(&self, w: &mut SnapshotWriter) {
+		w.write_option(&self.is_last, |w, is_last| w.write_bool(*is_last));
+		w.write_option(&self.is_last_empty, |w, is_last_empty| w.write_bool(*is_last_empty));
+		w.write_option(&self.m_nibbles, |w, m_nibbles| w.write_u8(*m_nibbles));
+		w.write_option(&self.m_skip_bytes, |w, m_skip_bytes| w.write_u8(*m_skip_bytes));
+		w.write_option(&self.m_skip_len, |w, m_skip_len| w.write_u32(*m_skip_len));
+		w.write_option(&self.m_len, |w, m_len| w.write_u32(*m_len));
+		w.write_option(&self.is_uncompressed, |w, is_uncompressed| w.write_bool(*is_uncompressed));
+		w.write_option(&self.n_bltypes_l, |w, n_bltypes| w.write_u16(*n_bltypes));
+		w.write_option(&self.n_bltypes_i, |w, n_bltypes| w.write_u16(*n_bltypes));
+		w.write_option(&self.n_bltypes_d, |w, n_bltypes| w.write_u16(*n_bltypes));
+		w.write_option(&self.n_postfix, |w, n_postfix| w.write_u8(*n_postfix));
+		w.write_option(&self.n_direct, |w, n_direct| w.write_u8(*n_direct));
+		w.write_option(&self.n_trees_l, |w, n_trees| w.write_u16(*n_trees));
+		w.write_option(&self.n_trees_d, |w, n_trees| w.write_u16(*n_trees));
+		w.write_option(&self.c_map_d, |w, c_map| w.write_bytes(c_map));
+		w.write_option(&self.c_map_l, |w, c_map| w.write_bytes(c_map));
+	}
+
+	fn read_snapshot(r: &mut SnapshotReader) -> Result<MetaBlockHeader, SnapshotError> {
+		Ok(MetaBlockHeader{
+			is_last: r.read_option(|r| r.read_bool())?,
+			is_last_empty: r.read_option(|r| r.read_bool())?,
+			m_nibbles: r.read_option(|r| r.read_u8())?,
+			m_skip_bytes: r.read_option(|r| r.read_u8())?,
+			m_skip_len: r.read_option(|r| r.read_u32())?,
+			m_len: r.read_option(|r| r.read_u32())?,
+			is_uncompressed: r.read_option(|r| r.read_bool())?,
+			n_bltypes_l: r.read_option(|r| r.read_u16())?,
+			n_bltypes_i: r.read_option(|r| r.read_u16())?,
+			n_bltypes_d: r.read_option(|r| r.read_u16())?,
+			n_postfix: r.read_option(|r| r.read_u8())?,
+			n_direct: r.read_option(|r| r.read_u8())?,
+			n_trees_l: r.read_option(|r| r.read_u16())?,
+			n_trees_d: r.read_option(|r| r.read_u16())?,
+			c_map_d: r.read_option(|r| r.read_bytes())?,
+			c_map_l: r.read_option(|r| r.read_bytes())?,
+		})
+	}
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -282,6 +468,7 @@ enum State {
 	InsertAndCopyLength(InsertAndCopyLength),
 	InsertLengthAndCopyLength(InsertLengthAndCopyLength),
 	InsertLiterals(Literals),
+	InsertLiteralsEnd,
 	DistanceCode(DistanceCode),
 	Distance(Distance),
 	CopyLiterals(Literals),
@@ -293,17 +480,23 @@ enum State {
 #[derive(Debug, Clone, PartialEq)]
 enum DecompressorError {
 	CodeLengthsChecksum,
+	DictionaryHashMismatch,
 	ExpectedEndOfStream,
 	ExceededExpectedBytes,
 	InvalidBlockCountCode,
 	InvalidBlockSwitchCommandCode,
+	InvalidCheckpointIndex,
+	InvalidDcbHeader,
 	InvalidLengthInStaticDictionary,
 	InvalidMSkipLen,
+	InvalidSnapshot,
 	InvalidSymbol,
 	InvalidTransformId,
 	InvalidNonPositiveDistance,
 	LessThanTwoNonZeroCodeLengths,
+	LostInputStream,
 	NoCodeLength,
+	NonResumableState,
 	NonZeroFillBit,
 	NonZeroReservedBit,
 	NonZeroTrailerBit,
@@ -316,6 +509,8 @@ enum DecompressorError {
 	RingBufferError,
 	RunLengthExceededSizeOfContextMap,
 	UnexpectedEOF,
+	UnknownDictionary,
+	UnsupportedSnapshotVersion,
 }
 
 impl Display for DecompressorError {
@@ -329,17 +524,23 @@ impl Error for DecompressorError {
 	fn description(&self) -> &str {
 		match *self {
 			DecompressorError::CodeLengthsChecksum => "Code length check sum did not add up in complex prefix code",
+			DecompressorError::DictionaryHashMismatch => "Dictionary did not match the SHA-256 hash in the dcb header",
 			DecompressorError::ExpectedEndOfStream => "Expected end-of-stream, but stream did not end",
 			DecompressorError::ExceededExpectedBytes => "More uncompressed bytes than expected in meta-block",
 			DecompressorError::InvalidBlockCountCode => "Encountered invalid value for block count code",
 			DecompressorError::InvalidBlockSwitchCommandCode => "Encountered invalid value for block switch command code",
+			DecompressorError::InvalidCheckpointIndex => "Checkpoint index is corrupted or was not created by SeekableDecompressor::save_index()",
+			DecompressorError::InvalidDcbHeader => "Stream did not start with the magic number of a dcb header",
 			DecompressorError::InvalidLengthInStaticDictionary => "Encountered invalid length in reference to static dictionary",
 			DecompressorError::InvalidMSkipLen => "Most significant byte of MSKIPLEN was zero",
+			DecompressorError::InvalidSnapshot => "Snapshot is corrupted or was not created by Decompressor::snapshot()",
 			DecompressorError::InvalidSymbol => "Encountered invalid symbol in prefix code",
 			DecompressorError::InvalidTransformId => "Encountered invalid transform id in reference to static dictionary",
 			DecompressorError::InvalidNonPositiveDistance => "Encountered invalid non-positive distance",
 			DecompressorError::LessThanTwoNonZeroCodeLengths => "Encountered invalid complex prefix code with less than two non-zero codelengths",
+			DecompressorError::LostInputStream => "Input stream was lost after failing to restore from a checkpoint",
 			DecompressorError::NoCodeLength => "Encountered invalid complex prefix code with all zero codelengths",
+			DecompressorError::NonResumableState => "Decompressor is not in a state that can be captured in a snapshot",
 			DecompressorError::NonZeroFillBit => "Enocuntered non-zero fill bit",
 			DecompressorError::NonZeroReservedBit => "Enocuntered non-zero reserved bit",
 			DecompressorError::NonZeroTrailerBit => "Enocuntered non-zero bit trailing the stream",
@@ -352,10 +553,119 @@ impl Error for DecompressorError {
 			DecompressorError::RingBufferError => "Error accessing distance ring buffer",
 			DecompressorError::RunLengthExceededSizeOfContextMap => "Run length excceeded declared length of context map",
 			DecompressorError::UnexpectedEOF => "Encountered unexpected EOF",
+			DecompressorError::UnknownDictionary => "Dictionary of the dcb header could not be resolved",
+			DecompressorError::UnsupportedSnapshotVersion => "Snapshot was created with an unsupported format version",
+		}
+	}
+}
+
+/// Progress of a running decompression, as reported to a callback
+/// registered with Decompressor::set_progress_callback().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+	/// Number of compressed bytes consumed so far, a partially consumed byte counts as a whole byte.
+	pub compressed_bytes: usize,
+	/// Number of decompressed bytes produced so far.
+	pub decompressed_bytes: usize,
+	/// Zero-based number of the meta-block that is currently being decoded.
+	pub meta_block: usize,
+}
+
+/// Serialized state of a Decompressor, as created by Decompressor::snapshot().
+///
+/// The byte representation is versioned and checksummed, and can be stored
+/// in order to continue decompressing later, with Decompressor::from_snapshot().
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+	bytes: Vec<u8>,
+	input_offset: u64,
+}
+
+impl Snapshot {
+	// magic, version, and input offset
+	const PREFIX_LEN: usize = 13;
+	// trailing checksum
+	const SUFFIX_LEN: usize = 4;
+
+	/// Creates a Snapshot from bytes that have previously been obtained from Snapshot::as_bytes().
+	/// Returns an error, if the bytes are not a snapshot, have been corrupted, or were written
+	/// by an incompatible version of this crate.
+	pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Snapshot> {
+		let invalid = |e: DecompressorError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+		if bytes.len() < Snapshot::PREFIX_LEN + Snapshot::SUFFIX_LEN || &bytes[0..4] != snapshot::MAGIC {
+			return Err(invalid(DecompressorError::InvalidSnapshot));
+		}
+
+		if bytes[4] != snapshot::VERSION {
+			return Err(invalid(DecompressorError::UnsupportedSnapshotVersion));
 		}
+
+		let mut r = SnapshotReader::new(&bytes[5..Snapshot::PREFIX_LEN]);
+		let input_offset = r.read_u64().map_err(|_| invalid(DecompressorError::InvalidSnapshot))?;
+		let mut r = SnapshotReader::new(&bytes[bytes.len() - Snapshot::SUFFIX_LEN..]);
+		let checksum = r.read_u32().map_err(|_| invalid(DecompressorError::InvalidSnapshot))?;
+
+		if checksum != snapshot::checksum(&bytes[..bytes.len() - Snapshot::SUFFIX_LEN]) {
+			return Err(invalid(DecompressorError::InvalidSnapshot));
+		}
+
+		Ok(Snapshot{
+			bytes,
+			input_offset,
+		})
+	}
+
+	/// Returns the byte representation of the snapshot.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	/// Returns the offset in bytes into the compressed stream, where the reader
+	/// that is passed to Decompressor::from_snapshot() needs to be positioned.
+	pub fn input_offset(&self) -> u64 {
+		self.input_offset
+	}
+
+	fn body(&self) -> &[u8] {
+		&self.bytes[Snapshot::PREFIX_LEN..self.bytes.len() - Snapshot::SUFFIX_LEN]
 	}
 }
 
+struct ProgressCallback {
+	callback: Box<dyn FnMut(&Progress) + Send>,
+	interval: Option<usize>,
+	next_report: usize,
+}
+
+impl fmt::Debug for ProgressCallback {
+	fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+		fmt.debug_struct("ProgressCallback")
+			.field("interval", &self.interval)
+			.field("next_report", &self.next_report)
+			.finish()
+	}
+}
+
+/// Decoder state that a single step of decompress() can modify before it fails,
+/// see Decompressor::rewind_step().
+#[derive(Debug, Clone, PartialEq)]
+struct StepStart {
+	count_meta_blocks: usize,
+	field_bit_pos: usize,
+	btype_l: NBltypes,
+	btype_l_prev: NBltypes,
+	blen_l: Option<BLen>,
+	btype_i: NBltypes,
+	btype_i_prev: NBltypes,
+	blen_i: Option<BLen>,
+	btype_d: NBltypes,
+	btype_d_prev: NBltypes,
+	blen_d: Option<BLen>,
+	literal_buf: RingBuffer<Literal>,
+	distance_buf: RingBuffer<Distance>,
+}
+
 /// Wraps an input stream and provides methods for decompressing.
 ///
 /// # Examples
@@ -391,6 +701,31 @@ pub struct Decompressor<R: Read> {
 	/// at the beginning of the stream, and then
 	/// lives until the end
 	distance_buf: RingBuffer<Distance>,
+	count_meta_blocks: usize,
+	progress: Option<ProgressCallback>,
+	/// makes decompress() return at the end of every meta-block,
+	/// setting paused until the next call continues decoding
+	pause_at_meta_block_end: bool,
+	paused: bool,
+	/// makes decompress() drop the decoded bytes that do not fit into its output slice,
+	/// instead of buffering them, see visit_commands()
+	discard_output: bool,
+	/// makes every step of decompress() revertible with rewind_step(),
+	/// for decompressing from input that arrives in pieces
+	rewindable: bool,
+	step_start: Option<StepStart>,
+	/// bytes that precede the output in the LZ77 window, see with_prefix_dictionary()
+	prefix_dictionary: Vec<u8>,
+	/// replaces the static dictionary, see with_shared_dictionary()
+	shared_dictionary: Option<SharedDictionary>,
+	/// input bit offset at which the current insert or copy has started
+	command_bit_pos: usize,
+	/// block switches that wait to be passed to the CommandVisitor, once the step that read them has succeeded
+	block_switches: Vec<BlockSwitchEvent>,
+	/// input bit offset at which the value of the current state has started, see record_structure()
+	field_bit_pos: usize,
+	/// the structure of the stream so far, if it is being recorded
+	structure: Option<StreamStructure>,
 }
 
 impl<R: Read> Decompressor<R> {
@@ -406,6 +741,369 @@ impl<R: Read> Decompressor<R> {
 			count_output: 0,
 			literal_buf: RingBuffer::from_vec(vec![0, 0]),
 			distance_buf: RingBuffer::from_vec(vec![4, 11, 15, 16]),
+			count_meta_blocks: 0,
+			progress: None,
+			pause_at_meta_block_end: false,
+			paused: false,
+			discard_output: false,
+			rewindable: false,
+			step_start: None,
+			prefix_dictionary: Vec::new(),
+			shared_dictionary: None,
+			command_bit_pos: 0,
+			block_switches: Vec::new(),
+			field_bit_pos: 0,
+			structure: None,
+		}
+	}
+
+	/// Creates Decompressor from Read, for a brotli stream that has been compressed against a custom
+	/// dictionary. The dictionary is attached as the start of the LZ77 window, so that backward
+	/// references can reach into it as if it preceded the output, and the static dictionary
+	/// references begin beyond it. Only the last 2^WBITS - 16 bytes of the dictionary are reachable,
+	/// and they drop out of the window as the output grows. The dictionary is not part of the output,
+	/// and does not serve as context for the first literals.
+	///
+	/// # Examples
+	/// ```
+	/// use std::io::Read;
+	/// use brotli::Decompressor;
+	///
+	/// // a single copy of 5 bytes from 6 bytes back, which reaches into the dictionary
+	/// let brotli_stream = [0x1b, 0x04, 0x00, 0x00, 0x20, 0x00, 0x62, 0x90, 0x38, 0x00];
+	///
+	/// let mut decompressed = String::new();
+	/// let _ = Decompressor::with_prefix_dictionary(&brotli_stream[..], b"Hello, World!").read_to_string(&mut decompressed);
+	///
+	/// assert_eq!("World", decompressed);
+	/// ```
+	pub fn with_prefix_dictionary(r: R, dictionary: &[u8]) -> Decompressor<R> {
+		let mut decompressor = Decompressor::new(r);
+
+		decompressor.prefix_dictionary = dictionary.to_vec();
+
+		decompressor
+	}
+
+	/// Creates Decompressor from Read, for a brotli stream that has been compressed against a shared
+	/// dictionary in the format of RFC 9841. Its LZ77 dictionary serves as a prefix dictionary, see
+	/// with_prefix_dictionary(), and references beyond the LZ77 window resolve against its word lists
+	/// and transforms instead of the static dictionary. See SharedDictionary for an example.
+	pub fn with_shared_dictionary(r: R, dictionary: &SharedDictionary) -> Decompressor<R> {
+		let mut decompressor = Decompressor::with_prefix_dictionary(r, dictionary.prefix());
+
+		decompressor.shared_dictionary = Some(dictionary.clone());
+
+		decompressor
+	}
+
+	/// Returns the largest distance of a backward reference into the output or the prefix dictionary,
+	/// larger distances refer to the static dictionary.
+	fn max_backward_distance(&self) -> usize {
+		cmp::min(self.header.window_size.unwrap(), self.count_output + self.prefix_dictionary.len())
+	}
+
+	/// Registers a callback that receives the current Progress at the end of every meta-block.
+	/// If interval is Some(n), the callback is additionally called whenever at least n more bytes
+	/// have been decompressed since the last report.
+	/// Replaces any previously registered callback.
+	pub fn set_progress_callback<F>(&mut self, interval: Option<usize>, callback: F)
+			where F: FnMut(&Progress) + Send + 'static {
+		self.progress = Some(ProgressCallback{
+			callback: Box::new(callback),
+			interval,
+			next_report: self.count_output + interval.unwrap_or(0),
+		});
+	}
+
+	/// Reads decompressed bytes into buf, like read(), and passes the commands that produce them, and the block
+	/// switches before them, to visitor. A command is passed on once, when it is decoded, so bytes that are buffered
+	/// internally, because they have not fit into buf, are returned by later calls without any command.
+	pub fn read_visiting<V: CommandVisitor>(&mut self, buf: &mut [u8], visitor: &mut V) -> io::Result<usize> {
+		if self.buf.is_empty() {
+			match self.decompress_with(buf, visitor) {
+				Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+				Ok(l) => Ok(l),
+			}
+		} else {
+			let l = cmp::min(self.buf.len(), buf.len());
+
+			for item in buf.iter_mut().take(l) {
+				*item = self.buf.pop_front().unwrap();
+			}
+
+			Ok(l)
+		}
+	}
+
+	/// Decodes the rest of the stream, and passes its commands and block switches to visitor,
+	/// instead of producing output. Discards decompressed bytes that are still buffered internally.
+	pub fn visit_commands<V: CommandVisitor>(&mut self, visitor: &mut V) -> io::Result<()> {
+		self.buf.clear();
+		self.discard_output = true;
+
+		while self.state != State::StreamEnd {
+			// with an empty output slice, decompress_with() returns after every command
+			if let Err(e) = self.decompress_with(&mut [], visitor) {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Makes the Decompressor record the structure of the stream while decoding it: the stream header,
+	/// and for every meta-block the fields of its header, its prefix codes and context maps,
+	/// each with the bit offsets in the input that it has been read from, see structure().
+	/// Has no effect once decompression has begun.
+	///
+	/// # Examples
+	/// ```
+	/// use std::io::Read;
+	/// use brotli::{ compress, Decompressor };
+	///
+	/// let compressed = compress(b"structure, structure, structure", 5);
+	/// let mut decompressor = Decompressor::new(&compressed[..]);
+	///
+	/// decompressor.record_structure();
+	/// decompressor.read_to_end(&mut Vec::new()).unwrap();
+	///
+	/// let structure = decompressor.structure().unwrap();
+	/// let m_len = structure.meta_blocks.iter()
+	///     .filter_map(|meta_block| meta_block.m_len.as_ref())
+	///     .map(|m_len| m_len.value)
+	///     .sum::<u32>();
+	///
+	/// assert_eq!(31, m_len);
+	/// ```
+	pub fn record_structure(&mut self) {
+		if self.state == State::StreamBegin && self.structure.is_none() {
+			self.structure = Some(StreamStructure::new());
+		}
+	}
+
+	/// Returns the structure of the stream as far as it has been decoded,
+	/// or None if it is not being recorded, see record_structure().
+	pub fn structure(&self) -> Option<&StreamStructure> {
+		self.structure.as_ref()
+	}
+
+	/// Passes the meta-block that is being decoded to f, if the structure of the stream is being recorded.
+	/// Fields are assigned rather than appended, so that a step that is repeated after rewind_step()
+	/// records the same values again.
+	fn record<F: FnOnce(&mut MetaBlockInfo)>(&mut self, f: F) {
+		if let Some(meta_block) = self.structure.as_mut().and_then(|structure| structure.meta_blocks.last_mut()) {
+			f(meta_block);
+		}
+	}
+
+	/// Captures the complete state of the Decompressor, so that decompression can later be continued
+	/// from this point with Decompressor::from_snapshot(), even in a different process.
+	/// Snapshots can be taken before the first, and after every successful call to read().
+	/// Returns an error if a previous call to read() failed.
+	pub fn snapshot(&self) -> io::Result<Snapshot> {
+		let mut w = SnapshotWriter::new();
+		let (global_bit_pos, bit_pos, current_byte) = self.in_stream.position();
+		let input_offset = ((global_bit_pos + 7) >> 3) as u64;
+
+		for &byte in snapshot::MAGIC {
+			w.write_u8(byte);
+		}
+		w.write_u8(snapshot::VERSION);
+		w.write_u64(input_offset);
+
+		w.write_usize(global_bit_pos);
+		w.write_u8(bit_pos);
+		w.write_option(&current_byte, |w, byte| w.write_u8(*byte));
+
+		match self.state {
+			State::StreamBegin => w.write_u8(0),
+			State::DataMetaBlockBegin => w.write_u8(1),
+			State::DistanceCode(distance_code) => {
+				w.write_u8(2);
+				w.write_u32(distance_code);
+			},
+			State::DataMetaBlockEnd => w.write_u8(3),
+			State::MetaBlockEnd => w.write_u8(4),
+			State::StreamEnd => w.write_u8(5),
+			State::InsertLiteralsEnd => w.write_u8(6),
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidInput, DecompressorError::NonResumableState.to_string())),
+		}
+
+		w.write_usize(self.count_output);
+		w.write_usize(self.count_meta_blocks);
+		self.header.write_snapshot(&mut w);
+		self.meta_block.write_snapshot(&mut w);
+		w.write_ring_buffer(&self.literal_buf, |w, literal| w.write_u8(*literal));
+		w.write_ring_buffer(&self.distance_buf, |w, distance| w.write_u32(*distance));
+		w.write_option(&self.output_window, |w, window| w.write_ring_buffer(window, |w, literal| w.write_u8(*literal)));
+		w.write_bytes(&self.buf.iter().cloned().collect::<Vec<_>>());
+		w.write_bytes(&self.prefix_dictionary);
+		w.write_option(&self.shared_dictionary, |w, dictionary| w.write_bytes(dictionary.as_bytes()));
+
+		let mut bytes = w.into_inner();
+		let checksum = snapshot::checksum(&bytes);
+		bytes.extend_from_slice(&checksum.to_le_bytes());
+
+		Ok(Snapshot{
+			bytes,
+			input_offset,
+		})
+	}
+
+	/// Creates a Decompressor that continues from the state captured in snapshot.
+	/// The reader r needs to be positioned at snapshot.input_offset() within the compressed stream.
+	/// A progress callback is not part of the snapshot, and needs to be registered again.
+	pub fn from_snapshot(r: R, snapshot: &Snapshot) -> io::Result<Decompressor<R>> {
+		match Decompressor::restore(r, snapshot.body()) {
+			Ok(decompressor) => Ok(decompressor),
+			Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, DecompressorError::InvalidSnapshot.to_string())),
+		}
+	}
+
+	fn restore(r: R, body: &[u8]) -> Result<Decompressor<R>, SnapshotError> {
+		let mut s = SnapshotReader::new(body);
+		let global_bit_pos = s.read_usize()?;
+		let bit_pos = s.read_u8()?;
+		let current_byte = s.read_option(|s| s.read_u8())?;
+
+		if bit_pos as usize != global_bit_pos % 8 || current_byte.is_some() != (bit_pos > 0) {
+			return Err(SnapshotError::InvalidValue);
+		}
+
+		let state = match s.read_u8()? {
+			0 => State::StreamBegin,
+			1 => State::DataMetaBlockBegin,
+			2 => State::DistanceCode(s.read_u32()?),
+			3 => State::DataMetaBlockEnd,
+			4 => State::MetaBlockEnd,
+			5 => State::StreamEnd,
+			6 => State::InsertLiteralsEnd,
+			_ => return Err(SnapshotError::InvalidValue),
+		};
+
+		let count_output = s.read_usize()?;
+		let count_meta_blocks = s.read_usize()?;
+		let header = Header::read_snapshot(&mut s)?;
+		let meta_block = MetaBlock::read_snapshot(&mut s)?;
+		let literal_buf = s.read_ring_buffer(1, |s| s.read_u8())?;
+		let distance_buf = s.read_ring_buffer(4, |s| s.read_u32())?;
+		let output_window = s.read_option(|s| s.read_ring_buffer(1, |s| s.read_u8()))?;
+		let pending = s.read_bytes()?;
+		let prefix_dictionary = s.read_bytes()?;
+		let shared_dictionary = match s.read_option(|s| s.read_bytes())? {
+			Some(bytes) => Some(SharedDictionary::from_bytes(&bytes).map_err(|_| SnapshotError::InvalidValue)?),
+			None => None,
+		};
+
+		if !s.is_at_end()
+			|| literal_buf.raw_parts().0.len() != 2
+			|| distance_buf.raw_parts().0.len() != 4
+			|| header.window_size != output_window.as_ref().map(|window| window.raw_parts().2) {
+			return Err(SnapshotError::InvalidValue);
+		}
+
+		Ok(Decompressor{
+			in_stream: BitReader::resume(r, global_bit_pos, bit_pos, current_byte),
+			header,
+			buf: pending.into_iter().collect(),
+			output_window,
+			state,
+			meta_block,
+			count_output,
+			literal_buf,
+			distance_buf,
+			count_meta_blocks,
+			progress: None,
+			pause_at_meta_block_end: false,
+			paused: false,
+			discard_output: false,
+			rewindable: false,
+			step_start: None,
+			prefix_dictionary,
+			shared_dictionary,
+			command_bit_pos: global_bit_pos,
+			block_switches: Vec::new(),
+			field_bit_pos: global_bit_pos,
+			structure: None,
+		})
+	}
+
+	/// Saves the parts of the decoder state that the next step of decompress() may modify
+	/// before it fails, if the decompressor is rewindable.
+	fn begin_step(&mut self) {
+		if self.rewindable {
+			self.in_stream.mark();
+			self.step_start = Some(StepStart{
+				count_meta_blocks: self.count_meta_blocks,
+				field_bit_pos: self.field_bit_pos,
+				btype_l: self.meta_block.btype_l,
+				btype_l_prev: self.meta_block.btype_l_prev,
+				blen_l: self.meta_block.blen_l,
+				btype_i: self.meta_block.btype_i,
+				btype_i_prev: self.meta_block.btype_i_prev,
+				blen_i: self.meta_block.blen_i,
+				btype_d: self.meta_block.btype_d,
+				btype_d_prev: self.meta_block.btype_d_prev,
+				blen_d: self.meta_block.blen_d,
+				literal_buf: self.literal_buf.clone(),
+				distance_buf: self.distance_buf.clone(),
+			});
+		}
+	}
+
+	/// Reverts the step of decompress() that has last failed, so that it can be tried again
+	/// once more input is available. Steps that produce output never fail after producing it,
+	/// the state only changes once a step has succeeded, and all other changes are either
+	/// repeated identically by the next attempt or undone here.
+	fn rewind_step(&mut self) {
+		if let Some(start) = self.step_start.take() {
+			self.in_stream.reset();
+			self.count_meta_blocks = start.count_meta_blocks;
+			self.field_bit_pos = start.field_bit_pos;
+			self.meta_block.btype_l = start.btype_l;
+			self.meta_block.btype_l_prev = start.btype_l_prev;
+			self.meta_block.blen_l = start.blen_l;
+			self.meta_block.btype_i = start.btype_i;
+			self.meta_block.btype_i_prev = start.btype_i_prev;
+			self.meta_block.blen_i = start.blen_i;
+			self.meta_block.btype_d = start.btype_d;
+			self.meta_block.btype_d_prev = start.btype_d_prev;
+			self.meta_block.blen_d = start.blen_d;
+			self.literal_buf = start.literal_buf;
+			self.distance_buf = start.distance_buf;
+			self.block_switches.clear();
+		}
+	}
+
+	fn into_inner(self) -> R {
+		self.in_stream.into_inner()
+	}
+
+	fn report_progress(&mut self) {
+		if let Some(ref mut p) = self.progress {
+			let progress = Progress{
+				compressed_bytes: (self.in_stream.global_bit_pos() + 7) >> 3,
+				decompressed_bytes: self.count_output,
+				meta_block: self.count_meta_blocks.saturating_sub(1),
+			};
+
+			if let Some(interval) = p.interval {
+				p.next_report = progress.decompressed_bytes + interval;
+			}
+
+			(p.callback)(&progress);
+		}
+	}
+
+	fn report_progress_interval(&mut self) {
+		let due = match self.progress {
+			Some(ProgressCallback{ interval: Some(_), next_report, .. }) => self.count_output >= next_report,
+			_ => false,
+		};
+
+		if due {
+			self.report_progress();
 		}
 	}
 
@@ -594,7 +1292,7 @@ impl<R: Read> Decompressor<R> {
 		}
 	}
 
-	fn parse_simple_prefix_code(&mut self, alphabet_size: usize) -> Result<HuffmanCodes, DecompressorError> {
+	fn parse_simple_prefix_code(&mut self, bit_offset: usize, alphabet_size: usize) -> Result<(HuffmanCodes, PrefixCodeInfo), DecompressorError> {
 		let bit_width = 16 - (alphabet_size as u16 - 1).leading_zeros() as usize;
 
 		// println!("Alphabet Size = {:?}", alphabet_size);
@@ -661,11 +1359,21 @@ impl<R: Read> Decompressor<R> {
 		// println!("Sorted Symbols = {:?}", symbols);
 		// println!("Code Lengths = {:?}", code_lengths);
 
-		Ok(huffman::codes_from_lengths_and_symbols(&code_lengths, &symbols))
+		let prefix_code = huffman::codes_from_lengths_and_symbols(&code_lengths, &symbols);
+
+		Ok((prefix_code, PrefixCodeInfo{
+			bits: bit_offset..self.in_stream.global_bit_pos(),
+			alphabet_size,
+			h_skip: None,
+			code_length_code_lengths: None,
+			tree_select,
+			symbols,
+			code_lengths,
+		}))
 	}
 
-	fn parse_complex_prefix_code(&mut self, h_skip: u8, alphabet_size: usize)
-			-> Result<HuffmanCodes, DecompressorError> {
+	fn parse_complex_prefix_code(&mut self, bit_offset: usize, h_skip: u8, alphabet_size: usize)
+			-> Result<(HuffmanCodes, PrefixCodeInfo), DecompressorError> {
 		let mut symbols = vec![1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
 		let bit_lengths_code = &self.header.bit_lengths_code;
 
@@ -871,10 +1579,25 @@ impl<R: Read> Decompressor<R> {
 			return Err(DecompressorError::LessThanTwoNonZeroCodeLengths);
 		}
 
-		Ok(huffman::codes_from_lengths(&actual_code_lengths))
+		let prefix_code = huffman::codes_from_lengths(&actual_code_lengths);
+		let mut symbols = (0..alphabet_size as u16).filter(|&symbol| actual_code_lengths[symbol as usize] > 0).collect::<Vec<_>>();
+
+		symbols.sort_by_key(|&symbol| actual_code_lengths[symbol as usize]);
+
+		Ok((prefix_code, PrefixCodeInfo{
+			bits: bit_offset..self.in_stream.global_bit_pos(),
+			alphabet_size,
+			h_skip: Some(h_skip),
+			code_length_code_lengths: Some(code_lengths),
+			tree_select: None,
+			code_lengths: symbols.iter().map(|&symbol| actual_code_lengths[symbol as usize]).collect(),
+			symbols,
+		}))
 	}
 
-	fn parse_prefix_code(&mut self, alphabet_size: usize) -> Result<HuffmanCodes, DecompressorError> {
+	/// Parses a prefix code, and describes it for the structure of the stream, see record_structure().
+	fn parse_prefix_code_info(&mut self, alphabet_size: usize) -> Result<(HuffmanCodes, PrefixCodeInfo), DecompressorError> {
+		let bit_offset = self.in_stream.global_bit_pos();
 		let prefix_code_kind = match self.parse_prefix_code_kind() {
 			Ok(kind) => kind,
 			Err(e) => return Err(e),
@@ -883,75 +1606,81 @@ impl<R: Read> Decompressor<R> {
 		// println!("Prefix Code Kind = {:?}", prefix_code_kind);
 
 		match prefix_code_kind {
-			PrefixCodeKind::Complex(h_skip) => self.parse_complex_prefix_code(h_skip, alphabet_size),
-			PrefixCodeKind::Simple => self.parse_simple_prefix_code(alphabet_size),
+			PrefixCodeKind::Complex(h_skip) => self.parse_complex_prefix_code(bit_offset, h_skip, alphabet_size),
+			PrefixCodeKind::Simple => self.parse_simple_prefix_code(bit_offset, alphabet_size),
 		}
 	}
 
 	fn parse_prefix_code_block_types_literals(&mut self) -> Result<State, DecompressorError> {
 		let alphabet_size = (self.meta_block.header.n_bltypes_l.unwrap() as usize) + 2;
 
-		Ok(State::PrefixCodeBlockTypesLiterals(
-			match self.parse_prefix_code(alphabet_size) {
-					Ok(prefix_code) => prefix_code,
-					Err(e) => return Err(e),
-			}
-		))
+		let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+		self.record(|meta_block| if let Some(ref mut block_types) = meta_block.literal_block_types {
+			block_types.block_type_code = Some(info);
+		});
+
+		Ok(State::PrefixCodeBlockTypesLiterals(prefix_code))
 	}
 
 	fn parse_prefix_code_block_counts_literals(&mut self) -> Result<State, DecompressorError> {
 		let alphabet_size = 26;
 
-		Ok(State::PrefixCodeBlockCountsLiterals(
-			match self.parse_prefix_code(alphabet_size) {
-					Ok(prefix_code) => prefix_code,
-					Err(e) => return Err(e),
-			}
-		))
+		let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+		self.record(|meta_block| if let Some(ref mut block_types) = meta_block.literal_block_types {
+			block_types.block_count_code = Some(info);
+		});
+
+		Ok(State::PrefixCodeBlockCountsLiterals(prefix_code))
 	}
 
 	fn parse_prefix_code_block_types_insert_and_copy_lengths(&mut self) -> Result<State, DecompressorError> {
 		let alphabet_size = (self.meta_block.header.n_bltypes_i.unwrap() as usize) + 2;
 
-		Ok(State::PrefixCodeBlockTypesInsertAndCopyLengths(
-			match self.parse_prefix_code(alphabet_size) {
-					Ok(prefix_code) => prefix_code,
-					Err(e) => return Err(e),
-			}
-		))
+		let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+		self.record(|meta_block| if let Some(ref mut block_types) = meta_block.insert_and_copy_length_block_types {
+			block_types.block_type_code = Some(info);
+		});
+
+		Ok(State::PrefixCodeBlockTypesInsertAndCopyLengths(prefix_code))
 	}
 
 	fn parse_prefix_code_block_counts_insert_and_copy_lengths(&mut self) -> Result<State, DecompressorError> {
 		let alphabet_size = 26;
 
-		Ok(State::PrefixCodeBlockCountsInsertAndCopyLengths(
-			match self.parse_prefix_code(alphabet_size) {
-					Ok(prefix_code) => prefix_code,
-					Err(e) => return Err(e),
-			}
-		))
+		let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+		self.record(|meta_block| if let Some(ref mut block_types) = meta_block.insert_and_copy_length_block_types {
+			block_types.block_count_code = Some(info);
+		});
+
+		Ok(State::PrefixCodeBlockCountsInsertAndCopyLengths(prefix_code))
 	}
 
 	fn parse_prefix_code_block_types_distances(&mut self) -> Result<State, DecompressorError> {
 		let alphabet_size = (self.meta_block.header.n_bltypes_d.unwrap() as usize) + 2;
 
-		Ok(State::PrefixCodeBlockTypesDistances(
-			match self.parse_prefix_code(alphabet_size) {
-					Ok(prefix_code) => prefix_code,
-					Err(e) => return Err(e),
-			}
-		))
+		let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+		self.record(|meta_block| if let Some(ref mut block_types) = meta_block.distance_block_types {
+			block_types.block_type_code = Some(info);
+		});
+
+		Ok(State::PrefixCodeBlockTypesDistances(prefix_code))
 	}
 
 	fn parse_prefix_code_block_counts_distances(&mut self) -> Result<State, DecompressorError> {
 		let alphabet_size = 26;
 
-		Ok(State::PrefixCodeBlockCountsDistances(
-			match self.parse_prefix_code(alphabet_size) {
-					Ok(prefix_code) => prefix_code,
-					Err(e) => return Err(e),
-			}
-		))
+		let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+		self.record(|meta_block| if let Some(ref mut block_types) = meta_block.distance_block_types {
+			block_types.block_count_code = Some(info);
+		});
+
+		Ok(State::PrefixCodeBlockCountsDistances(prefix_code))
 	}
 
 	fn parse_block_count(&mut self, prefix_code: &HuffmanCodes) -> Result<BLen, DecompressorError> {
@@ -1021,13 +1750,17 @@ impl<R: Read> Decompressor<R> {
 		// println!("NTREESL = {:?}", n_trees_l);
 		// println!("alphabet_size = {:?}", alphabet_size);
 
+		let mut infos = Vec::with_capacity(n_trees_l);
+
 		for _ in 0..n_trees_l {
-			prefix_codes.push(match self.parse_prefix_code(alphabet_size) {
-				Ok(prefix_code) => prefix_code,
-				Err(e) => return Err(e),
-			});
+			let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+			prefix_codes.push(prefix_code);
+			infos.push(info);
 		}
 
+		self.record(|meta_block| meta_block.literal_prefix_codes = infos);
+
 		Ok(State::PrefixCodesLiterals(prefix_codes))
 	}
 
@@ -1039,13 +1772,17 @@ impl<R: Read> Decompressor<R> {
 		// println!("NBLTYPESI = {:?}", n_bltypes_i);
 		// println!("alphabet_size = {:?}", alphabet_size);
 
+		let mut infos = Vec::with_capacity(n_bltypes_i);
+
 		for _ in 0..n_bltypes_i {
-			prefix_codes.push(match self.parse_prefix_code(alphabet_size) {
-				Ok(prefix_code) => prefix_code,
-				Err(e) => return Err(e),
-			});
+			let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+			prefix_codes.push(prefix_code);
+			infos.push(info);
 		}
 
+		self.record(|meta_block| meta_block.insert_and_copy_length_prefix_codes = infos);
+
 		Ok(State::PrefixCodesInsertAndCopyLengths(prefix_codes))
 	}
 
@@ -1057,17 +1794,23 @@ impl<R: Read> Decompressor<R> {
 		// println!("NDIRECT = {:?}", self.meta_block.header.n_direct.unwrap());
 		// println!("NPOSTFIX = {:?}", self.meta_block.header.n_postfix.unwrap());
 
+		let mut infos = Vec::with_capacity(n_trees_d);
+
 		for _ in 0..n_trees_d {
-			prefix_codes.push(match self.parse_prefix_code(alphabet_size) {
-				Ok(prefix_code) => prefix_code,
-				Err(e) => return Err(e),
-			});
+			let (prefix_code, info) = self.parse_prefix_code_info(alphabet_size)?;
+
+			prefix_codes.push(prefix_code);
+			infos.push(info);
 		}
 
+		self.record(|meta_block| meta_block.distance_prefix_codes = infos);
+
 		Ok(State::PrefixCodesDistances(prefix_codes))
 	}
 
-	fn parse_context_map(&mut self, n_trees: NTrees, len: usize) -> Result<ContextMap, DecompressorError> {
+	/// Parses a context map, and describes it for the structure of the stream, see record_structure().
+	fn parse_context_map_info(&mut self, n_trees: NTrees, len: usize) -> Result<ContextMapInfo, DecompressorError> {
+		let bit_offset = self.in_stream.global_bit_pos();
 		let rlemax = match self.in_stream.read_bit() {
 			Ok(false) => 0u16,
 			Ok(true) => match self.in_stream.read_u16_from_n_bits(4) {
@@ -1083,10 +1826,8 @@ impl<R: Read> Decompressor<R> {
 
 		// debug(&format!("Alphabet Size = {:?}", alphabet_size));
 
-		let prefix_tree = match self.parse_prefix_code(alphabet_size) {
-			Ok(v) => v,
-			Err(e) => return Err(e),
-		};
+		let rle_max_bits = bit_offset..self.in_stream.global_bit_pos();
+		let (prefix_tree, prefix_code_info) = self.parse_prefix_code_info(alphabet_size)?;
 
 		// !("Prefix Tree Context Map = {:?}", prefix_tree);
 
@@ -1105,14 +1846,13 @@ impl<R: Read> Decompressor<R> {
 
 					// debug(&format!("repeat = {:?}", repeat));
 
-					for _ in 0..repeat {
-						c_map.push(0);
-						c_pushed += 1;
+					c_pushed += repeat as usize;
 
-						if c_pushed > len {
-							return Err(DecompressorError::RunLengthExceededSizeOfContextMap);
-						}
+					if c_pushed > len {
+						return Err(DecompressorError::RunLengthExceededSizeOfContextMap);
 					}
+
+					c_map.resize(c_pushed, 0);
 				},
 				Ok(Some(context_id)) => {
 					c_map.push(if context_id == 0 { 0 } else { (context_id - rlemax) as u8 });
@@ -1128,6 +1868,7 @@ impl<R: Read> Decompressor<R> {
 			// debug(&format!("{:?}", (c_pushed, len)));
 		}
 
+		let imtf_bit_offset = self.in_stream.global_bit_pos();
 		let imtf_bit = match self.in_stream.read_bit() {
 			Ok(v) => v,
 			Err(_) => return Err(DecompressorError::UnexpectedEOF),
@@ -1140,25 +1881,35 @@ impl<R: Read> Decompressor<R> {
 			Self::inverse_move_to_front_transform(&mut c_map);
 		}
 
-		Ok(c_map)
+		Ok(ContextMapInfo{
+			bits: bit_offset..self.in_stream.global_bit_pos(),
+			rle_max: Field{ bits: rle_max_bits, value: rlemax },
+			prefix_code: prefix_code_info,
+			inverse_move_to_front: Field{ bits: imtf_bit_offset..self.in_stream.global_bit_pos(), value: imtf_bit },
+			map: c_map,
+		})
 	}
 
 	fn parse_context_map_literals(&mut self) -> Result<State, DecompressorError> {
 		let n_trees = self.meta_block.header.n_trees_l.unwrap();
 		let len = self.meta_block.header.n_bltypes_l.unwrap() as usize * 64;
-		match self.parse_context_map(n_trees, len) {
-			Ok(c_map_l) => Ok(State::ContextMapLiterals(c_map_l)),
-			Err(e) => Err(e),
-		}
+		let info = self.parse_context_map_info(n_trees, len)?;
+		let c_map_l = info.map.clone();
+
+		self.record(|meta_block| meta_block.literal_context_map = Some(info));
+
+		Ok(State::ContextMapLiterals(c_map_l))
 	}
 
 	fn parse_context_map_distances(&mut self) -> Result<State, DecompressorError> {
 		let n_trees = self.meta_block.header.n_trees_d.unwrap();
 		let len = (self.meta_block.header.n_bltypes_d.unwrap() * 4) as usize;
-		match self.parse_context_map(n_trees, len) {
-			Ok(c_map_d) => Ok(State::ContextMapDistances(c_map_d)),
-			Err(e) => Err(e),
-		}
+		let info = self.parse_context_map_info(n_trees, len)?;
+		let c_map_d = info.map.clone();
+
+		self.record(|meta_block| meta_block.distance_context_map = Some(info));
+
+		Ok(State::ContextMapDistances(c_map_d))
 	}
 
 	fn inverse_move_to_front_transform(v: &mut[u8]) {
@@ -1200,6 +1951,8 @@ impl<R: Read> Decompressor<R> {
 
 		// debug(&format!("btype_i = {:?}", btype));
 
+		self.command_bit_pos = self.in_stream.global_bit_pos();
+
 		match self.meta_block.prefix_trees_insert_and_copy_lengths.as_ref().unwrap()[btype].lookup_symbol(&mut self.in_stream) {
 			Ok(Some(symbol)) => Ok(State::InsertAndCopyLength(symbol)),
 			Ok(None) => Err(DecompressorError::ParseErrorInsertAndCopyLength),
@@ -1223,7 +1976,8 @@ impl<R: Read> Decompressor<R> {
 		Ok(State::InsertLengthAndCopyLength((insert_length, copy_length)))
 	}
 
-	fn parse_block_switch_command(&mut self, prefix_tree_types: HuffmanCodes, btype: NBltypes, btype_prev: NBltypes, n_bltypes: NBltypes, prefix_tree_counts: HuffmanCodes) -> Result<BlockSwitch, DecompressorError> {
+	fn parse_block_switch_command(&mut self, category: BlockCategory, prefix_tree_types: HuffmanCodes, btype: NBltypes, btype_prev: NBltypes, n_bltypes: NBltypes, prefix_tree_counts: HuffmanCodes) -> Result<BlockSwitch, DecompressorError> {
+		let start = self.in_stream.global_bit_pos();
 		let block_type_code = match prefix_tree_types.lookup_symbol(&mut self.in_stream) {
 			Ok(Some(block_type_code)) => block_type_code,
 			Ok(None) => return Err(DecompressorError::InvalidBlockSwitchCommandCode),
@@ -1246,6 +2000,13 @@ impl<R: Read> Decompressor<R> {
 
 		// debug(&format!("block count = {:?}", block_count));
 
+		self.block_switches.push(BlockSwitchEvent{
+			bits: start..self.in_stream.global_bit_pos(),
+			category,
+			block_type,
+			block_count,
+		});
+
 		Ok((block_type, block_count))
 	}
 
@@ -1257,7 +2018,7 @@ impl<R: Read> Decompressor<R> {
 
 		let prefix_tree_counts = self.meta_block.prefix_tree_block_counts_literals.as_ref().unwrap().clone();
 
-		self.parse_block_switch_command(prefix_tree_types, btype, btype_prev, n_bltypes, prefix_tree_counts)
+		self.parse_block_switch_command(BlockCategory::Literals, prefix_tree_types, btype, btype_prev, n_bltypes, prefix_tree_counts)
 	}
 
 	fn parse_block_switch_command_insert_and_copy_lengths(&mut self) -> Result<BlockSwitch, DecompressorError> {
@@ -1269,7 +2030,7 @@ impl<R: Read> Decompressor<R> {
 
 		let prefix_tree_counts = self.meta_block.prefix_tree_block_counts_insert_and_copy_lengths.as_ref().unwrap().clone();
 
-		self.parse_block_switch_command(prefix_tree_types, btype, btype_prev, n_bltypes, prefix_tree_counts)
+		self.parse_block_switch_command(BlockCategory::InsertAndCopyLengths, prefix_tree_types, btype, btype_prev, n_bltypes, prefix_tree_counts)
 	}
 
 	fn parse_block_switch_command_distances(&mut self) -> Result<BlockSwitch, DecompressorError> {
@@ -1280,7 +2041,7 @@ impl<R: Read> Decompressor<R> {
 
 		let prefix_tree_counts = self.meta_block.prefix_tree_block_counts_distances.as_ref().unwrap().clone();
 
-		self.parse_block_switch_command(prefix_tree_types, btype, btype_prev, n_bltypes, prefix_tree_counts)
+		self.parse_block_switch_command(BlockCategory::Distances, prefix_tree_types, btype, btype_prev, n_bltypes, prefix_tree_counts)
 	}
 
 	fn parse_insert_literals(&mut self) -> Result<State, DecompressorError> {
@@ -1309,36 +2070,7 @@ impl<R: Read> Decompressor<R> {
 
 			// println!("btype = {:?}", btype);
 
-			let context_mode = self.meta_block.context_modes_literals.as_ref().unwrap()[btype];
-
-			// debug(&format!("[p1, p2] = {:?}", self.literal_buf));
-			// debug(&format!("Context Mode = {:?}", context_mode));
-
-			let cid = match context_mode {
-				0 => {
-					let p1 = *self.literal_buf.nth(0).unwrap() as usize;
-
-					p1 & 0x3f
-				},
-				1 => {
-					let p1 = *self.literal_buf.nth(0).unwrap() as usize;
-
-					p1 >> 2
-				},
-				2 => {
-					let p1 = *self.literal_buf.nth(0).unwrap() as usize;
-					let p2 = *self.literal_buf.nth(1).unwrap() as usize;
-
-					LUT_0[p1] | LUT_1[p2]
-				},
-				3 => {
-					let p1 = *self.literal_buf.nth(0).unwrap() as usize;
-					let p2 = *self.literal_buf.nth(1).unwrap() as usize;
-
-					(LUT_2[p1] << 3) | LUT_2[p2]
-				},
-				_ => unreachable!(), // confirmed unreachable, context_mode is always read from two bits
-			};
+			let cid = self.literal_context_id(btype);
 
 			// println!("(btype, cid) = {:?}", (btype, cid));
 
@@ -1369,7 +2101,11 @@ impl<R: Read> Decompressor<R> {
 
 		// check for implicit distance 0 ([…]"as indicated by the insert-and-copy length code")
 		match self.meta_block.distance {
-			Some(0) => return Ok(State::DistanceCode(0)),
+			Some(0) => {
+				self.command_bit_pos = self.in_stream.global_bit_pos();
+
+				return Ok(State::DistanceCode(0));
+			},
 			Some(_) => unreachable!(), // confirmed unreachable, code sets meta_block.distance to None|Some(0) before this portion
 			None => {}
 		}
@@ -1388,6 +2124,8 @@ impl<R: Read> Decompressor<R> {
 			Some(ref mut blen_d) => *blen_d -= 1,
 		}
 
+		self.command_bit_pos = self.in_stream.global_bit_pos();
+
 		let cid = match self.meta_block.copy_length {
 			Some(0...1) => unreachable!(), // confirmed unreachable, copy_length will always be >= 2
 			Some(c @ 2...4) => c - 2,
@@ -1473,20 +2211,69 @@ impl<R: Read> Decompressor<R> {
 
 		// println!("(dc, db, d) = {:?}", (self.meta_block.distance_code, self.distance_buf.clone(), distance));
 
-		if self.meta_block.distance_code.unwrap() > 0 && distance as usize <= cmp::min(self.header.window_size.unwrap(), self.count_output) {
+		if self.meta_block.distance_code.unwrap() > 0 && distance as usize <= self.max_backward_distance() {
 			self.distance_buf.push(distance);
 		}
 
 		Ok(State::Distance(distance))
 	}
 
+	/// Returns the context id of the next literal, from the context mode of literal block type btype
+	/// and the last two literals.
+	fn literal_context_id(&self, btype: usize) -> usize {
+		let context_mode = self.meta_block.context_modes_literals.as_ref().unwrap()[btype];
+
+		// debug(&format!("[p1, p2] = {:?}", self.literal_buf));
+		// debug(&format!("Context Mode = {:?}", context_mode));
+
+		match context_mode {
+			0 => {
+				let p1 = *self.literal_buf.nth(0).unwrap() as usize;
+
+				p1 & 0x3f
+			},
+			1 => {
+				let p1 = *self.literal_buf.nth(0).unwrap() as usize;
+
+				p1 >> 2
+			},
+			2 => {
+				let p1 = *self.literal_buf.nth(0).unwrap() as usize;
+				let p2 = *self.literal_buf.nth(1).unwrap() as usize;
+
+				LUT_0[p1] | LUT_1[p2]
+			},
+			3 => {
+				let p1 = *self.literal_buf.nth(0).unwrap() as usize;
+				let p2 = *self.literal_buf.nth(1).unwrap() as usize;
+
+				(LUT_2[p1] << 3) | LUT_2[p2]
+			},
+			_ => unreachable!(), // confirmed unreachable, context_mode is always read from two bits
+		}
+	}
+
+	/// Returns the number of the dictionary within the shared dictionary, or None for the static dictionary,
+	/// the word id and the transform id of the dictionary word that copy_literals() has resolved address to.
+	fn dictionary_word_ids(&self, copy_length: usize, address: usize) -> (Option<usize>, usize, usize) {
+		match self.shared_dictionary {
+			Some(ref dictionary) => match dictionary.locate(copy_length, address, self.literal_context_id(self.meta_block.btype_l as usize)) {
+				Some((i, word_id, transform_id)) => (Some(i), word_id, transform_id),
+				None => unreachable!(), // confirmed unreachable, copy_literals() has resolved the same address
+			},
+			None => {
+				let size_bits = BROTLI_DICTIONARY_SIZE_BITS_BY_LENGTH[copy_length];
+
+				(None, address & ((1 << size_bits) - 1), address >> size_bits)
+			},
+		}
+	}
+
 	fn copy_literals(&mut self) -> Result<State, DecompressorError> {
-		let window_size = self.header.window_size.unwrap();
 		let copy_length = self.meta_block.copy_length.unwrap() as usize;
-		let count_output = self.count_output;
 		let distance = self.meta_block.distance.unwrap() as usize;
+		let max_allowed_distance = self.max_backward_distance();
 		let output_window = self.output_window.as_ref().unwrap();
-		let max_allowed_distance = cmp::min(count_output, window_size);
 
 		if distance <= max_allowed_distance {
 			let mut window = vec![0; copy_length];
@@ -1503,8 +2290,17 @@ impl<R: Read> Decompressor<R> {
 			}
 
 			Ok(State::CopyLiterals(window))
+		} else if let Some(ref dictionary) = self.shared_dictionary {
+			let address = distance - max_allowed_distance - 1;
+			let context_id = self.literal_context_id(self.meta_block.btype_l as usize);
+
+			match dictionary.word(copy_length, address, context_id) {
+				Some(word) => Ok(State::CopyLiterals(word)),
+				None if !(4..=31).contains(&copy_length) => Err(DecompressorError::InvalidLengthInStaticDictionary),
+				None => Err(DecompressorError::InvalidTransformId),
+			}
 		} else {
-			if copy_length < 4 || copy_length > 24 {
+			if !(4..=24).contains(&copy_length) {
 				return Err(DecompressorError::InvalidLengthInStaticDictionary);
 			}
 
@@ -1543,9 +2339,25 @@ impl<R: Read> Decompressor<R> {
 
 
 	fn decompress(&mut self, buf: &mut [u8]) -> Result<usize, DecompressorError> {
+		self.decompress_with(buf, &mut NoVisitor)
+	}
+
+	/// Like decompress(), and additionally passes the commands and block switches to visitor.
+	fn decompress_with(&mut self, buf: &mut [u8], visitor: &mut dyn CommandVisitor) -> Result<usize, DecompressorError> {
 		let mut buf_pos = 0;
 
 		loop {
+			for event in self.block_switches.drain(..) {
+				visitor.visit_block_switch(&event);
+			}
+
+			self.begin_step();
+
+			// the bits from which the value of the current state has been read
+			let field_bits = self.field_bit_pos..self.in_stream.global_bit_pos();
+
+			self.field_bit_pos = field_bits.end;
+
 			match self.state.clone() {
 				State::StreamBegin => {
 
@@ -1558,10 +2370,21 @@ impl<R: Read> Decompressor<R> {
 					};
 				},
 				State::WBits(wbits) => {
+					if let Some(ref mut structure) = self.structure {
+						structure.wbits = Some(Field{ bits: field_bits, value: wbits });
+					}
+
 					self.header.wbits = Some(wbits);
 					self.header.window_size = Some((1 << wbits) - 16);
 					self.output_window = Some(RingBuffer::with_capacity(self.header.window_size.unwrap()));
 
+					let window_size = self.header.window_size.unwrap();
+					let reachable = self.prefix_dictionary.len().saturating_sub(window_size);
+
+					for &byte in &self.prefix_dictionary[reachable..] {
+						self.output_window.as_mut().unwrap().push(byte);
+					}
+
 					// println!("(WBITS, Window Size) = {:?}", (wbits, self.header.window_size));
 
 					self.state = State::HeaderEnd;
@@ -1571,6 +2394,17 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::HeaderMetaBlockBegin => {
 					self.meta_block = MetaBlock::new();
+					self.count_meta_blocks += 1;
+
+					let count_meta_blocks = self.count_meta_blocks;
+
+					if let Some(ref mut structure) = self.structure {
+						// a repeated step must not add the meta-block again
+						if structure.meta_blocks.len() < count_meta_blocks {
+							structure.meta_blocks.push(MetaBlockInfo::new(field_bits.end));
+						}
+					}
+
 					self.state = match self.parse_is_last() {
 						Ok(state) => state,
 						Err(e) => return Err(e),
@@ -1578,6 +2412,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::IsLast(true) => {
 					self.meta_block.header.is_last = Some(true);
+					self.record(|meta_block| meta_block.is_last = Some(Field{ bits: field_bits, value: true }));
 
 					// debug(&format!("ISLAST = true"));
 
@@ -1588,6 +2423,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::IsLast(false) => {
 					self.meta_block.header.is_last = Some(false);
+					self.record(|meta_block| meta_block.is_last = Some(Field{ bits: field_bits, value: false }));
 
 					// println!("ISLAST = false");
 
@@ -1598,14 +2434,20 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::IsLastEmpty(true) => {
 					self.meta_block.header.is_last_empty = Some(true);
+					self.record(|meta_block| {
+						meta_block.bits.end = field_bits.end;
+						meta_block.is_last_empty = Some(Field{ bits: field_bits, value: true });
+					});
 
 					// debug(&format!("ISLASTEMPTY = true"));
 
+					self.report_progress();
 
 					self.state = State::StreamEnd;
 				},
 				State::IsLastEmpty(false) => {
 					self.meta_block.header.is_last_empty = Some(false);
+					self.record(|meta_block| meta_block.is_last_empty = Some(Field{ bits: field_bits, value: false }));
 
 					// debug(&format!("ISLASTEMPTY = false"));
 
@@ -1621,9 +2463,12 @@ impl<R: Read> Decompressor<R> {
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
 					}
 
+					self.field_bit_pos = self.in_stream.global_bit_pos();
+
 					// debug(&format!("MNibbles = 0"));
 
 					self.meta_block.header.m_nibbles = Some(0);
+					self.record(|meta_block| meta_block.m_nibbles = Some(Field{ bits: field_bits, value: 0 }));
 
 					self.state = match self.parse_m_skip_bytes() {
 						Ok(state) => state,
@@ -1632,6 +2477,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::MNibbles(m_nibbles) => {
 					self.meta_block.header.m_nibbles = Some(m_nibbles);
+					self.record(|meta_block| meta_block.m_nibbles = Some(Field{ bits: field_bits, value: m_nibbles }));
 
 					// debug(&format!("MNibbles = {:?}", m_nibbles));
 
@@ -1642,6 +2488,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::MSkipBytes(0) => {
 					self.meta_block.header.m_skip_bytes = Some(0);
+					self.record(|meta_block| meta_block.m_skip_bytes = Some(Field{ bits: field_bits, value: 0 }));
 
 					// debug(&format!("MSKIPBYTES = 0"));
 
@@ -1655,6 +2502,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::MSkipBytes(m_skip_bytes) => {
 					self.meta_block.header.m_skip_bytes = Some(m_skip_bytes);
+					self.record(|meta_block| meta_block.m_skip_bytes = Some(Field{ bits: field_bits, value: m_skip_bytes }));
 
 					// debug(&format!("MSKIPBYTES = {:?}", m_skip_bytes));
 
@@ -1665,6 +2513,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::MSkipLen(m_skip_len) => {
 					self.meta_block.header.m_skip_len = Some(m_skip_len);
+					self.record(|meta_block| meta_block.m_skip_len = Some(Field{ bits: field_bits, value: m_skip_len }));
 
 					// debug(&format!("MSKIPLEN = {:?}", m_skip_len));
 
@@ -1674,15 +2523,22 @@ impl<R: Read> Decompressor<R> {
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
 					};
 
+					let metadata_bit_offset = self.in_stream.global_bit_pos();
+
 					match self.in_stream.read_fixed_length_string(m_skip_len as usize) {
 						Ok(_) => {},
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
 					};
 
+					let metadata_bits = metadata_bit_offset..self.in_stream.global_bit_pos();
+
+					self.record(|meta_block| meta_block.data_bits = Some(metadata_bits));
+
 					self.state = State::MetaBlockEnd;
 				},
 				State::MLen(m_len) => {
 					self.meta_block.header.m_len = Some(m_len);
+					self.record(|meta_block| meta_block.m_len = Some(Field{ bits: field_bits, value: m_len }));
 
 					// println!("MLEN = {:?}", m_len);
 
@@ -1700,6 +2556,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::IsUncompressed(true) => {
 					self.meta_block.header.is_uncompressed = Some(true);
+					self.record(|meta_block| meta_block.is_uncompressed = Some(Field{ bits: field_bits, value: true }));
 
 					// println!("UNCOMPRESSED = true");
 
@@ -1709,6 +2566,7 @@ impl<R: Read> Decompressor<R> {
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
 					};
 
+					self.command_bit_pos = self.in_stream.global_bit_pos();
 					self.state = match self.parse_mlen_literals() {
 						Ok(state) => state,
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
@@ -1719,14 +2577,24 @@ impl<R: Read> Decompressor<R> {
 						if buf_pos < buf.len() {
 							buf[buf_pos] = *literal;
 							buf_pos += 1;
-						} else {
-							self.buf.push_front(*literal);
+						} else if !self.discard_output {
+							self.buf.push_back(*literal);
 						}
 						self.output_window.as_mut().unwrap().push(*literal);
 						self.literal_buf.push(*literal);
 						self.count_output += 1;
 					}
 
+					let data_bits = self.command_bit_pos..self.in_stream.global_bit_pos();
+
+					visitor.visit_insert(&InsertEvent{
+						bits: data_bits.clone(),
+						literals: &m_len_literals,
+						uncompressed: true,
+					});
+					self.record(|meta_block| meta_block.data_bits = Some(data_bits));
+					self.report_progress_interval();
+
 					self.state = State::MetaBlockEnd;
 					if buf_pos == buf.len() {
 						return Ok(buf_pos);
@@ -1734,6 +2602,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::IsUncompressed(false) => {
 					self.meta_block.header.is_uncompressed = Some(false);
+					self.record(|meta_block| meta_block.is_uncompressed = Some(Field{ bits: field_bits, value: false }));
 
 					// println!("UNCOMPRESSED = false");
 
@@ -1744,6 +2613,12 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NBltypesL(n_bltypes_l) => {
 					self.meta_block.header.n_bltypes_l = Some(n_bltypes_l);
+					self.record(|meta_block| meta_block.literal_block_types = Some(BlockTypesInfo{
+						n_bltypes: Field{ bits: field_bits, value: n_bltypes_l },
+						block_type_code: None,
+						block_count_code: None,
+						first_block_count: None,
+					}));
 
 					// println!("NBLTYPESL = {:?}", n_bltypes_l);
 
@@ -1781,6 +2656,9 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::FirstBlockCountLiterals(blen) => {
 					self.meta_block.blen_l = Some(blen);
+					self.record(|meta_block| if let Some(ref mut block_types) = meta_block.literal_block_types {
+						block_types.first_block_count = Some(Field{ bits: field_bits, value: blen });
+					});
 
 					// debug(&format!("Block count literals = {:?}", blen));
 
@@ -1791,6 +2669,12 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NBltypesI(n_bltypes_i) => {
 					self.meta_block.header.n_bltypes_i = Some(n_bltypes_i);
+					self.record(|meta_block| meta_block.insert_and_copy_length_block_types = Some(BlockTypesInfo{
+						n_bltypes: Field{ bits: field_bits, value: n_bltypes_i },
+						block_type_code: None,
+						block_count_code: None,
+						first_block_count: None,
+					}));
 
 					// println!("NBLTYPESI = {:?}", n_bltypes_i);
 
@@ -1828,6 +2712,9 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::FirstBlockCountInsertAndCopyLengths(blen) => {
 					self.meta_block.blen_i = Some(blen);
+					self.record(|meta_block| if let Some(ref mut block_types) = meta_block.insert_and_copy_length_block_types {
+						block_types.first_block_count = Some(Field{ bits: field_bits, value: blen });
+					});
 
 					// debug(&format!("Block count insert and copy lengths = {:?}", blen));
 
@@ -1838,6 +2725,12 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NBltypesD(n_bltypes_d) => {
 					self.meta_block.header.n_bltypes_d = Some(n_bltypes_d);
+					self.record(|meta_block| meta_block.distance_block_types = Some(BlockTypesInfo{
+						n_bltypes: Field{ bits: field_bits, value: n_bltypes_d },
+						block_type_code: None,
+						block_count_code: None,
+						first_block_count: None,
+					}));
 
 					// println!("NBLTYPESD = {:?}", n_bltypes_d);
 
@@ -1875,6 +2768,9 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::FirstBlockCountDistances(blen) => {
 					self.meta_block.blen_d = Some(blen);
+					self.record(|meta_block| if let Some(ref mut block_types) = meta_block.distance_block_types {
+						block_types.first_block_count = Some(Field{ bits: field_bits, value: blen });
+					});
 
 					// debug(&format!("Block count distances = {:?}", blen));
 
@@ -1885,6 +2781,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NPostfix(n_postfix) => {
 					self.meta_block.header.n_postfix = Some(n_postfix);
+					self.record(|meta_block| meta_block.n_postfix = Some(Field{ bits: field_bits, value: n_postfix }));
 
 					// debug(&format!("NPOSTFIX = {:?}", n_postfix));
 
@@ -1895,6 +2792,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NDirect(n_direct) => {
 					self.meta_block.header.n_direct = Some(n_direct);
+					self.record(|meta_block| meta_block.n_direct = Some(Field{ bits: field_bits, value: n_direct }));
 
 					// debug(&format!("NDIRECT = {:?}", n_direct));
 
@@ -1904,6 +2802,9 @@ impl<R: Read> Decompressor<R> {
 					};
 				},
 				State::ContextModesLiterals(context_modes) => {
+					let modes = context_modes.iter().map(|&mode| mode as u8).collect();
+
+					self.record(|meta_block| meta_block.context_modes = Some(Field{ bits: field_bits, value: modes }));
 					self.meta_block.context_modes_literals = Some(context_modes);
 
 					// println!("Context Modes Literals = {:?}", self.meta_block.context_modes_literals);
@@ -1915,6 +2816,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NTreesL(n_trees_l) => {
 					self.meta_block.header.n_trees_l = Some(n_trees_l);
+					self.record(|meta_block| meta_block.n_trees_l = Some(Field{ bits: field_bits, value: n_trees_l }));
 					self.meta_block.header.c_map_l = Some(vec![0; 64 * self.meta_block.header.n_bltypes_l.unwrap() as usize]);
 
 					// println!("NTREESL = {:?}", n_trees_l);
@@ -1943,6 +2845,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::NTreesD(n_trees_d) => {
 					self.meta_block.header.n_trees_d = Some(n_trees_d);
+					self.record(|meta_block| meta_block.n_trees_d = Some(Field{ bits: field_bits, value: n_trees_d }));
 					self.meta_block.header.c_map_d = Some(vec![0; 4 * self.meta_block.header.n_bltypes_d.unwrap() as usize]);
 
 					// println!("NTREESD = {:?}", n_trees_d);
@@ -1995,6 +2898,7 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::PrefixCodesDistances(prefix_trees) => {
 					self.meta_block.prefix_trees_distances = Some(prefix_trees);
+					self.record(|meta_block| meta_block.data_bits = Some(field_bits.end..field_bits.end));
 
 					// debug(&format!("Prefix Trees Distances = {:?}", self.meta_block.prefix_trees_distances));
 
@@ -2058,27 +2962,34 @@ impl<R: Read> Decompressor<R> {
 						if buf_pos < buf.len() {
 							buf[buf_pos] = *literal;
 							buf_pos += 1;
-						} else {
-							self.buf.push_front(*literal);
+						} else if !self.discard_output {
+							self.buf.push_back(*literal);
 						}
 						self.output_window.as_mut().unwrap().push(*literal);
 						self.count_output += 1;
 						self.meta_block.count_output += 1;
 					}
 
+					visitor.visit_insert(&InsertEvent{
+						bits: self.command_bit_pos..self.in_stream.global_bit_pos(),
+						literals: &insert_literals,
+						uncompressed: false,
+					});
+					self.report_progress_interval();
+
 					self.state = if self.meta_block.header.m_len.unwrap() as usize == self.meta_block.count_output {
 						State::DataMetaBlockEnd
 					} else {
-						match self.parse_distance_code() {
-							Ok(state) => state,
-							Err(e) => return Err(e),
-						}
+						State::InsertLiteralsEnd
 					};
 
 					if buf_pos == buf.len() {
 						return Ok(buf_pos);
 					}
 				},
+				State::InsertLiteralsEnd => {
+					self.state = self.parse_distance_code()?;
+				},
 				State::DistanceCode(distance_code) => {
 					self.meta_block.distance_code = Some(distance_code);
 
@@ -2101,6 +3012,16 @@ impl<R: Read> Decompressor<R> {
 				},
 				State::CopyLiterals(copy_literals) => {
 					let m_len = self.meta_block.header.m_len.unwrap() as usize;
+					let distance = self.meta_block.distance.unwrap() as usize;
+					let distance_code = self.meta_block.distance_code.unwrap();
+					let bits = self.command_bit_pos..self.in_stream.global_bit_pos();
+					// as decided by copy_literals(), before the copied bytes extend the output
+					let max_distance = self.max_backward_distance();
+					let dictionary_word_ids = if distance > max_distance {
+						Some(self.dictionary_word_ids(self.meta_block.copy_length.unwrap() as usize, distance - max_distance - 1))
+					} else {
+						None
+					};
 
 					if m_len < self.meta_block.count_output + copy_literals.len() {
 
@@ -2111,8 +3032,8 @@ impl<R: Read> Decompressor<R> {
 						if buf_pos < buf.len() {
 							buf[buf_pos] = *literal;
 							buf_pos += 1;
-						} else {
-							self.buf.push_front(*literal);
+						} else if !self.discard_output {
+							self.buf.push_back(*literal);
 						}
 						self.literal_buf.push(*literal);
 
@@ -2125,6 +3046,27 @@ impl<R: Read> Decompressor<R> {
 
 					// debug(&format!("output = {:?}", self.buf));
 
+					match dictionary_word_ids {
+						Some((dictionary, word_id, transform_id)) => visitor.visit_dictionary_word(&DictionaryWordEvent{
+							bits,
+							distance_code,
+							from_distance_cache: distance_code < 16,
+							distance,
+							dictionary,
+							word_id,
+							transform_id,
+							word: &copy_literals,
+						}),
+						None => visitor.visit_copy(&CopyEvent{
+							bits,
+							distance_code,
+							from_distance_cache: distance_code < 16,
+							distance,
+							bytes: &copy_literals,
+						}),
+					}
+					self.report_progress_interval();
+
 					self.state = if self.meta_block.header.m_len.unwrap() as usize == self.meta_block.count_output {
 
 						State::DataMetaBlockEnd
@@ -2144,6 +3086,22 @@ impl<R: Read> Decompressor<R> {
 					self.state = State::MetaBlockEnd;
 				},
 				State::MetaBlockEnd => {
+					if self.pause_at_meta_block_end && !self.paused {
+						self.paused = true;
+
+						return Ok(buf_pos);
+					}
+
+					self.paused = false;
+					self.report_progress();
+					self.record(|meta_block| {
+						meta_block.bits.end = field_bits.end;
+
+						if let Some(ref mut data_bits) = meta_block.data_bits {
+							data_bits.end = field_bits.end;
+						}
+					});
+
 					self.state = if self.meta_block.header.is_last.unwrap() {
 
 						State::StreamEnd
@@ -2159,8 +3117,15 @@ impl<R: Read> Decompressor<R> {
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
 					}
 
+					if let Some(ref mut structure) = self.structure {
+						// only the first time, the stream end is revisited by later calls
+						if structure.trailing_bits.is_none() {
+							structure.trailing_bits = Some(field_bits.end..self.in_stream.global_bit_pos());
+						}
+					}
+
 					match self.in_stream.read_u8() {
-						Err(BitReaderError::EOF) => return Ok(buf_pos),
+						Err(BitReaderError::Eof) => return Ok(buf_pos),
 						Ok(_) => return Err(DecompressorError::ExpectedEndOfStream),
 						Err(_) => return Err(DecompressorError::UnexpectedEOF),
 					}
@@ -2171,24 +3136,28 @@ impl<R: Read> Decompressor<R> {
 }
 
 impl<R: Read> Read for Decompressor<R> {
-	fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
-		if self.buf.is_empty() {
-			match self.decompress(&mut buf) {
-				Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.description())),
-				Ok(l) => {
-					Ok(l)
-				},
-			}
-		} else {
-			let l = cmp::min(self.buf.len(), buf.len());
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_visiting(buf, &mut NoVisitor)
+	}
+}
 
-			for i in 0..l {
-				buf[i] = self.buf.pop_back().unwrap();
+impl<R: Read> BufRead for Decompressor<R> {
+	/// Returns the decompressed bytes that are buffered internally, decompressing
+	/// the next chunk of the stream if there are none. An empty slice means end-of-stream.
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		while self.buf.is_empty() && self.state != State::StreamEnd {
+			// an empty output slice makes decompress() put all decoded bytes into self.buf
+			if let Err(e) = self.decompress(&mut []) {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
 			}
-
-			Ok(l)
 		}
 
+		Ok(self.buf.as_slices().0)
 	}
-}
 
+	fn consume(&mut self, amt: usize) {
+		let amt = cmp::min(amt, self.buf.len());
+
+		self.buf.drain(..amt);
+	}
+}
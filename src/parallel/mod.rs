@@ -0,0 +1,194 @@
+use ::Decompressor;
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{ Read, Write };
+use std::ops::Range;
+use std::panic;
+use std::sync::{ Condvar, Mutex, MutexGuard };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::thread;
+
+/// Outputs of the worker threads, and the progress of writing them in order.
+#[derive(Debug)]
+struct Reorder {
+	/// number of the next segment to write
+	next_to_write: usize,
+	/// decoded segments that wait for the ones before them
+	pending: BTreeMap<usize, io::Result<Vec<u8>>>,
+	/// set when writing fails or a segment fails to decode, to stop the workers
+	aborted: bool,
+}
+
+/// Decodes independent brotli streams, the segments of an input, on multiple threads,
+/// and writes their decompressed outputs in order.
+///
+/// The segments can be the chunks of a framed file, see FramedReader::chunks(), or the members
+/// of an input of concatenated brotli streams. Every worker thread decodes one segment at a time
+/// with its own Decompressor. A segment is only decoded when fewer than max_pending segments
+/// before it wait to be written, so that memory stays bounded by the largest decompressed segments,
+/// however the work is distributed between the threads.
+///
+/// # Examples
+/// ```
+/// use brotli::{ compress, ParallelDecoder };
+///
+/// // two concatenated brotli streams
+/// let first = compress(b"decoded in parallel, ", 5);
+/// let second = compress(b"written in order", 5);
+/// let input = [&first[..], &second[..]].concat();
+///
+/// let mut decoder = ParallelDecoder::new(&input, vec![0..first.len(), first.len()..input.len()]);
+/// decoder.set_threads(2);
+///
+/// let mut decompressed = Vec::new();
+/// decoder.decode_to(&mut decompressed).unwrap();
+///
+/// assert_eq!(b"decoded in parallel, written in order".to_vec(), decompressed);
+/// ```
+#[derive(Debug)]
+pub struct ParallelDecoder<'a> {
+	input: &'a [u8],
+	segments: Vec<Range<usize>>,
+	threads: usize,
+	max_pending: usize,
+}
+
+impl<'a> ParallelDecoder<'a> {
+	/// Creates a ParallelDecoder for the segments of input, each one a byte range that holds a complete
+	/// brotli stream. Uses as many threads as the system reports to be available, and holds up to twice
+	/// as many decoded segments for reordering.
+	pub fn new(input: &'a [u8], segments: Vec<Range<usize>>) -> ParallelDecoder<'a> {
+		let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+		ParallelDecoder{
+			input,
+			segments,
+			threads,
+			max_pending: 2 * threads,
+		}
+	}
+
+	/// Sets the number of worker threads, at least one.
+	pub fn set_threads(&mut self, threads: usize) {
+		self.threads = cmp::max(1, threads);
+	}
+
+	/// Sets the maximum number of decoded segments that wait to be written, at least one.
+	pub fn set_max_pending(&mut self, max_pending: usize) {
+		self.max_pending = cmp::max(1, max_pending);
+	}
+
+	/// Decodes all segments, and writes their outputs to w in the order of the segments.
+	/// Returns the number of bytes written.
+	/// Stops at the first segment that fails to decode, and returns an error that names it.
+	pub fn decode_to<W: Write>(&self, w: &mut W) -> io::Result<u64> {
+		if self.segments.iter().any(|segment| segment.start > segment.end || segment.end > self.input.len()) {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "segment beyond the end of the input"));
+		}
+
+		let next_to_decode = AtomicUsize::new(0);
+		let reorder = Mutex::new(Reorder{
+			next_to_write: 0,
+			pending: BTreeMap::new(),
+			aborted: false,
+		});
+		let changed = Condvar::new();
+
+		thread::scope(|scope| {
+			for _ in 0..cmp::min(self.threads, self.segments.len()) {
+				scope.spawn(|| self.work(&next_to_decode, &reorder, &changed));
+			}
+
+			let result = self.write_in_order(w, &reorder, &changed);
+
+			if result.is_err() {
+				lock(&reorder).aborted = true;
+				changed.notify_all();
+			}
+
+			result
+		})
+	}
+
+	/// Decodes segments until there are none left, or decoding has been aborted.
+	fn work(&self, next_to_decode: &AtomicUsize, reorder: &Mutex<Reorder>, changed: &Condvar) {
+		loop {
+			let i = next_to_decode.fetch_add(1, Ordering::SeqCst);
+
+			if i >= self.segments.len() {
+				return;
+			}
+
+			{
+				let mut state = lock(reorder);
+
+				while i >= state.next_to_write + self.max_pending && !state.aborted {
+					state = match changed.wait(state) {
+						Ok(state) => state,
+						Err(poisoned) => poisoned.into_inner(),
+					};
+				}
+
+				if state.aborted {
+					return;
+				}
+			}
+
+			let segment = &self.input[self.segments[i].clone()];
+			// a panic must not leave the writing thread waiting for the segment
+			let result = match panic::catch_unwind(|| {
+				let mut decompressed = Vec::new();
+
+				Decompressor::new(segment).read_to_end(&mut decompressed).map(|_| decompressed)
+			}) {
+				Ok(Ok(decompressed)) => Ok(decompressed),
+				Ok(Err(e)) => Err(io::Error::new(e.kind(), format!("{} in segment {}", e, i))),
+				Err(_) => Err(io::Error::other(format!("Decompressor panicked in segment {}", i))),
+			};
+
+			lock(reorder).pending.insert(i, result);
+			changed.notify_all();
+		}
+	}
+
+	/// Writes the decoded segments in order, as they become available.
+	fn write_in_order<W: Write>(&self, w: &mut W, reorder: &Mutex<Reorder>, changed: &Condvar) -> io::Result<u64> {
+		let mut written = 0;
+
+		for i in 0..self.segments.len() {
+			let result = {
+				let mut state = lock(reorder);
+
+				loop {
+					match state.pending.remove(&i) {
+						Some(result) => break result,
+						None => state = match changed.wait(state) {
+							Ok(state) => state,
+							Err(poisoned) => poisoned.into_inner(),
+						},
+					}
+				}
+			};
+
+			let decompressed = result?;
+
+			w.write_all(&decompressed)?;
+			written += decompressed.len() as u64;
+
+			lock(reorder).next_to_write = i + 1;
+			changed.notify_all();
+		}
+
+		Ok(written)
+	}
+}
+
+/// Locks the reorder state, which stays consistent even if a thread panicked while holding it.
+fn lock(reorder: &Mutex<Reorder>) -> MutexGuard<'_, Reorder> {
+	match reorder.lock() {
+		Ok(state) => state,
+		Err(poisoned) => poisoned.into_inner(),
+	}
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{ Debug, Display, Formatter };
@@ -33,6 +34,27 @@ impl<T: Copy + Debug> RingBuffer<T> {
 		}
 	}
 
+	/// Reassembles a RingBuffer from the parts returned by raw_parts().
+	/// Returns RingBufferError::InvalidRawParts, if the parts could not
+	/// have been produced by a RingBuffer.
+	pub fn from_raw_parts(buf: Vec<T>, pos: usize, cap: usize) -> Result<RingBuffer<T>, RingBufferError> {
+		if cap == 0 || buf.len() > cap || pos >= cmp::max(buf.len(), 1) {
+			Err(RingBufferError::InvalidRawParts)
+		} else {
+			Ok(RingBuffer {
+				buf,
+				pos,
+				cap,
+			})
+		}
+	}
+
+	/// Returns the stored elements in storage order, the index of the
+	/// last pushed element, and the capacity.
+	pub fn raw_parts(&self) -> (&[T], usize, usize) {
+		(&self.buf, self.pos, self.cap)
+	}
+
 	/// Returns a result containing the nth element from the back,
 	/// i.e. the 0th element is the last element that has been pushed.
 	/// Returns RingBufferError::ParameterExceededSize, if n exceeds
@@ -82,9 +104,25 @@ fn should_retrieve_last_item() {
 	assert_eq!(item, *buf.nth(0).unwrap());;
 }
 
+#[test]
+fn should_reassemble_from_raw_parts() {
+	let mut buf = RingBuffer::with_capacity(3);
+	for item in 0..5 {
+		buf.push(item);
+	}
+
+	let (items, pos, cap) = buf.raw_parts();
+	let restored = RingBuffer::from_raw_parts(items.to_vec(), pos, cap).unwrap();
+
+	assert_eq!(buf, restored);
+	assert_eq!(Err(RingBufferError::InvalidRawParts), RingBuffer::from_raw_parts(vec![1, 2], 2, 2));
+	assert_eq!(Err(RingBufferError::InvalidRawParts), RingBuffer::<u8>::from_raw_parts(vec![], 0, 0));
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum RingBufferError {
 	ParameterExceededSize,
+	InvalidRawParts,
 }
 
 impl Display for RingBufferError {
@@ -98,6 +136,7 @@ impl Error for RingBufferError {
 	fn description(&self) -> &str {
 		match *self {
 			RingBufferError::ParameterExceededSize => "Index parameter exceeded ring buffer size",
+			RingBufferError::InvalidRawParts => "Raw parts do not describe a valid ring buffer",
 		}
 	}
 }
@@ -0,0 +1,236 @@
+use ::{ CommandVisitor, CopyEvent, Decompressor, DictionaryWordEvent, InsertEvent };
+
+use std::cmp;
+use std::io;
+use std::io::Read;
+
+/// Knuth-Morris-Pratt automaton that runs over the commands of a stream. Its state after a byte
+/// of the output is the length of the longest prefix of the pattern that ends with that byte.
+#[derive(Debug)]
+struct Automaton<'a> {
+	pattern: &'a [u8],
+	/// borders[k] is the length of the longest proper prefix of pattern[..k] that is also a suffix of it
+	borders: Vec<usize>,
+	state: usize,
+	/// number of bytes of the output so far
+	position: u64,
+	/// offsets of the matches found so far, in increasing order
+	matches: Vec<u64>,
+}
+
+impl<'a> Automaton<'a> {
+	fn new(pattern: &'a [u8]) -> Automaton<'a> {
+		let mut borders = vec![0; pattern.len() + 1];
+		let mut k = 0;
+
+		for (i, &byte) in pattern.iter().enumerate().skip(1) {
+			while k > 0 && pattern[k] != byte {
+				k = borders[k];
+			}
+
+			if pattern[k] == byte {
+				k += 1;
+			}
+
+			borders[i + 1] = k;
+		}
+
+		Automaton{
+			pattern,
+			borders,
+			state: 0,
+			position: 0,
+			matches: Vec::new(),
+		}
+	}
+
+	/// Returns the state after byte, from the state after the byte before it.
+	fn next_state(&self, state: usize, byte: u8) -> usize {
+		let mut state = if state == self.pattern.len() {
+			self.borders[state]
+		} else {
+			state
+		};
+
+		while state > 0 && self.pattern[state] != byte {
+			state = self.borders[state];
+		}
+
+		if self.pattern[state] == byte {
+			state += 1;
+		}
+
+		state
+	}
+
+	/// Runs the automaton over the next byte of the output.
+	fn scan(&mut self, byte: u8) {
+		self.state = self.next_state(self.state, byte);
+		self.position += 1;
+
+		if self.state == self.pattern.len() {
+			self.matches.push(self.position - self.state as u64);
+		}
+	}
+
+	/// Skips the next len bytes of the output, which repeat the bytes distance bytes back, where
+	/// the pattern.len() - 1 bytes before them repeat as well. A match that ends within them repeats
+	/// a match distance bytes back, so the matches of the skipped bytes are taken over from there.
+	/// The matches taken over can be copies themselves, if the bytes overlap their copy.
+	fn skip_copied(&mut self, len: usize, distance: usize, last_bytes: &[u8]) {
+		let first = self.position + 1 - self.pattern.len() as u64;
+		let last = self.position + len as u64 - self.pattern.len() as u64;
+		let mut i = self.matches.partition_point(|&offset| offset + (distance as u64) < first);
+
+		while i < self.matches.len() && self.matches[i] + distance as u64 <= last {
+			let offset = self.matches[i] + distance as u64;
+
+			self.matches.push(offset);
+			i += 1;
+		}
+
+		// the state only depends on the last pattern.len() bytes
+		self.state = last_bytes.iter().fold(0, |state, &byte| self.next_state(state, byte));
+		self.position += len as u64;
+	}
+}
+
+impl<'a> CommandVisitor for Automaton<'a> {
+	fn visit_insert(&mut self, event: &InsertEvent) {
+		for &byte in event.literals {
+			self.scan(byte);
+		}
+	}
+
+	fn visit_copy(&mut self, event: &CopyEvent) {
+		// matches that begin before the copy can only be found by running the automaton
+		let scanned = cmp::min(event.bytes.len(), self.pattern.len() - 1);
+
+		for &byte in &event.bytes[..scanned] {
+			self.scan(byte);
+		}
+
+		if scanned < event.bytes.len() {
+			let last_bytes = &event.bytes[event.bytes.len() - self.pattern.len()..];
+
+			self.skip_copied(event.bytes.len() - scanned, event.distance, last_bytes);
+		}
+	}
+
+	fn visit_dictionary_word(&mut self, event: &DictionaryWordEvent) {
+		for &byte in event.word {
+			self.scan(byte);
+		}
+	}
+}
+
+/// Searches the brotli stream in r for pattern, and returns the offsets of all matches in the
+/// decompressed output, including overlapping ones, in increasing order.
+///
+/// The search follows the commands of the stream instead of scanning the decompressed output.
+/// Inserted literals and dictionary words are scanned, and so are the first pattern.len() - 1
+/// bytes of every copy, because a match may begin before the copy. The rest of a copy is skipped:
+/// every match within it repeats a match of the copied bytes, which has been found before, and the
+/// state of the matcher is recomputed from the last pattern.len() bytes of the copy. So the work per
+/// copy depends on the length of the pattern and the number of matches, not on the length of the copy.
+///
+/// Returns an error with io::ErrorKind::InvalidInput if pattern is empty,
+/// and io::ErrorKind::InvalidData if the stream is invalid.
+///
+/// # Examples
+/// ```
+/// use brotli::{ compress, search };
+///
+/// let compressed = compress(b"to be, or not to be", 5);
+///
+/// assert_eq!(vec![0, 14], search(&compressed[..], b"to be").unwrap());
+/// ```
+pub fn search<R: Read>(r: R, pattern: &[u8]) -> io::Result<Vec<u64>> {
+	if pattern.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty search pattern"));
+	}
+
+	let mut automaton = Automaton::new(pattern);
+
+	Decompressor::new(r).visit_commands(&mut automaton)?;
+
+	Ok(automaton.matches)
+}
+
+mod tests {
+	#[test]
+	fn should_take_over_matches_within_copies() {
+		use super::*;
+
+		let mut automaton = Automaton::new(b"abab");
+		let copy = |distance, bytes| CopyEvent{
+			bits: 0..0,
+			distance_code: 0,
+			from_distance_cache: true,
+			distance,
+			bytes,
+		};
+
+		automaton.visit_insert(&InsertEvent{ bits: 0..0, literals: b"xab", uncompressed: false });
+		// the copy overlaps itself, and continues the match that begins before it
+		automaton.visit_copy(&copy(2, b"ababab"));
+		automaton.visit_insert(&InsertEvent{ bits: 0..0, literals: b"x", uncompressed: false });
+		automaton.visit_copy(&copy(10, b"xababab"));
+
+		assert_eq!(vec![1, 3, 5, 11, 13], automaton.matches);
+	}
+
+	#[test]
+	fn should_skip_copied_bytes() {
+		use super::*;
+
+		let mut automaton = Automaton::new(b"ab");
+
+		automaton.visit_insert(&InsertEvent{ bits: 0..0, literals: b"xy", uncompressed: false });
+		// bytes that do not repeat the copied ones show which of them are scanned: only the first pattern.len() - 1,
+		// and the last pattern.len() for the state after the copy
+		automaton.visit_copy(&CopyEvent{
+			bits: 0..0,
+			distance_code: 0,
+			from_distance_cache: true,
+			distance: 2,
+			bytes: b"xabababy",
+		});
+		automaton.visit_insert(&InsertEvent{ bits: 0..0, literals: b"abxa", uncompressed: false });
+		automaton.visit_insert(&InsertEvent{ bits: 0..0, literals: b"b", uncompressed: false });
+
+		assert_eq!(vec![10, 13], automaton.matches);
+	}
+
+	#[test]
+	fn should_find_the_same_matches_as_a_scan_of_the_output() {
+		use super::*;
+		use ::compress;
+
+		let mut text = Vec::new();
+
+		for i in 0..2000 {
+			text.extend_from_slice(format!("{} aaaa, aab{}\n", i % 7, i % 13).as_bytes());
+		}
+
+		let compressed = compress(&text, 5);
+
+		for pattern in [&b"a"[..], b"aa", b"aab", b"aaaa, aab1", b"\n3 a", b"b12\n5 aaaa", b"missing"].iter() {
+			let expected = text.windows(pattern.len())
+				.enumerate()
+				.filter(|&(_, window)| window == *pattern)
+				.map(|(i, _)| i as u64)
+				.collect::<Vec<_>>();
+
+			assert_eq!(expected, search(&compressed[..], pattern).unwrap());
+		}
+	}
+
+	#[test]
+	fn should_reject_empty_pattern() {
+		use super::*;
+
+		assert_eq!(io::ErrorKind::InvalidInput, search(&[0x06][..], b"").unwrap_err().kind());
+	}
+}
+
@@ -0,0 +1,328 @@
+use ::{ Decompressor, DecompressorError, Snapshot };
+use ::snapshot::{ SnapshotError, SnapshotReader, SnapshotWriter };
+
+use std::io;
+use std::io::{ Read, Seek, SeekFrom, Write };
+
+const INDEX_MAGIC: &[u8; 4] = b"BRSI";
+const INDEX_VERSION: u8 = 1;
+/// Default minimum number of decompressed bytes between two checkpoints.
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 1 << 22;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Checkpoint {
+	output_offset: u64,
+	snapshot: Snapshot,
+}
+
+/// Wraps a seekable input stream and provides random access to the decompressed stream.
+///
+/// While decompressing, the SeekableDecompressor records checkpoints at meta-block boundaries,
+/// each one holding the complete decoder state at that point, including the window contents
+/// and the distance cache. Seeking resumes from the nearest checkpoint before the target position,
+/// instead of decompressing from the beginning of the stream.
+/// The index of checkpoints can be saved and loaded, so that it needs to be built only once.
+///
+/// # Examples
+/// ```
+/// use std::io::{ Read, Seek, SeekFrom };
+/// use brotli::SeekableDecompressor;
+///
+/// let brotli_stream = std::fs::File::open("data/alice29.txt.compressed").unwrap();
+/// let mut decompressor = SeekableDecompressor::new(brotli_stream).unwrap();
+///
+/// let mut heading = [0u8; 10];
+/// decompressor.seek(SeekFrom::Start(12159)).unwrap();
+/// decompressor.read_exact(&mut heading).unwrap();
+///
+/// assert_eq!(b"CHAPTER II", &heading);
+/// ```
+#[derive(Debug)]
+pub struct SeekableDecompressor<R: Read + Seek> {
+	decompressor: Option<Decompressor<R>>,
+	/// offset of the brotli stream within the input stream
+	base_offset: u64,
+	/// sorted by output offset, the first one is always at the start of the stream
+	checkpoints: Vec<Checkpoint>,
+	checkpoint_interval: u64,
+	/// number of bytes the current decompressor has produced
+	output_offset: u64,
+	/// position within the decompressed stream, as seen by the user
+	position: u64,
+	len: Option<u64>,
+}
+
+impl<R: Read + Seek> SeekableDecompressor<R> {
+	/// Creates a SeekableDecompressor from a Read + Seek that is positioned
+	/// at the beginning of a brotli stream.
+	pub fn new(mut r: R) -> io::Result<SeekableDecompressor<R>> {
+		let base_offset = r.stream_position()?;
+		let mut decompressor = Decompressor::new(r);
+		decompressor.pause_at_meta_block_end = true;
+
+		let checkpoint = Checkpoint{
+			output_offset: 0,
+			snapshot: decompressor.snapshot()?,
+		};
+
+		Ok(SeekableDecompressor{
+			decompressor: Some(decompressor),
+			base_offset,
+			checkpoints: vec![checkpoint],
+			checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+			output_offset: 0,
+			position: 0,
+			len: None,
+		})
+	}
+
+	/// Sets the minimum number of decompressed bytes between two checkpoints, defaults to 4 MiB.
+	/// Every checkpoint holds a copy of the window, so smaller intervals make seeking faster
+	/// at the expense of a larger index.
+	pub fn set_checkpoint_interval(&mut self, interval: usize) {
+		self.checkpoint_interval = interval as u64;
+	}
+
+	/// Decompresses the remainder of the stream, recording checkpoints along the way.
+	/// Returns the length of the decompressed stream. The current position is not changed.
+	pub fn build_index(&mut self) -> io::Result<u64> {
+		if let Some(len) = self.len {
+			return Ok(len);
+		}
+
+		let last = self.checkpoints.len() - 1;
+
+		if self.checkpoints[last].output_offset > self.output_offset {
+			self.restore(last)?;
+		}
+
+		let mut scratch = [0u8; 4096];
+
+		while self.read_decompressed(&mut scratch)? > 0 {}
+
+		Ok(self.output_offset)
+	}
+
+	/// Writes the index of checkpoints that have been recorded so far to w.
+	pub fn save_index<W: Write>(&self, mut w: W) -> io::Result<()> {
+		let mut s = SnapshotWriter::new();
+
+		for &byte in INDEX_MAGIC {
+			s.write_u8(byte);
+		}
+		s.write_u8(INDEX_VERSION);
+		s.write_option(&self.len, |s, len| s.write_u64(*len));
+		s.write_usize(self.checkpoints.len());
+
+		for checkpoint in &self.checkpoints {
+			s.write_u64(checkpoint.output_offset);
+			s.write_bytes(checkpoint.snapshot.as_bytes());
+		}
+
+		w.write_all(&s.into_inner())
+	}
+
+	/// Replaces the index of checkpoints with one that has previously been written by save_index()
+	/// for the same brotli stream.
+	pub fn load_index<I: Read>(&mut self, mut r: I) -> io::Result<()> {
+		let mut bytes = Vec::new();
+		r.read_to_end(&mut bytes)?;
+
+		let (len, checkpoints) = match SeekableDecompressor::<R>::parse_index(&bytes) {
+			Ok(index) => index,
+			Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, DecompressorError::InvalidCheckpointIndex.to_string())),
+		};
+
+		self.len = len;
+		self.checkpoints = checkpoints;
+
+		Ok(())
+	}
+
+	fn parse_index(bytes: &[u8]) -> Result<(Option<u64>, Vec<Checkpoint>), SnapshotError> {
+		let mut s = SnapshotReader::new(bytes);
+
+		for &byte in INDEX_MAGIC {
+			if s.read_u8()? != byte {
+				return Err(SnapshotError::InvalidValue);
+			}
+		}
+
+		if s.read_u8()? != INDEX_VERSION {
+			return Err(SnapshotError::InvalidValue);
+		}
+
+		let len = s.read_option(|s| s.read_u64())?;
+		// output offset and snapshot length take 16 bytes per checkpoint
+		let count = s.read_count(16)?;
+		let mut checkpoints = Vec::with_capacity(count);
+
+		for _ in 0..count {
+			let output_offset = s.read_u64()?;
+			let snapshot = match Snapshot::from_bytes(s.read_bytes()?) {
+				Ok(snapshot) => snapshot,
+				Err(_) => return Err(SnapshotError::InvalidValue),
+			};
+
+			checkpoints.push(Checkpoint{
+				output_offset,
+				snapshot,
+			});
+		}
+
+		let sorted = checkpoints.windows(2).all(|pair| pair[0].output_offset < pair[1].output_offset);
+
+		match checkpoints.first() {
+			Some(&Checkpoint{ output_offset: 0, .. }) if sorted && s.is_at_end() => Ok((len, checkpoints)),
+			_ => Err(SnapshotError::InvalidValue),
+		}
+	}
+
+	fn decompressor(&mut self) -> io::Result<&mut Decompressor<R>> {
+		match self.decompressor {
+			Some(ref mut decompressor) => Ok(decompressor),
+			None => Err(io::Error::other(DecompressorError::LostInputStream.to_string())),
+		}
+	}
+
+	/// Replaces the current decompressor with one restored from the ith checkpoint.
+	fn restore(&mut self, i: usize) -> io::Result<()> {
+		let mut r = match self.decompressor.take() {
+			Some(decompressor) => decompressor.into_inner(),
+			None => return Err(io::Error::other(DecompressorError::LostInputStream.to_string())),
+		};
+
+		let checkpoint = &self.checkpoints[i];
+		r.seek(SeekFrom::Start(self.base_offset + checkpoint.snapshot.input_offset()))?;
+
+		let mut decompressor = Decompressor::from_snapshot(r, &checkpoint.snapshot)?;
+		decompressor.pause_at_meta_block_end = true;
+
+		self.decompressor = Some(decompressor);
+		self.output_offset = checkpoint.output_offset;
+
+		Ok(())
+	}
+
+	/// Records a checkpoint at the current output offset, if the previous one is far enough behind.
+	fn record_checkpoint(&mut self) -> io::Result<()> {
+		let i = match self.checkpoints.binary_search_by_key(&self.output_offset, |checkpoint| checkpoint.output_offset) {
+			Ok(_) => return Ok(()),
+			Err(i) => i,
+		};
+
+		if self.output_offset - self.checkpoints[i - 1].output_offset < self.checkpoint_interval {
+			return Ok(());
+		}
+
+		let checkpoint = Checkpoint{
+			output_offset: self.output_offset,
+			snapshot: self.decompressor()?.snapshot()?,
+		};
+
+		self.checkpoints.insert(i, checkpoint);
+
+		Ok(())
+	}
+
+	/// Reads from the current decompressor, recording checkpoints at meta-block boundaries.
+	fn read_decompressed(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		loop {
+			let (n, paused) = {
+				let decompressor = self.decompressor()?;
+				let n = decompressor.read(buf)?;
+
+				(n, decompressor.paused)
+			};
+
+			self.output_offset += n as u64;
+
+			if paused {
+				self.record_checkpoint()?;
+			}
+
+			if n > 0 {
+				return Ok(n);
+			}
+
+			if !paused {
+				self.len = Some(self.output_offset);
+
+				return Ok(0);
+			}
+		}
+	}
+
+	/// Brings the output offset of the decompressor to the current position,
+	/// restoring from the nearest checkpoint if that saves work.
+	fn catch_up(&mut self) -> io::Result<()> {
+		let i = match self.checkpoints.binary_search_by_key(&self.position, |checkpoint| checkpoint.output_offset) {
+			Ok(i) => i,
+			Err(i) => i - 1,
+		};
+
+		if self.position < self.output_offset || self.checkpoints[i].output_offset > self.output_offset {
+			self.restore(i)?;
+		}
+
+		let mut scratch = [0u8; 4096];
+
+		while self.output_offset < self.position {
+			let len = (self.position - self.output_offset).min(scratch.len() as u64) as usize;
+
+			if self.read_decompressed(&mut scratch[0..len])? == 0 {
+				break;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl<R: Read + Seek> Read for SeekableDecompressor<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if buf.is_empty() || matches!(self.len, Some(len) if self.position >= len) {
+			return Ok(0);
+		}
+
+		if self.output_offset != self.position {
+			self.catch_up()?;
+
+			if self.output_offset != self.position {
+				return Ok(0);
+			}
+		}
+
+		let n = self.read_decompressed(buf)?;
+		self.position += n as u64;
+
+		Ok(n)
+	}
+}
+
+impl<R: Read + Seek> Seek for SeekableDecompressor<R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let position = match pos {
+			SeekFrom::Start(n) => Some(n),
+			SeekFrom::Current(n) => offset(self.position, n),
+			SeekFrom::End(n) => offset(self.build_index()?, n),
+		};
+
+		match position {
+			Some(position) => {
+				self.position = position;
+
+				Ok(position)
+			},
+			None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")),
+		}
+	}
+}
+
+fn offset(base: u64, n: i64) -> Option<u64> {
+	if n < 0 {
+		base.checked_sub(n.unsigned_abs())
+	} else {
+		base.checked_add(n as u64)
+	}
+}
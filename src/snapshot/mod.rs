@@ -0,0 +1,386 @@
+use ::huffman::tree::Tree;
+use ::ringbuffer::RingBuffer;
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{ Debug, Display, Formatter };
+
+/// Identifies a serialized decoder state.
+pub const MAGIC: &[u8; 4] = b"BRSN";
+/// Version of the serialized decoder state format, gets bumped on every incompatible change.
+pub const VERSION: u8 = 3;
+/// Upper bound for the length of a prefix tree's buffer, corresponding to a maximum code length of 15.
+const MAX_TREE_BUF_LEN: usize = (1 << 16) - 1;
+
+/// Computes the Adler-32 checksum of bytes, used to detect snapshots that got corrupted in storage.
+pub fn checksum(bytes: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+
+	for chunk in bytes.chunks(5552) {
+		for &byte in chunk {
+			a += u32::from(byte);
+			b += a;
+		}
+		a %= 65521;
+		b %= 65521;
+	}
+
+	(b << 16) | a
+}
+
+/// Appends little-endian encoded values to a growing byte buffer.
+#[derive(Debug)]
+pub struct SnapshotWriter {
+	buf: Vec<u8>,
+}
+
+impl SnapshotWriter {
+	/// Creates a SnapshotWriter with an empty buffer.
+	pub fn new() -> SnapshotWriter {
+		SnapshotWriter{
+			buf: Vec::new(),
+		}
+	}
+
+	pub fn write_u8(&mut self, value: u8) {
+		self.buf.push(value);
+	}
+
+	pub fn write_bool(&mut self, value: bool) {
+		self.buf.push(value as u8);
+	}
+
+	pub fn write_u16(&mut self, value: u16) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_u32(&mut self, value: u32) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_u64(&mut self, value: u64) {
+		self.buf.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn write_usize(&mut self, value: usize) {
+		self.write_u64(value as u64);
+	}
+
+	/// Writes the slice prefixed by its length.
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.write_usize(bytes.len());
+		self.buf.extend_from_slice(bytes);
+	}
+
+	/// Writes a presence flag, followed by the value, if there is one.
+	pub fn write_option<T, F>(&mut self, value: &Option<T>, f: F) where F: FnOnce(&mut SnapshotWriter, &T) {
+		match *value {
+			Some(ref value) => {
+				self.write_bool(true);
+				f(self, value);
+			},
+			None => self.write_bool(false),
+		}
+	}
+
+	/// Writes a prefix tree sparsely, i.e. only the occupied slots of its buffer.
+	pub fn write_tree(&mut self, tree: &Tree) {
+		let (buf, len, last_symbol) = tree.raw_data();
+		let slots = buf.iter().enumerate().filter_map(|(i, slot)| slot.map(|symbol| (i, symbol))).collect::<Vec<_>>();
+
+		self.write_usize(buf.len());
+		self.write_usize(len);
+		self.write_option(&last_symbol, |w, symbol| w.write_u16(*symbol));
+		self.write_usize(slots.len());
+
+		for (i, symbol) in slots {
+			self.write_u32(i as u32);
+			self.write_u16(symbol);
+		}
+	}
+
+	/// Writes a list of prefix trees, prefixed by its length.
+	pub fn write_trees(&mut self, trees: &[Tree]) {
+		self.write_usize(trees.len());
+
+		for tree in trees {
+			self.write_tree(tree);
+		}
+	}
+
+	/// Writes the raw parts of a ring buffer, using f to write each item.
+	pub fn write_ring_buffer<T, F>(&mut self, ring_buffer: &RingBuffer<T>, f: F)
+			where T: Copy + Debug, F: Fn(&mut SnapshotWriter, &T) {
+		let (buf, pos, cap) = ring_buffer.raw_parts();
+
+		self.write_usize(cap);
+		self.write_usize(pos);
+		self.write_usize(buf.len());
+
+		for item in buf {
+			f(self, item);
+		}
+	}
+
+	pub fn into_inner(self) -> Vec<u8> {
+		self.buf
+	}
+}
+
+/// Reads back the values written by a SnapshotWriter.
+#[derive(Debug)]
+pub struct SnapshotReader<'a> {
+	buf: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+	/// Creates a SnapshotReader that reads from the start of buf.
+	pub fn new(buf: &'a [u8]) -> SnapshotReader<'a> {
+		SnapshotReader{
+			buf,
+			pos: 0,
+		}
+	}
+
+	fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+		if n > self.buf.len() - self.pos {
+			return Err(SnapshotError::Truncated);
+		}
+
+		let bytes = &self.buf[self.pos..self.pos + n];
+		self.pos += n;
+
+		Ok(bytes)
+	}
+
+	pub fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+		Ok(self.take(1)?[0])
+	}
+
+	pub fn read_bool(&mut self) -> Result<bool, SnapshotError> {
+		match self.read_u8()? {
+			0 => Ok(false),
+			1 => Ok(true),
+			_ => Err(SnapshotError::InvalidValue),
+		}
+	}
+
+	pub fn read_u16(&mut self) -> Result<u16, SnapshotError> {
+		let bytes = self.take(2)?;
+
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+
+	pub fn read_u32(&mut self) -> Result<u32, SnapshotError> {
+		let bytes = self.take(4)?;
+
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+
+	pub fn read_u64(&mut self) -> Result<u64, SnapshotError> {
+		let mut bytes = [0u8; 8];
+		bytes.copy_from_slice(self.take(8)?);
+
+		Ok(u64::from_le_bytes(bytes))
+	}
+
+	/// Reads a length or count, rejecting values that could not possibly
+	/// be backed by the remaining bytes of the snapshot.
+	pub fn read_usize(&mut self) -> Result<usize, SnapshotError> {
+		let value = self.read_u64()?;
+
+		if value > usize::MAX as u64 {
+			Err(SnapshotError::InvalidValue)
+		} else {
+			Ok(value as usize)
+		}
+	}
+
+	/// Reads a length-prefixed byte string.
+	pub fn read_bytes(&mut self) -> Result<Vec<u8>, SnapshotError> {
+		let len = self.read_usize()?;
+
+		Ok(self.take(len)?.to_vec())
+	}
+
+	/// Reads an optional value written by SnapshotWriter::write_option().
+	pub fn read_option<T, F>(&mut self, f: F) -> Result<Option<T>, SnapshotError>
+			where F: FnOnce(&mut SnapshotReader<'a>) -> Result<T, SnapshotError> {
+		if self.read_bool()? {
+			Ok(Some(f(self)?))
+		} else {
+			Ok(None)
+		}
+	}
+
+	/// Reads a count of items that each take at least item_size bytes,
+	/// rejecting counts that exceed the remaining length of the snapshot.
+	pub fn read_count(&mut self, item_size: usize) -> Result<usize, SnapshotError> {
+		let count = self.read_usize()?;
+
+		if count.saturating_mul(item_size) > self.buf.len() - self.pos {
+			Err(SnapshotError::Truncated)
+		} else {
+			Ok(count)
+		}
+	}
+
+	/// Reads a prefix tree written by SnapshotWriter::write_tree().
+	pub fn read_tree(&mut self) -> Result<Tree, SnapshotError> {
+		let buf_len = self.read_usize()?;
+
+		if buf_len == 0 || buf_len > MAX_TREE_BUF_LEN {
+			return Err(SnapshotError::InvalidValue);
+		}
+
+		let len = self.read_usize()?;
+		let last_symbol = self.read_option(|r| r.read_u16())?;
+		let count = self.read_count(6)?;
+		let mut buf = vec![None; buf_len];
+
+		for _ in 0..count {
+			let i = self.read_u32()? as usize;
+
+			if i >= buf_len {
+				return Err(SnapshotError::InvalidValue);
+			}
+
+			buf[i] = Some(self.read_u16()?);
+		}
+
+		Ok(Tree::from_raw_data(buf, len, last_symbol))
+	}
+
+	/// Reads a list of prefix trees written by SnapshotWriter::write_trees().
+	pub fn read_trees(&mut self) -> Result<Vec<Tree>, SnapshotError> {
+		// buffer length, length, last symbol flag, and slot count take at least 25 bytes per tree
+		let count = self.read_count(25)?;
+		let mut trees = Vec::with_capacity(count);
+
+		for _ in 0..count {
+			trees.push(self.read_tree()?);
+		}
+
+		Ok(trees)
+	}
+
+	/// Reads a ring buffer written by SnapshotWriter::write_ring_buffer(),
+	/// using f to read each item of item_size bytes.
+	pub fn read_ring_buffer<T, F>(&mut self, item_size: usize, f: F) -> Result<RingBuffer<T>, SnapshotError>
+			where T: Copy + Debug, F: Fn(&mut SnapshotReader<'a>) -> Result<T, SnapshotError> {
+		let cap = self.read_usize()?;
+		let pos = self.read_usize()?;
+		let len = self.read_count(item_size)?;
+		let mut buf = Vec::with_capacity(len);
+
+		for _ in 0..len {
+			buf.push(f(self)?);
+		}
+
+		RingBuffer::from_raw_parts(buf, pos, cap).map_err(|_| SnapshotError::InvalidValue)
+	}
+
+	pub fn is_at_end(&self) -> bool {
+		self.pos == self.buf.len()
+	}
+}
+
+/// Error types that can be returned while reading a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotError {
+	/// The snapshot ended before all values could be read.
+	Truncated,
+	/// The snapshot contained a value that is out of range.
+	InvalidValue,
+}
+
+impl Display for SnapshotError {
+	fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+		fmt.write_str(match *self {
+			SnapshotError::Truncated => "Snapshot ended prematurely",
+			SnapshotError::InvalidValue => "Snapshot contained an invalid value",
+		})
+	}
+}
+
+impl Error for SnapshotError {}
+
+mod tests {
+	#[test]
+	fn should_read_back_written_values() {
+		use super::*;
+
+		let mut w = SnapshotWriter::new();
+		w.write_u8(0xab);
+		w.write_bool(true);
+		w.write_u16(0x1234);
+		w.write_u32(0xdeadbeef);
+		w.write_usize(1 << 40);
+		w.write_bytes(b"brotli");
+		w.write_option(&Some(7u32), |w, v| w.write_u32(*v));
+		w.write_option(&None::<u32>, |w, v| w.write_u32(*v));
+
+		let buf = w.into_inner();
+		let mut r = SnapshotReader::new(&buf);
+
+		assert_eq!(Ok(0xab), r.read_u8());
+		assert_eq!(Ok(true), r.read_bool());
+		assert_eq!(Ok(0x1234), r.read_u16());
+		assert_eq!(Ok(0xdeadbeef), r.read_u32());
+		assert_eq!(Ok(1 << 40), r.read_usize());
+		assert_eq!(Ok(b"brotli".to_vec()), r.read_bytes());
+		assert_eq!(Ok(Some(7)), r.read_option(|r| r.read_u32()));
+		assert_eq!(Ok(None), r.read_option(|r| r.read_u32()));
+		assert!(r.is_at_end());
+	}
+
+	#[test]
+	fn should_read_back_tree_and_ring_buffer() {
+		use super::*;
+		use ::huffman::codes_from_lengths;
+
+		let tree = codes_from_lengths(&[2, 1, 3, 3]);
+		let mut ring_buffer = RingBuffer::with_capacity(4);
+		for distance in 1..7u32 {
+			ring_buffer.push(distance);
+		}
+
+		let mut w = SnapshotWriter::new();
+		w.write_tree(&tree);
+		w.write_ring_buffer(&ring_buffer, |w, item| w.write_u32(*item));
+
+		let buf = w.into_inner();
+		let mut r = SnapshotReader::new(&buf);
+
+		assert_eq!(Ok(tree), r.read_tree());
+		assert_eq!(Ok(ring_buffer), r.read_ring_buffer(4, |r| r.read_u32()));
+		assert!(r.is_at_end());
+	}
+
+	#[test]
+	fn should_compute_adler32() {
+		use super::*;
+
+		assert_eq!(0x00000001, checksum(&[]));
+		assert_eq!(0x11e60398, checksum(b"Wikipedia"));
+	}
+
+	#[test]
+	fn should_reject_truncated_input() {
+		use super::*;
+
+		let mut r = SnapshotReader::new(&[0x01, 0x02, 0x03]);
+
+		assert_eq!(Err(SnapshotError::Truncated), r.read_u32());
+	}
+
+	#[test]
+	fn should_reject_invalid_bool() {
+		use super::*;
+
+		let mut r = SnapshotReader::new(&[0x02]);
+
+		assert_eq!(Err(SnapshotError::InvalidValue), r.read_bool());
+	}
+}
@@ -0,0 +1,155 @@
+use ::bitwriter::BitWriter;
+use ::compressor::{ write_meta_block_header, write_wbits };
+
+use std::cmp;
+use std::io;
+use std::io::Write;
+
+/// Maximum length of a meta-block.
+const MAX_MLEN: usize = 1 << 24;
+/// Writes smaller than this are collected, so that every meta-block header is worth its bytes.
+const BUFFER_LEN: usize = 1 << 16;
+/// The window size does not matter for uncompressed data, 2^16 - 16 bytes keep the decoder small.
+const WBITS: u8 = 16;
+
+/// Wraps an output stream and writes the data that is written to it as a brotli stream,
+/// without compressing it.
+///
+/// The data is stored in uncompressed meta-blocks, each one of them byte-aligned,
+/// and the stream is completed with an empty ISLAST meta-block by finish(), or,
+/// ignoring errors, when the StoredWriter is dropped. Every flush() ends the current
+/// meta-block, so that a decoder can decode everything written up to that point.
+///
+/// # Examples
+/// ```
+/// use std::io::{ Read, Write };
+/// use brotli::{ Decompressor, StoredWriter };
+///
+/// let mut writer = StoredWriter::new(Vec::new());
+/// writer.write_all(b"stored, not compressed").unwrap();
+/// let brotli_stream = writer.finish().unwrap();
+///
+/// let mut decompressed = String::new();
+/// Decompressor::new(&brotli_stream[..]).read_to_string(&mut decompressed).unwrap();
+///
+/// assert_eq!("stored, not compressed", decompressed);
+/// ```
+#[derive(Debug)]
+pub struct StoredWriter<W: Write> {
+	/// None once the stream has been finished
+	out: Option<BitWriter<W>>,
+	buf: Vec<u8>,
+}
+
+impl<W: Write> StoredWriter<W> {
+	/// Creates a StoredWriter that writes the brotli stream to w.
+	pub fn new(w: W) -> StoredWriter<W> {
+		let mut out = BitWriter::new(w);
+
+		match write_wbits(&mut out, WBITS) {
+			Ok(()) => {},
+			Err(_) => unreachable!(), // confirmed unreachable, a single bit does not complete a byte to be written
+		}
+
+		StoredWriter{
+			out: Some(out),
+			buf: Vec::new(),
+		}
+	}
+
+	/// Gets a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		match self.out {
+			Some(ref out) => out.get_ref(),
+			None => unreachable!(), // confirmed unreachable, out is only taken by finish(), which consumes self
+		}
+	}
+
+	/// Writes the buffered data, and completes the brotli stream with an empty ISLAST meta-block.
+	/// Returns the underlying writer, after flushing it.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.finish_stream()?;
+
+		match self.out.take() {
+			Some(out) => Ok(out.into_inner()),
+			None => unreachable!(), // confirmed unreachable, see get_ref()
+		}
+	}
+
+	fn finish_stream(&mut self) -> io::Result<()> {
+		self.write_buffered()?;
+
+		if let Some(ref mut out) = self.out {
+			// ISLAST, ISLASTEMPTY
+			out.write_bits(0b11, 2)?;
+			out.align()?;
+			out.get_mut().flush()?;
+		}
+
+		Ok(())
+	}
+
+	fn write_buffered(&mut self) -> io::Result<()> {
+		if let Some(ref mut out) = self.out {
+			write_meta_blocks(out, &self.buf)?;
+		}
+
+		self.buf.clear();
+
+		Ok(())
+	}
+}
+
+/// Writes data as uncompressed meta-blocks.
+fn write_meta_blocks<W: Write>(out: &mut BitWriter<W>, data: &[u8]) -> io::Result<()> {
+	for chunk in data.chunks(MAX_MLEN) {
+		write_meta_block_header(out, chunk.len(), false, true)?;
+		out.align()?;
+		out.write_bytes(chunk)?;
+	}
+
+	Ok(())
+}
+
+impl<W: Write> Write for StoredWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if self.buf.len() + buf.len() < BUFFER_LEN {
+			self.buf.extend_from_slice(buf);
+
+			return Ok(buf.len());
+		}
+
+		// top up the buffered data, and write the rest of a large write without copying it
+		let n = cmp::min(buf.len(), BUFFER_LEN - self.buf.len());
+		self.buf.extend_from_slice(&buf[0..n]);
+		self.write_buffered()?;
+
+		if let Some(ref mut out) = self.out {
+			let rest = &buf[n..];
+			let n_direct = rest.len() - rest.len() % BUFFER_LEN;
+
+			write_meta_blocks(out, &rest[0..n_direct])?;
+			self.buf.extend_from_slice(&rest[n_direct..]);
+		}
+
+		Ok(buf.len())
+	}
+
+	/// Ends the current meta-block, and flushes the underlying writer.
+	fn flush(&mut self) -> io::Result<()> {
+		self.write_buffered()?;
+
+		match self.out {
+			Some(ref mut out) => out.get_mut().flush(),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<W: Write> Drop for StoredWriter<W> {
+	fn drop(&mut self) {
+		if self.out.is_some() {
+			let _ = self.finish_stream();
+		}
+	}
+}
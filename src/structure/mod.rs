@@ -0,0 +1,285 @@
+use ::Decompressor;
+use ::visitor::NoVisitor;
+
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+
+/// A decoded value of a header, with the bit offsets in the input that it has been read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field<T> {
+	/// Bit offsets in the input.
+	pub bits: Range<usize>,
+	/// The decoded value.
+	pub value: T,
+}
+
+/// The structure of a brotli stream: its header, and the headers of its meta-blocks, with the bit
+/// offsets of every field, as recorded by a Decompressor, see Decompressor::record_structure().
+///
+/// # Examples
+/// ```
+/// use brotli::StreamStructure;
+///
+/// // WBITS 22, a single uncompressed meta-block with the byte 'X', then an empty last meta-block
+/// let structure = StreamStructure::parse(&[0x0b, 0x00, 0x80, 0x58, 0x03][..]).unwrap();
+///
+/// assert_eq!(22, structure.wbits.unwrap().value);
+/// assert_eq!(2, structure.meta_blocks.len());
+///
+/// let meta_block = &structure.meta_blocks[0];
+///
+/// assert_eq!(Some(1), meta_block.m_len.as_ref().map(|m_len| m_len.value));
+/// assert_eq!(Some(24..32), meta_block.data_bits);
+/// assert_eq!(Some(true), structure.meta_blocks[1].is_last_empty.as_ref().map(|is_last_empty| is_last_empty.value));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamStructure {
+	/// WBITS, the base-2 logarithm of the window size plus 16.
+	pub wbits: Option<Field<u8>>,
+	/// The meta-blocks, the last one may be incomplete while the stream is being decoded.
+	pub meta_blocks: Vec<MetaBlockInfo>,
+	/// Bit offsets in the input of the bits that pad the last meta-block to the end of its byte.
+	pub trailing_bits: Option<Range<usize>>,
+}
+
+impl StreamStructure {
+	/// Creates a StreamStructure, before the stream header has been read.
+	pub fn new() -> StreamStructure {
+		StreamStructure{
+			wbits: None,
+			meta_blocks: Vec::new(),
+			trailing_bits: None,
+		}
+	}
+
+	/// Decodes the brotli stream in r, and returns its structure.
+	/// Returns an error with io::ErrorKind::InvalidData if the stream is invalid.
+	pub fn parse<R: Read>(r: R) -> io::Result<StreamStructure> {
+		let mut decompressor = Decompressor::new(r);
+
+		decompressor.record_structure();
+		decompressor.visit_commands(&mut NoVisitor)?;
+
+		match decompressor.structure.take() {
+			Some(structure) => Ok(structure),
+			None => unreachable!(), // confirmed unreachable, recording has been started before the first step
+		}
+	}
+
+	/// Returns the window size, 2^WBITS - 16, once WBITS has been read.
+	pub fn window_size(&self) -> Option<usize> {
+		self.wbits.as_ref().map(|wbits| (1 << wbits.value) - 16)
+	}
+}
+
+impl Default for StreamStructure {
+	fn default() -> StreamStructure {
+		StreamStructure::new()
+	}
+}
+
+/// The header of a meta-block, with the parts that describe how its data is encoded.
+/// A field is None if the meta-block does not contain it, or if it has not been read yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaBlockInfo {
+	/// Bit offsets in the input of the whole meta-block, from ISLAST to the end of its data.
+	pub bits: Range<usize>,
+	/// ISLAST, set for the last meta-block of the stream.
+	pub is_last: Option<Field<bool>>,
+	/// ISLASTEMPTY, set if the last meta-block is empty. Only present in the last meta-block.
+	pub is_last_empty: Option<Field<bool>>,
+	/// MNIBBLES, the number of nibbles of MLEN - 1, or 0 for a metadata meta-block.
+	pub m_nibbles: Option<Field<u8>>,
+	/// MSKIPBYTES, the number of bytes of MSKIPLEN - 1, in a metadata meta-block.
+	pub m_skip_bytes: Option<Field<u8>>,
+	/// MSKIPLEN, the number of bytes of metadata.
+	pub m_skip_len: Option<Field<u32>>,
+	/// MLEN, the number of bytes that the meta-block adds to the output.
+	pub m_len: Option<Field<u32>>,
+	/// ISUNCOMPRESSED, set if the meta-block contains MLEN bytes of uncompressed data.
+	/// Not present in the last meta-block.
+	pub is_uncompressed: Option<Field<bool>>,
+	/// NBLTYPESL and the codes of the block switch commands for literals.
+	pub literal_block_types: Option<BlockTypesInfo>,
+	/// NBLTYPESI and the codes of the block switch commands for insert-and-copy lengths.
+	pub insert_and_copy_length_block_types: Option<BlockTypesInfo>,
+	/// NBLTYPESD and the codes of the block switch commands for distances.
+	pub distance_block_types: Option<BlockTypesInfo>,
+	/// NPOSTFIX, the number of postfix bits of distance codes.
+	pub n_postfix: Option<Field<u8>>,
+	/// NDIRECT, the number of direct distance codes, already shifted by NPOSTFIX.
+	pub n_direct: Option<Field<u8>>,
+	/// The context modes of the literal block types.
+	pub context_modes: Option<Field<Vec<u8>>>,
+	/// NTREESL, the number of prefix codes for literals.
+	pub n_trees_l: Option<Field<u16>>,
+	/// The context map for literals, present if NTREESL is at least 2.
+	pub literal_context_map: Option<ContextMapInfo>,
+	/// NTREESD, the number of prefix codes for distances.
+	pub n_trees_d: Option<Field<u16>>,
+	/// The context map for distances, present if NTREESD is at least 2.
+	pub distance_context_map: Option<ContextMapInfo>,
+	/// The NTREESL prefix codes for literals.
+	pub literal_prefix_codes: Vec<PrefixCodeInfo>,
+	/// The NBLTYPESI prefix codes for insert-and-copy lengths.
+	pub insert_and_copy_length_prefix_codes: Vec<PrefixCodeInfo>,
+	/// The NTREESD prefix codes for distances.
+	pub distance_prefix_codes: Vec<PrefixCodeInfo>,
+	/// Bit offsets in the input of the compressed data, the uncompressed data or the metadata.
+	pub data_bits: Option<Range<usize>>,
+}
+
+impl MetaBlockInfo {
+	/// Creates a MetaBlockInfo for a meta-block that begins at bit_offset, before any field has been read.
+	pub fn new(bit_offset: usize) -> MetaBlockInfo {
+		MetaBlockInfo{
+			bits: bit_offset..bit_offset,
+			is_last: None,
+			is_last_empty: None,
+			m_nibbles: None,
+			m_skip_bytes: None,
+			m_skip_len: None,
+			m_len: None,
+			is_uncompressed: None,
+			literal_block_types: None,
+			insert_and_copy_length_block_types: None,
+			distance_block_types: None,
+			n_postfix: None,
+			n_direct: None,
+			context_modes: None,
+			n_trees_l: None,
+			literal_context_map: None,
+			n_trees_d: None,
+			distance_context_map: None,
+			literal_prefix_codes: Vec::new(),
+			insert_and_copy_length_prefix_codes: Vec::new(),
+			distance_prefix_codes: Vec::new(),
+			data_bits: None,
+		}
+	}
+}
+
+/// The number of block types of a category, and, if there are at least two,
+/// the prefix codes of the block switch commands, and the count of the first block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTypesInfo {
+	/// NBLTYPES, the number of block types.
+	pub n_bltypes: Field<u16>,
+	/// The prefix code of block type codes.
+	pub block_type_code: Option<PrefixCodeInfo>,
+	/// The prefix code of block count codes.
+	pub block_count_code: Option<PrefixCodeInfo>,
+	/// The number of symbols in the first block.
+	pub first_block_count: Option<Field<u32>>,
+}
+
+/// A prefix code, with the symbols it contains and their code lengths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixCodeInfo {
+	/// Bit offsets in the input of the description of the prefix code.
+	pub bits: Range<usize>,
+	/// The number of symbols of the alphabet.
+	pub alphabet_size: usize,
+	/// HSKIP of a complex prefix code, None for a simple prefix code.
+	pub h_skip: Option<u8>,
+	/// The code lengths of the code length symbols 0 to 17 of a complex prefix code.
+	pub code_length_code_lengths: Option<Vec<usize>>,
+	/// The tree-select bit of a simple prefix code with four symbols.
+	pub tree_select: Option<bool>,
+	/// The symbols that the prefix code contains, in the order of their codes,
+	/// i.e. ordered by code length, and then by symbol.
+	pub symbols: Vec<u16>,
+	/// The code length of every symbol in symbols, 0 if the prefix code contains a single symbol.
+	pub code_lengths: Vec<usize>,
+}
+
+/// An encoded context map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextMapInfo {
+	/// Bit offsets in the input of the whole context map.
+	pub bits: Range<usize>,
+	/// RLEMAX, the largest run length code, 0 if zeros are not run length encoded.
+	pub rle_max: Field<u16>,
+	/// The prefix code of the context map symbols.
+	pub prefix_code: PrefixCodeInfo,
+	/// The IMTF bit, set if the inverse move-to-front transform has been applied.
+	pub inverse_move_to_front: Field<bool>,
+	/// The decoded context map, the index of a prefix code for every block type and context id.
+	pub map: Vec<u8>,
+}
+
+mod tests {
+	#[test]
+	fn should_record_metadata_meta_block() {
+		use super::*;
+
+		// WBITS 16, a metadata meta-block with the 2 bytes "hi", then an empty last meta-block
+		let structure = StreamStructure::parse(&[0xac, 0x00, b'h', b'i', 0x03][..]).unwrap();
+		let meta_block = &structure.meta_blocks[0];
+
+		assert_eq!(Some(Field{ bits: 0..1, value: 16 }), structure.wbits);
+		assert_eq!(Some(65520), structure.window_size());
+		assert_eq!(1..32, meta_block.bits);
+		assert_eq!(Some(Field{ bits: 1..2, value: false }), meta_block.is_last);
+		assert_eq!(Some(Field{ bits: 2..4, value: 0 }), meta_block.m_nibbles);
+		assert_eq!(Some(Field{ bits: 5..7, value: 1 }), meta_block.m_skip_bytes);
+		assert_eq!(Some(Field{ bits: 7..15, value: 2 }), meta_block.m_skip_len);
+		assert_eq!(None, meta_block.m_len);
+		assert_eq!(Some(16..32), meta_block.data_bits);
+		assert_eq!(32..34, structure.meta_blocks[1].bits);
+		assert_eq!(Some(34..40), structure.trailing_bits);
+	}
+
+	#[test]
+	fn should_record_consistent_prefix_codes_and_bits() {
+		use super::*;
+		use ::compress;
+
+		let mut text = Vec::new();
+
+		for i in 0..3000 {
+			text.extend_from_slice(format!("{} structure {}, ", i % 11, i * 7 % 101).as_bytes());
+		}
+
+		let compressed = compress(&text, 9);
+		let structure = StreamStructure::parse(&compressed[..]).unwrap();
+		let mut bit_offset = structure.wbits.as_ref().unwrap().bits.end;
+
+		for meta_block in &structure.meta_blocks {
+			assert_eq!(bit_offset, meta_block.bits.start);
+
+			bit_offset = meta_block.bits.end;
+
+			let mut prefix_codes = meta_block.literal_prefix_codes.iter()
+				.chain(&meta_block.insert_and_copy_length_prefix_codes)
+				.chain(&meta_block.distance_prefix_codes)
+				.collect::<Vec<_>>();
+
+			prefix_codes.extend(meta_block.literal_context_map.as_ref().map(|context_map| &context_map.prefix_code));
+			prefix_codes.extend(meta_block.distance_context_map.as_ref().map(|context_map| &context_map.prefix_code));
+
+			for prefix_code in prefix_codes {
+				assert!(meta_block.bits.start <= prefix_code.bits.start && prefix_code.bits.end <= meta_block.bits.end);
+				assert_eq!(prefix_code.symbols.len(), prefix_code.code_lengths.len());
+
+				if prefix_code.symbols.len() > 1 {
+					// a complete prefix code
+					assert_eq!(1 << 15, prefix_code.code_lengths.iter().map(|&length| 1 << (15 - length)).sum::<usize>());
+				}
+
+				let mut sorted = prefix_code.code_lengths.iter().zip(&prefix_code.symbols).collect::<Vec<_>>();
+
+				sorted.sort();
+
+				assert_eq!(sorted, prefix_code.code_lengths.iter().zip(&prefix_code.symbols).collect::<Vec<_>>());
+			}
+		}
+
+		assert_eq!(bit_offset, structure.trailing_bits.unwrap().start);
+		assert_eq!(text.len() as u32, structure.meta_blocks.iter()
+			.filter_map(|meta_block| meta_block.m_len.as_ref())
+			.map(|m_len| m_len.value)
+			.sum::<u32>());
+	}
+}
@@ -0,0 +1,12 @@
+/// Returns a generator of pseudo-random numbers below a bound, an xorshift that starts from seed,
+/// so that the tests that use it are random-ish and repeatable.
+pub fn random(seed: u64) -> impl FnMut(u64) -> u64 {
+	let mut state = seed;
+
+	move |bound| {
+		state ^= state << 13;
+		state ^= state >> 7;
+		state ^= state << 17;
+		state % bound
+	}
+}
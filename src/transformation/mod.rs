@@ -1,6 +1,6 @@
 use std::cmp::{ max, min };
 
-fn uppercase_all(base_word: &[u8]) -> Vec<u8> {
+pub fn uppercase_all(base_word: &[u8]) -> Vec<u8> {
 	let l = base_word.len();
 	let mut v = Vec::with_capacity(l);
 	let mut i = 0;
@@ -39,7 +39,7 @@ fn uppercase_all(base_word: &[u8]) -> Vec<u8> {
 	v
 }
 
-fn uppercase_first(base_word: &[u8]) -> Vec<u8> {
+pub fn uppercase_first(base_word: &[u8]) -> Vec<u8> {
 	let l = base_word.len();
 
 	if l == 0 {
@@ -50,7 +50,7 @@ fn uppercase_first(base_word: &[u8]) -> Vec<u8> {
 	let i;
 
 	match base_word[0] {
-		1...96|123...191 => {
+		0...96|123...191 => {
 			v.push(base_word[0]);
 			i = 1;
 		},
@@ -75,7 +75,6 @@ fn uppercase_first(base_word: &[u8]) -> Vec<u8> {
 			}
 			i = 3;
 		},
-		_ => unreachable!(), // confirmed unreachable, all byte values covered
 	}
 
 	[v, Vec::from(&base_word[i..])].concat()
@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+/// Receives the commands of a brotli stream, and its block switches, while a Decompressor decodes them,
+/// see Decompressor::read_visiting() and Decompressor::visit_commands().
+///
+/// Commands are reported after their bytes have been appended to the output. Block switches are
+/// reported before the command during which they are read, a literal block switch before the insert
+/// whose literals it precedes, and the other ones before the insert or copy whose code follows them.
+/// All methods do nothing by default.
+///
+/// # Examples
+/// ```
+/// use brotli::{ compress, CommandVisitor, CopyEvent, Decompressor };
+///
+/// #[derive(Default)]
+/// struct CopiedBytes(usize);
+///
+/// impl CommandVisitor for CopiedBytes {
+///     fn visit_copy(&mut self, event: &CopyEvent) {
+///         self.0 += event.bytes.len();
+///     }
+/// }
+///
+/// let compressed = compress(b"abcabcabcabcabcabcabcabc", 5);
+/// let mut copied = CopiedBytes::default();
+///
+/// Decompressor::new(&compressed[..]).visit_commands(&mut copied).unwrap();
+///
+/// assert_eq!(21, copied.0);
+/// ```
+pub trait CommandVisitor {
+	/// Called for every insert, with its literals, which may be none,
+	/// and for the contents of every uncompressed meta-block.
+	fn visit_insert(&mut self, _event: &InsertEvent) {}
+
+	/// Called for every copy from the output window.
+	fn visit_copy(&mut self, _event: &CopyEvent) {}
+
+	/// Called for every reference into the static dictionary, or into a shared dictionary.
+	fn visit_dictionary_word(&mut self, _event: &DictionaryWordEvent) {}
+
+	/// Called for every block switch command.
+	fn visit_block_switch(&mut self, _event: &BlockSwitchEvent) {}
+}
+
+/// The literals of an insert, or the contents of an uncompressed meta-block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsertEvent<'a> {
+	/// Bit offsets in the input of the insert-and-copy length code, its extra bits and the literals,
+	/// or of the contents of the uncompressed meta-block.
+	pub bits: Range<usize>,
+	/// The inserted literals, as many as the insert length.
+	pub literals: &'a [u8],
+	/// True for the contents of an uncompressed meta-block.
+	pub uncompressed: bool,
+}
+
+/// A copy of bytes from the output window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyEvent<'a> {
+	/// Bit offsets in the input of the distance code and its extra bits,
+	/// empty if the insert-and-copy length code implies the last distance.
+	pub bits: Range<usize>,
+	/// The distance code, 0 if the insert-and-copy length code implies the last distance.
+	pub distance_code: u32,
+	/// True if the distance has been derived from the distance cache, i.e. for distance codes 0 to 15.
+	pub from_distance_cache: bool,
+	/// The distance back in the output window.
+	pub distance: usize,
+	/// The copied bytes, as many as the copy length.
+	pub bytes: &'a [u8],
+}
+
+/// A reference to a word of the static dictionary, or of a shared dictionary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictionaryWordEvent<'a> {
+	/// Bit offsets in the input of the distance code and its extra bits.
+	pub bits: Range<usize>,
+	/// The distance code.
+	pub distance_code: u32,
+	/// True if the distance has been derived from the distance cache, i.e. for distance codes 0 to 15.
+	pub from_distance_cache: bool,
+	/// The distance beyond the output window.
+	pub distance: usize,
+	/// The number of the dictionary within the shared dictionary, or None for the static dictionary.
+	pub dictionary: Option<usize>,
+	/// The index of the word among the words of the dictionary whose length is the copy length.
+	pub word_id: usize,
+	/// The id of the transform that has been applied to the word.
+	pub transform_id: usize,
+	/// The transformed word.
+	pub word: &'a [u8],
+}
+
+/// The category of blocks that a block switch applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockCategory {
+	/// Blocks of literals.
+	Literals,
+	/// Blocks of insert-and-copy length codes.
+	InsertAndCopyLengths,
+	/// Blocks of distance codes.
+	Distances,
+}
+
+/// A block switch command, which starts a new block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSwitchEvent {
+	/// Bit offsets in the input of the block type code, and the block count code and its extra bits.
+	pub bits: Range<usize>,
+	/// The category of the new block.
+	pub category: BlockCategory,
+	/// The type of the new block.
+	pub block_type: u16,
+	/// The number of symbols in the new block.
+	pub block_count: u32,
+}
+
+/// Ignores all commands, for decoding without a visitor.
+#[derive(Debug, Clone, Copy)]
+pub struct NoVisitor;
+
+impl CommandVisitor for NoVisitor {}
@@ -0,0 +1,354 @@
+use super::{ Reader, Woff2Error };
+
+const HEADER_LEN: usize = 36;
+
+/// Flags of simple glyph points.
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const REPEAT_FLAG: u8 = 0x08;
+const X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+/// Flags of composite glyph components.
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+/// The glyf and loca tables, as rebuilt from a transformed glyf table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyphs {
+	pub glyf: Vec<u8>,
+	pub loca: Vec<u8>,
+	/// xMin of every glyph, 0 for empty glyphs, for reversing the hmtx transform
+	pub x_mins: Vec<i16>,
+}
+
+/// Reverses the transform of the glyf table, and rebuilds the loca table from the glyph offsets.
+pub fn reconstruct(data: &[u8]) -> Result<Glyphs, Woff2Error> {
+	let mut header = Reader::new(data);
+
+	// reserved
+	header.read_u16()?;
+	let option_flags = header.read_u16()?;
+	let num_glyphs = header.read_u16()? as usize;
+	let index_format = header.read_u16()?;
+	let mut streams = Vec::with_capacity(7);
+	let mut offset = HEADER_LEN;
+
+	for _ in 0..7 {
+		let len = header.read_u32()? as usize;
+
+		if data.len() - offset < len {
+			return Err(Woff2Error::Truncated);
+		}
+
+		streams.push(Reader::new(&data[offset..offset + len]));
+		offset += len;
+	}
+
+	let mut instructions = streams.pop().unwrap();
+	let mut bboxes = streams.pop().unwrap();
+	let mut composites = streams.pop().unwrap();
+	let mut glyph_stream = streams.pop().unwrap();
+	let mut flags = streams.pop().unwrap();
+	let mut n_points = streams.pop().unwrap();
+	let mut n_contours = streams.pop().unwrap();
+
+	let bitmap_len = 4 * ((num_glyphs + 31) >> 5);
+	let bbox_bitmap = bboxes.take(bitmap_len)?;
+	let overlap_bitmap = match option_flags & 1 {
+		1 => Reader::new(&data[offset..]).take((num_glyphs + 7) >> 3)?,
+		_ => &[],
+	};
+	let has_bit = |bitmap: &[u8], i: usize| bitmap.get(i >> 3).is_some_and(|byte| byte & (0x80 >> (i & 7)) != 0);
+
+	let mut glyf = Vec::new();
+	let mut offsets = Vec::with_capacity(num_glyphs + 1);
+	let mut x_mins = Vec::with_capacity(num_glyphs);
+
+	for i in 0..num_glyphs {
+		offsets.push(glyf.len());
+
+		let contours = n_contours.read_i16()?;
+		let has_bbox = has_bit(bbox_bitmap, i);
+		let start = glyf.len();
+
+		match contours {
+			0 => {
+				if has_bbox {
+					return Err(Woff2Error::InvalidGlyfTable);
+				}
+
+				x_mins.push(0);
+
+				continue;
+			},
+			-1 => {
+				if !has_bbox {
+					return Err(Woff2Error::InvalidGlyfTable);
+				}
+
+				glyf.extend_from_slice(&(-1i16).to_be_bytes());
+				glyf.extend_from_slice(bboxes.take(8)?);
+
+				let mut have_instructions = false;
+
+				loop {
+					let component_flags = composites.read_u16()?;
+					// glyph index, arguments and transformation
+					let len = 2 + if component_flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 } + if component_flags & WE_HAVE_A_SCALE != 0 {
+						2
+					} else if component_flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+						4
+					} else if component_flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+						8
+					} else {
+						0
+					};
+
+					glyf.extend_from_slice(&component_flags.to_be_bytes());
+					glyf.extend_from_slice(composites.take(len)?);
+					have_instructions |= component_flags & WE_HAVE_INSTRUCTIONS != 0;
+
+					if component_flags & MORE_COMPONENTS == 0 {
+						break;
+					}
+				}
+
+				if have_instructions {
+					let len = glyph_stream.read_255_u16()?;
+
+					glyf.extend_from_slice(&len.to_be_bytes());
+					glyf.extend_from_slice(instructions.take(len as usize)?);
+				}
+			},
+			contours if contours > 0 => {
+				let mut end_points = Vec::with_capacity(contours as usize);
+				let mut num_points = 0usize;
+
+				for _ in 0..contours {
+					num_points += n_points.read_255_u16()? as usize;
+
+					if num_points == 0 || num_points > 0x1_0000 {
+						return Err(Woff2Error::InvalidGlyfTable);
+					}
+
+					end_points.push(num_points - 1);
+				}
+
+				let point_flags = flags.take(num_points)?;
+				let mut points = Vec::with_capacity(num_points);
+				let (mut x, mut y) = (0i32, 0i32);
+
+				for &flag in point_flags {
+					let (dx, dy) = decode_triplet(flag & 0x7f, &mut glyph_stream)?;
+
+					x += dx;
+					y += dy;
+					points.push((x, y, flag & 0x80 == 0));
+				}
+
+				let bbox = if has_bbox {
+					let bbox = bboxes.take(8)?;
+
+					[
+						i16::from_be_bytes([bbox[0], bbox[1]]), i16::from_be_bytes([bbox[2], bbox[3]]),
+						i16::from_be_bytes([bbox[4], bbox[5]]), i16::from_be_bytes([bbox[6], bbox[7]]),
+					]
+				} else {
+					[
+						points.iter().map(|p| p.0).min().unwrap() as i16, points.iter().map(|p| p.1).min().unwrap() as i16,
+						points.iter().map(|p| p.0).max().unwrap() as i16, points.iter().map(|p| p.1).max().unwrap() as i16,
+					]
+				};
+
+				glyf.extend_from_slice(&contours.to_be_bytes());
+				for value in &bbox {
+					glyf.extend_from_slice(&value.to_be_bytes());
+				}
+
+				for &end_point in &end_points {
+					glyf.extend_from_slice(&(end_point as u16).to_be_bytes());
+				}
+
+				let len = glyph_stream.read_255_u16()?;
+
+				glyf.extend_from_slice(&len.to_be_bytes());
+				glyf.extend_from_slice(instructions.take(len as usize)?);
+
+				write_points(&mut glyf, &points, has_bit(overlap_bitmap, i));
+			},
+			_ => return Err(Woff2Error::InvalidGlyfTable),
+		}
+
+		x_mins.push(i16::from_be_bytes([glyf[start + 2], glyf[start + 3]]));
+
+		// the loca table can only address even offsets in the short format
+		glyf.resize((glyf.len() + 3) & !3, 0);
+	}
+
+	offsets.push(glyf.len());
+
+	let loca = match index_format {
+		0 if glyf.len() < 0x2_0000 => offsets.iter().flat_map(|&offset| ((offset >> 1) as u16).to_be_bytes().to_vec()).collect(),
+		1 => offsets.iter().flat_map(|&offset| (offset as u32).to_be_bytes().to_vec()).collect(),
+		_ => return Err(Woff2Error::InvalidGlyfTable),
+	};
+
+	Ok(Glyphs{
+		glyf,
+		loca,
+		x_mins,
+	})
+}
+
+/// Decodes the coordinate deltas of a point from the glyph stream, as given by the lower seven bits of its flag.
+fn decode_triplet(flag: u8, glyph_stream: &mut Reader) -> Result<(i32, i32), Woff2Error> {
+	let with_sign = |flag: u8, value: i32| if flag & 1 == 0 { -value } else { value };
+	let flag_i = i32::from(flag);
+
+	Ok(match flag {
+		0..=9 => {
+			let b0 = i32::from(glyph_stream.read_u8()?);
+
+			(0, with_sign(flag, ((flag_i & 14) << 7) + b0))
+		},
+		10..=19 => {
+			let b0 = i32::from(glyph_stream.read_u8()?);
+
+			(with_sign(flag, (((flag_i - 10) & 14) << 7) + b0), 0)
+		},
+		20..=83 => {
+			let b0 = flag_i - 20;
+			let b1 = i32::from(glyph_stream.read_u8()?);
+
+			(with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)), with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)))
+		},
+		84..=119 => {
+			let b0 = flag_i - 84;
+			let bytes = glyph_stream.take(2)?;
+
+			(with_sign(flag, 1 + ((b0 / 12) << 8) + i32::from(bytes[0])), with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + i32::from(bytes[1])))
+		},
+		120..=123 => {
+			let bytes = glyph_stream.take(3)?;
+			let (b0, b1, b2) = (i32::from(bytes[0]), i32::from(bytes[1]), i32::from(bytes[2]));
+
+			(with_sign(flag, (b0 << 4) + (b1 >> 4)), with_sign(flag >> 1, ((b1 & 0x0f) << 8) + b2))
+		},
+		_ => {
+			let bytes = glyph_stream.take(4)?;
+
+			(with_sign(flag, i32::from(bytes[0]) << 8 | i32::from(bytes[1])), with_sign(flag >> 1, i32::from(bytes[2]) << 8 | i32::from(bytes[3])))
+		},
+	})
+}
+
+/// Writes the flags and the coordinates of the points of a simple glyph, with runs of equal flags repeated
+/// and coordinates as short vectors where possible.
+fn write_points(glyf: &mut Vec<u8>, points: &[(i32, i32, bool)], overlap: bool) {
+	let mut point_flags = Vec::with_capacity(points.len());
+	let mut xs = Vec::new();
+	let mut ys = Vec::new();
+	let (mut last_x, mut last_y) = (0, 0);
+
+	for &(x, y, on_curve) in points {
+		let mut flag = if on_curve { ON_CURVE_POINT } else { 0 };
+		let (dx, dy) = (x - last_x, y - last_y);
+
+		if point_flags.is_empty() && overlap {
+			flag |= OVERLAP_SIMPLE;
+		}
+
+		if dx == 0 {
+			flag |= X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR;
+		} else if dx.abs() < 256 {
+			flag |= X_SHORT_VECTOR | if dx > 0 { X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR } else { 0 };
+			xs.push(dx.unsigned_abs() as u8);
+		} else {
+			xs.extend_from_slice(&(dx as i16).to_be_bytes());
+		}
+
+		if dy == 0 {
+			flag |= Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR;
+		} else if dy.abs() < 256 {
+			flag |= Y_SHORT_VECTOR | if dy > 0 { Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR } else { 0 };
+			ys.push(dy.unsigned_abs() as u8);
+		} else {
+			ys.extend_from_slice(&(dy as i16).to_be_bytes());
+		}
+
+		point_flags.push(flag);
+		last_x = x;
+		last_y = y;
+	}
+
+	let mut i = 0;
+
+	while i < point_flags.len() {
+		let flag = point_flags[i];
+		let repeat = point_flags[i + 1..].iter().take(255).take_while(|&&f| f == flag).count();
+
+		if repeat > 0 {
+			glyf.push(flag | REPEAT_FLAG);
+			glyf.push(repeat as u8);
+		} else {
+			glyf.push(flag);
+		}
+
+		i += 1 + repeat;
+	}
+
+	glyf.extend_from_slice(&xs);
+	glyf.extend_from_slice(&ys);
+}
+
+mod tests {
+	#[test]
+	fn should_rebuild_simple_empty_and_composite_glyphs() {
+		use super::*;
+
+		let streams: [&[u8]; 7] = [
+			// contours: a triangle, an empty glyph, a composite glyph
+			&[0x00, 0x01, 0x00, 0x00, 0xff, 0xff],
+			// points
+			&[3],
+			// flags of the deltas (0, 300) on curve, (16, -16) on curve, (-16, -284) off curve
+			&[0x03, 0x15, 0x80 | 0x58],
+			// triplet data, then the instruction lengths of the triangle and of the composite glyph
+			&[0x2c, 0xff, 0x0f, 0x1b, 2, 1],
+			// a single component with byte offsets and instructions
+			&[0x01, 0x02, 0x00, 0x00, 0x05, 0xfb],
+			// bbox bitmap with the composite glyph, and its bbox
+			&[0x20, 0x00, 0x00, 0x00, 0xff, 0xfb, 0x00, 0x00, 0x00, 0x10, 0x01, 0x2c],
+			// instructions
+			&[0xb0, 0x01, 0x4b],
+		];
+		let mut data = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00];
+		for stream in &streams {
+			data.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+		}
+		for stream in &streams {
+			data.extend_from_slice(stream);
+		}
+
+		let glyphs = reconstruct(&data).unwrap();
+
+		assert_eq!(vec![
+			// one contour, bbox (0, 0)..(16, 300), end point 2, instructions
+			0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x01, 0x2c, 0x00, 0x02, 0x00, 0x02, 0xb0, 0x01,
+			// flags, x deltas, y deltas, padding
+			0x11, 0x17, 0x02, 0x10, 0x10, 0x01, 0x2c, 0x10, 0xfe, 0xe4, 0x00, 0x00,
+			// composite glyph, instructions, padding
+			0xff, 0xff, 0xff, 0xfb, 0x00, 0x00, 0x00, 0x10, 0x01, 0x2c, 0x01, 0x02, 0x00, 0x00, 0x05, 0xfb,
+			0x00, 0x01, 0x4b, 0x00,
+		], glyphs.glyf);
+		assert_eq!(vec![0x00, 0x00, 0x00, 0x0e, 0x00, 0x0e, 0x00, 0x18], glyphs.loca);
+		assert_eq!(vec![0, 0, -5], glyphs.x_mins);
+	}
+}
@@ -0,0 +1,45 @@
+use super::{ Reader, Woff2Error };
+
+/// The left side bearings of the proportional glyphs equal their xMin, and are omitted.
+const NO_PROPORTIONAL_LSBS: u8 = 0x01;
+/// The left side bearings of the monospaced glyphs equal their xMin, and are omitted.
+const NO_MONOSPACE_LSBS: u8 = 0x02;
+
+/// Reverses the transform of the hmtx table, with the number of advance widths from the hhea table,
+/// and the xMin of every glyph from the glyf table.
+pub fn reconstruct(data: &[u8], num_h_metrics: usize, x_mins: &[i16]) -> Result<Vec<u8>, Woff2Error> {
+	let mut r = Reader::new(data);
+	let flags = r.read_u8()?;
+	let num_glyphs = x_mins.len();
+
+	// without omitted left side bearings, the transform must not be applied
+	if flags & !(NO_PROPORTIONAL_LSBS | NO_MONOSPACE_LSBS) != 0 || flags == 0 || num_h_metrics == 0 || num_h_metrics > num_glyphs {
+		return Err(Woff2Error::InvalidHmtxTable);
+	}
+
+	let mut advance_widths = Vec::with_capacity(num_h_metrics);
+
+	for _ in 0..num_h_metrics {
+		advance_widths.push(r.read_u16()?);
+	}
+
+	let mut lsbs = Vec::with_capacity(num_glyphs);
+
+	for (i, &x_min) in x_mins.iter().enumerate() {
+		let omitted = if i < num_h_metrics { NO_PROPORTIONAL_LSBS } else { NO_MONOSPACE_LSBS };
+
+		lsbs.push(if flags & omitted != 0 { x_min } else { r.read_i16()? });
+	}
+
+	let mut hmtx = Vec::with_capacity(4 * num_h_metrics + 2 * (num_glyphs - num_h_metrics));
+
+	for (i, lsb) in lsbs.iter().enumerate() {
+		if i < num_h_metrics {
+			hmtx.extend_from_slice(&advance_widths[i].to_be_bytes());
+		}
+
+		hmtx.extend_from_slice(&lsb.to_be_bytes());
+	}
+
+	Ok(hmtx)
+}
@@ -0,0 +1,478 @@
+/// glyf reverses the transform of the glyf table, and rebuilds the loca table.
+mod glyf;
+/// hmtx reverses the transform of the hmtx table.
+mod hmtx;
+
+use ::Decompressor;
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{ Display, Formatter };
+use std::io::Read;
+
+/// "wOF2"
+const SIGNATURE: u32 = 0x774f_4632;
+/// "ttcf", the flavor of font collections
+const COLLECTION_FLAVOR: u32 = 0x7474_6366;
+const SFNT_HEADER_LEN: usize = 12;
+const TABLE_RECORD_LEN: usize = 16;
+/// Offset of checkSumAdjustment within the head table.
+const CHECKSUM_ADJUSTMENT_OFFSET: usize = 8;
+const CHECKSUM_MAGIC: u32 = 0xb1b0_afba;
+/// Offset of numberOfHMetrics within the hhea table.
+const NUMBER_OF_H_METRICS_OFFSET: usize = 34;
+/// Maximum total length of the tables in the brotli stream, the limit of the reference decoder.
+const MAX_TABLE_DATA_LEN: usize = 30 << 20;
+
+/// Tags of the table directory entries whose flags hold an index into this list, instead of 63.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+	b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post",
+	b"cvt ", b"fpgm", b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT",
+	b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea",
+	b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH",
+	b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+	b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar",
+	b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop",
+	b"trak", b"Zapf", b"Silf", b"Glat", b"Gloc", b"Feat", b"Sill",
+];
+
+/// An entry of the table directory.
+#[derive(Debug, Clone, PartialEq)]
+struct Table {
+	tag: [u8; 4],
+	/// transform version, 0 means transformed for glyf and loca, and not transformed otherwise
+	version: u8,
+	orig_length: usize,
+	/// offset of the table data within the decompressed stream
+	offset: usize,
+	/// length of the table data within the decompressed stream
+	length: usize,
+}
+
+impl Table {
+	fn is_transformed(&self) -> bool {
+		match &self.tag {
+			b"glyf" | b"loca" => self.version != 3,
+			_ => self.version != 0,
+		}
+	}
+}
+
+/// A font of the WOFF2 file, as a flavor and indices into the table directory.
+#[derive(Debug, Clone, PartialEq)]
+struct Font {
+	flavor: u32,
+	tables: Vec<usize>,
+}
+
+/// Converts a WOFF2 file into the TrueType or OpenType font, or font collection, that it was encoded from.
+///
+/// The tables are decompressed from the brotli stream, the transforms of the glyf, loca and hmtx tables
+/// are reversed, and the tables are written into an sfnt file with recomputed table checksums and
+/// checksum adjustment. The extended metadata and private data blocks are not part of the font, and are
+/// ignored.
+///
+/// # Examples
+/// ```
+/// let woff2 = std::fs::read("data/open-sans-regular.woff2").unwrap();
+/// let sfnt = brotli::woff2_to_sfnt(&woff2).unwrap();
+///
+/// // the TrueType flavor
+/// assert_eq!([0x00, 0x01, 0x00, 0x00], sfnt[..4]);
+/// ```
+pub fn woff2_to_sfnt(woff2: &[u8]) -> Result<Vec<u8>, Woff2Error> {
+	let mut r = Reader::new(woff2);
+
+	if r.read_u32()? != SIGNATURE {
+		return Err(Woff2Error::InvalidSignature);
+	}
+
+	let flavor = r.read_u32()?;
+	let length = r.read_u32()? as usize;
+	let num_tables = r.read_u16()? as usize;
+
+	if length > woff2.len() {
+		return Err(Woff2Error::Truncated);
+	}
+
+	if length < woff2.len() || num_tables == 0 || r.read_u16()? != 0 {
+		return Err(Woff2Error::InvalidTableDirectory);
+	}
+
+	// totalSfntSize, only a hint
+	r.read_u32()?;
+	let total_compressed_size = r.read_u32()? as usize;
+	// major and minor version, metadata and private data block
+	r.take(24)?;
+
+	let mut tables = Vec::with_capacity(num_tables);
+	let mut offset = 0;
+
+	for _ in 0..num_tables {
+		let flags = r.read_u8()?;
+		let tag = match (flags & 0x3f) as usize {
+			63 => {
+				let mut tag = [0; 4];
+				tag.copy_from_slice(r.take(4)?);
+
+				tag
+			},
+			i => *KNOWN_TAGS[i],
+		};
+		let mut table = Table{
+			tag,
+			version: flags >> 6,
+			orig_length: r.read_base128()? as usize,
+			offset,
+			length: 0,
+		};
+
+		table.length = match (&table.tag, table.version) {
+			_ if !table.is_transformed() => table.orig_length,
+			(b"glyf", 0) | (b"hmtx", 1) => r.read_base128()? as usize,
+			// the loca table is rebuilt from the glyf table
+			(b"loca", 0) => match r.read_base128()? {
+				0 => 0,
+				_ => return Err(Woff2Error::InvalidTableDirectory),
+			},
+			_ => return Err(Woff2Error::InvalidTableDirectory),
+		};
+
+		// the lengths are untrusted, and the tables are decompressed into memory
+		offset = match offset.checked_add(table.length) {
+			Some(offset) if offset <= MAX_TABLE_DATA_LEN => offset,
+			_ => return Err(Woff2Error::InvalidTableDirectory),
+		};
+		tables.push(table);
+	}
+
+	let (collection_version, fonts) = if flavor == COLLECTION_FLAVOR {
+		let version = r.read_u32()?;
+		let num_fonts = r.read_255_u16()? as usize;
+		let mut fonts = Vec::with_capacity(num_fonts);
+
+		if num_fonts == 0 || (version != 0x0001_0000 && version != 0x0002_0000) {
+			return Err(Woff2Error::InvalidTableDirectory);
+		}
+
+		for _ in 0..num_fonts {
+			let num_font_tables = r.read_255_u16()? as usize;
+			let mut font = Font{
+				flavor: r.read_u32()?,
+				tables: Vec::with_capacity(num_font_tables),
+			};
+
+			for _ in 0..num_font_tables {
+				match r.read_255_u16()? as usize {
+					i if i < num_tables => font.tables.push(i),
+					_ => return Err(Woff2Error::InvalidTableDirectory),
+				}
+			}
+
+			fonts.push(font);
+		}
+
+		(Some(version), fonts)
+	} else {
+		(None, vec![Font{
+			flavor,
+			tables: (0..num_tables).collect(),
+		}])
+	};
+
+	let mut data = Vec::new();
+
+	match Decompressor::new(r.take(total_compressed_size)?).take(offset as u64 + 1).read_to_end(&mut data) {
+		Ok(len) if len == offset => {},
+		_ => return Err(Woff2Error::InvalidBrotliStream),
+	}
+
+	let table_data = reconstruct(&tables, &fonts, &data)?;
+
+	Ok(write_sfnt(collection_version, &tables, &fonts, &table_data))
+}
+
+/// Returns the data of every table of the directory, with the transforms reversed.
+fn reconstruct(tables: &[Table], fonts: &[Font], data: &[u8]) -> Result<Vec<Vec<u8>>, Woff2Error> {
+	let mut table_data = vec![None; tables.len()];
+
+	for (i, table) in tables.iter().enumerate() {
+		if !table.is_transformed() {
+			table_data[i] = Some(data[table.offset..table.offset + table.length].to_vec());
+		}
+	}
+
+	for font in fonts {
+		let find = |tag: &[u8; 4]| font.tables.iter().cloned().find(|&i| &tables[i].tag == tag);
+		let (glyf, loca, hmtx) = (find(b"glyf"), find(b"loca"), find(b"hmtx"));
+		let glyf_transformed = glyf.is_some_and(|i| tables[i].is_transformed());
+
+		if glyf_transformed != loca.is_some_and(|i| tables[i].is_transformed()) {
+			return Err(Woff2Error::InvalidGlyfTable);
+		}
+
+		let x_mins = match (glyf, loca) {
+			(Some(glyf), Some(loca)) if glyf_transformed => {
+				let glyphs = glyf::reconstruct(&data[tables[glyf].offset..tables[glyf].offset + tables[glyf].length])?;
+
+				if glyphs.loca.len() != tables[loca].orig_length {
+					return Err(Woff2Error::InvalidGlyfTable);
+				}
+
+				table_data[glyf] = Some(glyphs.glyf);
+				table_data[loca] = Some(glyphs.loca);
+
+				Some(glyphs.x_mins)
+			},
+			_ => None,
+		};
+
+		match hmtx {
+			Some(hmtx) if tables[hmtx].is_transformed() => {
+				let hhea = match find(b"hhea") {
+					Some(hhea) => &data[tables[hhea].offset..tables[hhea].offset + tables[hhea].length],
+					None => return Err(Woff2Error::InvalidHmtxTable),
+				};
+
+				if hhea.len() < NUMBER_OF_H_METRICS_OFFSET + 2 {
+					return Err(Woff2Error::InvalidHmtxTable);
+				}
+
+				let num_h_metrics = u16::from_be_bytes([hhea[NUMBER_OF_H_METRICS_OFFSET], hhea[NUMBER_OF_H_METRICS_OFFSET + 1]]) as usize;
+				let x_mins = match x_mins {
+					Some(ref x_mins) => x_mins,
+					None => return Err(Woff2Error::InvalidHmtxTable),
+				};
+
+				table_data[hmtx] = Some(hmtx::reconstruct(&data[tables[hmtx].offset..tables[hmtx].offset + tables[hmtx].length], num_h_metrics, x_mins)?);
+			},
+			_ => {},
+		}
+	}
+
+	// transformed tables that are not part of any font, or whose counterpart is missing
+	table_data.into_iter().map(|data| data.ok_or(Woff2Error::InvalidTableDirectory)).collect()
+}
+
+/// Writes the tables into a font file, or into a font collection file with version, and returns it.
+fn write_sfnt(collection_version: Option<u32>, tables: &[Table], fonts: &[Font], table_data: &[Vec<u8>]) -> Vec<u8> {
+	let mut sfnt = Vec::new();
+
+	if let Some(version) = collection_version {
+		sfnt.extend_from_slice(&COLLECTION_FLAVOR.to_be_bytes());
+		sfnt.extend_from_slice(&version.to_be_bytes());
+		sfnt.extend_from_slice(&(fonts.len() as u32).to_be_bytes());
+		// offsets of the table directories, and no DSIG table with version 2
+		sfnt.resize(sfnt.len() + 4 * fonts.len() + if version == 0x0002_0000 { 12 } else { 0 }, 0);
+	}
+
+	let directory_offsets = fonts.iter().map(|font| {
+		let offset = sfnt.len();
+
+		sfnt.resize(offset + SFNT_HEADER_LEN + TABLE_RECORD_LEN * font.tables.len(), 0);
+
+		offset
+	}).collect::<Vec<_>>();
+
+	// table data follows the table directories, in the order of the table directory of the WOFF2 file
+	let mut table_offsets = vec![0; tables.len()];
+	let mut checksums = vec![0; tables.len()];
+
+	for (i, data) in table_data.iter().enumerate() {
+		if !fonts.iter().any(|font| font.tables.contains(&i)) {
+			continue;
+		}
+
+		let mut data = data.clone();
+
+		if &tables[i].tag == b"head" && data.len() >= CHECKSUM_ADJUSTMENT_OFFSET + 4 {
+			data[CHECKSUM_ADJUSTMENT_OFFSET..CHECKSUM_ADJUSTMENT_OFFSET + 4].copy_from_slice(&[0; 4]);
+		}
+
+		table_offsets[i] = sfnt.len();
+		checksums[i] = checksum(&data);
+		sfnt.extend_from_slice(&data);
+		sfnt.resize((sfnt.len() + 3) & !3, 0);
+	}
+
+	for (n, (font, &offset)) in fonts.iter().zip(directory_offsets.iter()).enumerate() {
+		let mut sorted = font.tables.clone();
+		sorted.sort_by_key(|&i| tables[i].tag);
+
+		let num_tables = sorted.len();
+		let entry_selector = (0..16).rev().find(|&bits| 1 << bits <= num_tables).unwrap_or(0);
+		let search_range = (1 << entry_selector) * TABLE_RECORD_LEN;
+		let mut directory = Vec::with_capacity(SFNT_HEADER_LEN + TABLE_RECORD_LEN * num_tables);
+
+		directory.extend_from_slice(&font.flavor.to_be_bytes());
+		for &value in &[num_tables, search_range, entry_selector, num_tables * TABLE_RECORD_LEN - search_range] {
+			directory.extend_from_slice(&(value as u16).to_be_bytes());
+		}
+
+		for &i in &sorted {
+			directory.extend_from_slice(&tables[i].tag);
+			directory.extend_from_slice(&checksums[i].to_be_bytes());
+			directory.extend_from_slice(&(table_offsets[i] as u32).to_be_bytes());
+			directory.extend_from_slice(&(table_data[i].len() as u32).to_be_bytes());
+		}
+
+		sfnt[offset..offset + directory.len()].copy_from_slice(&directory);
+
+		if collection_version.is_some() {
+			let entry = SFNT_HEADER_LEN + 4 * n;
+
+			sfnt[entry..entry + 4].copy_from_slice(&(offset as u32).to_be_bytes());
+		}
+
+		// the font checksum equals the checksum of the whole file for a single font
+		if let Some(head) = sorted.iter().cloned().find(|&i| &tables[i].tag == b"head" && table_data[i].len() >= CHECKSUM_ADJUSTMENT_OFFSET + 4) {
+			let font_checksum = sorted.iter().fold(checksum(&directory), |sum, &i| sum.wrapping_add(checksums[i]));
+			let adjustment = table_offsets[head] + CHECKSUM_ADJUSTMENT_OFFSET;
+
+			sfnt[adjustment..adjustment + 4].copy_from_slice(&CHECKSUM_MAGIC.wrapping_sub(font_checksum).to_be_bytes());
+		}
+	}
+
+	sfnt
+}
+
+/// Returns the sum of the big-endian 32-bit words of data, padded with zeros.
+fn checksum(data: &[u8]) -> u32 {
+	data.chunks(4).fold(0u32, |sum, chunk| {
+		let mut word = [0; 4];
+		word[..chunk.len()].copy_from_slice(chunk);
+
+		sum.wrapping_add(u32::from_be_bytes(word))
+	})
+}
+
+/// Reads the big-endian fields of a WOFF2 file and of its transformed tables.
+struct Reader<'a> {
+	buf: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn new(buf: &'a [u8]) -> Reader<'a> {
+		Reader{
+			buf,
+			pos: 0,
+		}
+	}
+
+	fn take(&mut self, n: usize) -> Result<&'a [u8], Woff2Error> {
+		if self.buf.len() - self.pos < n {
+			return Err(Woff2Error::Truncated);
+		}
+
+		self.pos += n;
+
+		Ok(&self.buf[self.pos - n..self.pos])
+	}
+
+	fn read_u8(&mut self) -> Result<u8, Woff2Error> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn read_u16(&mut self) -> Result<u16, Woff2Error> {
+		let bytes = self.take(2)?;
+
+		Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+	}
+
+	fn read_i16(&mut self) -> Result<i16, Woff2Error> {
+		Ok(self.read_u16()? as i16)
+	}
+
+	fn read_u32(&mut self) -> Result<u32, Woff2Error> {
+		let bytes = self.take(4)?;
+
+		Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+
+	/// Reads a UIntBase128, a big-endian base 128 number of up to 32 bits without leading zeros.
+	fn read_base128(&mut self) -> Result<u32, Woff2Error> {
+		let mut value = 0u32;
+
+		for i in 0..5 {
+			let byte = self.read_u8()?;
+
+			if (i == 0 && byte == 0x80) || value & 0xfe00_0000 != 0 {
+				return Err(Woff2Error::InvalidTableDirectory);
+			}
+
+			value = (value << 7) | u32::from(byte & 0x7f);
+
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+
+		Err(Woff2Error::InvalidTableDirectory)
+	}
+
+	/// Reads a 255UInt16, a 16-bit number in one to three bytes.
+	fn read_255_u16(&mut self) -> Result<u16, Woff2Error> {
+		match self.read_u8()? {
+			253 => self.read_u16(),
+			254 => Ok(u16::from(self.read_u8()?) + 2 * 253),
+			255 => Ok(u16::from(self.read_u8()?) + 253),
+			code => Ok(u16::from(code)),
+		}
+	}
+}
+
+/// Error types that can be returned while converting a WOFF2 file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Woff2Error {
+	/// The data does not start with the WOFF2 signature.
+	InvalidSignature,
+	/// The WOFF2 file, or one of its tables, ended prematurely.
+	Truncated,
+	/// The header, the table directory or the collection directory contained an invalid value.
+	InvalidTableDirectory,
+	/// The compressed font data was not a valid brotli stream, or did not match the table lengths.
+	InvalidBrotliStream,
+	/// The transformed glyf or loca table was malformed.
+	InvalidGlyfTable,
+	/// The transformed hmtx table was malformed, or the tables it depends on were missing.
+	InvalidHmtxTable,
+}
+
+impl Display for Woff2Error {
+	fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+		fmt.write_str(match *self {
+			Woff2Error::InvalidSignature => "WOFF2 file did not start with the expected signature",
+			Woff2Error::Truncated => "WOFF2 file ended prematurely",
+			Woff2Error::InvalidTableDirectory => "WOFF2 file contained an invalid table directory",
+			Woff2Error::InvalidBrotliStream => "WOFF2 file contained invalid compressed font data",
+			Woff2Error::InvalidGlyfTable => "WOFF2 file contained an invalid transformed glyf table",
+			Woff2Error::InvalidHmtxTable => "WOFF2 file contained an invalid transformed hmtx table",
+		})
+	}
+}
+
+impl Error for Woff2Error {}
+
+mod tests {
+	#[test]
+	fn should_read_variable_length_numbers() {
+		use super::*;
+
+		let mut r = Reader::new(&[0x3f, 0x81, 0x00, 0x8f, 0xff, 0xff, 0xff, 0x7f, 0x80, 0x01]);
+
+		assert_eq!(Ok(63), r.read_base128());
+		assert_eq!(Ok(128), r.read_base128());
+		assert_eq!(Ok(u32::MAX), r.read_base128());
+		// leading zeros
+		assert_eq!(Err(Woff2Error::InvalidTableDirectory), r.read_base128());
+
+		let mut r = Reader::new(&[252, 255, 253, 254, 0, 253, 0x12, 0x34]);
+
+		assert_eq!(Ok(252), r.read_255_u16());
+		assert_eq!(Ok(506), r.read_255_u16());
+		assert_eq!(Ok(506), r.read_255_u16());
+		assert_eq!(Ok(0x1234), r.read_255_u16());
+		assert_eq!(Err(Woff2Error::Truncated), r.read_255_u16());
+	}
+}
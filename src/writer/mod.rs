@@ -0,0 +1,161 @@
+use ::{ Decompressor, DecompressorError, State };
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::{ Read, Write };
+
+/// Compressed bytes that have been written to a DecompressorWriter, but not yet decoded.
+#[derive(Debug)]
+struct PendingInput {
+	buf: VecDeque<u8>,
+	/// set when a read found no more bytes
+	exhausted: bool,
+}
+
+impl Read for PendingInput {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = {
+			let (front, back) = self.buf.as_slices();
+			let n_front = front.len().min(buf.len());
+			let n_back = back.len().min(buf.len() - n_front);
+
+			buf[0..n_front].copy_from_slice(&front[0..n_front]);
+			buf[n_front..n_front + n_back].copy_from_slice(&back[0..n_back]);
+
+			n_front + n_back
+		};
+
+		self.buf.drain(0..n);
+
+		if n == 0 && !buf.is_empty() {
+			self.exhausted = true;
+		}
+
+		Ok(n)
+	}
+}
+
+/// Wraps an output stream and decompresses the brotli stream that is written to it.
+///
+/// Every call to write() decodes as much of the compressed input as possible, and writes the
+/// decompressed bytes to the wrapped output stream. A step of the decoder that runs out of input
+/// is retried once more input has been written. finish() checks that the stream ended properly.
+///
+/// # Examples
+/// ```
+/// use std::io::{ Read, Write };
+/// use brotli::DecompressorWriter;
+///
+/// let mut brotli_stream = Vec::new();
+/// std::fs::File::open("data/64x.compressed").unwrap().read_to_end(&mut brotli_stream).unwrap();
+///
+/// let mut decompressor = DecompressorWriter::new(Vec::new());
+///
+/// for chunk in brotli_stream.chunks(3) {
+///     decompressor.write_all(chunk).unwrap();
+/// }
+///
+/// let decompressed = decompressor.finish().unwrap();
+///
+/// assert_eq!(vec![b'X'; 64], decompressed);
+/// ```
+#[derive(Debug)]
+pub struct DecompressorWriter<W: Write> {
+	decompressor: Decompressor<PendingInput>,
+	inner: W,
+	/// number of pending input bytes required before retrying the step that ran out of input,
+	/// it grows with the number of bytes that the failed attempt read, so that long steps
+	/// do not get retried for every small write
+	needed: usize,
+}
+
+impl<W: Write> DecompressorWriter<W> {
+	/// Creates DecompressorWriter from Write, which receives the decompressed stream.
+	pub fn new(w: W) -> DecompressorWriter<W> {
+		let mut decompressor = Decompressor::new(PendingInput{
+			buf: VecDeque::new(),
+			exhausted: false,
+		});
+		decompressor.rewindable = true;
+
+		DecompressorWriter{
+			decompressor,
+			inner: w,
+			needed: 0,
+		}
+	}
+
+	/// Gets a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		&self.inner
+	}
+
+	/// Gets a mutable reference to the underlying writer.
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.inner
+	}
+
+	/// Decodes the remaining input, and checks that the brotli stream ended with an ISLAST meta-block,
+	/// followed by nothing but zero fill bits.
+	/// Returns the underlying writer, after flushing it.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.decompress(true)?;
+
+		if self.decompressor.state != State::StreamEnd {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, DecompressorError::UnexpectedEOF.to_string()));
+		}
+
+		self.inner.flush()?;
+
+		Ok(self.inner)
+	}
+
+	/// Decodes as much of the pending input as possible. Unless finishing, a step that runs
+	/// out of input gets rewound, to be retried after the next write.
+	fn decompress(&mut self, finishing: bool) -> io::Result<()> {
+		loop {
+			self.decompressor.in_stream.get_mut().exhausted = false;
+
+			// an empty output slice makes decompress() put all decoded bytes into decompressor.buf,
+			// where they are kept, even if a later step fails
+			let result = self.decompressor.decompress(&mut []);
+
+			{
+				let (front, back) = self.decompressor.buf.as_slices();
+				self.inner.write_all(front)?;
+				self.inner.write_all(back)?;
+			}
+			self.decompressor.buf.clear();
+
+			match result {
+				Ok(_) if self.decompressor.state == State::StreamEnd => return Ok(()),
+				Ok(_) => {},
+				Err(_) if !finishing && self.decompressor.in_stream.get_ref().exhausted => {
+					self.needed = self.decompressor.in_stream.len_since_mark();
+					self.decompressor.rewind_step();
+
+					return Ok(());
+				},
+				Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+			}
+		}
+	}
+}
+
+impl<W: Write> Write for DecompressorWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let input = self.decompressor.in_stream.get_mut();
+		input.buf.extend(buf);
+
+		if input.buf.len() >= self.needed {
+			self.needed = 0;
+			self.decompress(false)?;
+		}
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
@@ -673,3 +673,1257 @@ fn should_compose_to_identity() {
 }
 
 
+
+#[test]
+/// Progress callback
+/// gets called at the end of every meta-block, the last report covers the whole stream
+fn should_report_progress_per_meta_block() {
+	use std::io::Read;
+	use std::sync::{ Arc, Mutex };
+	use brotli::{ Decompressor, Progress };
+
+	let reports: Arc<Mutex<Vec<Progress>>> = Arc::new(Mutex::new(Vec::new()));
+	let sink = reports.clone();
+
+	let brotli_stream = std::fs::File::open("data/metablock_reset.compressed").unwrap();
+	let mut decompressor = Decompressor::new(brotli_stream);
+	decompressor.set_progress_callback(None, move |progress| sink.lock().unwrap().push(*progress));
+
+	let mut decompressed = Vec::new();
+	let _ = decompressor.read_to_end(&mut decompressed);
+
+	let reports = reports.lock().unwrap();
+	let compressed_len = std::fs::metadata("data/metablock_reset.compressed").unwrap().len() as usize;
+	let last = reports.last().unwrap();
+
+	assert!(reports.len() > 1);
+	assert_eq!(decompressed.len(), last.decompressed_bytes);
+	assert_eq!(compressed_len, last.compressed_bytes);
+	assert_eq!(reports.len() - 1, last.meta_block);
+
+	for (i, progress) in reports.iter().enumerate() {
+		assert_eq!(i, progress.meta_block);
+	}
+}
+
+#[test]
+/// Progress callback
+/// gets called additionally every time the given number of bytes has been decompressed
+fn should_report_progress_every_n_bytes() {
+	use std::io::Read;
+	use std::sync::{ Arc, Mutex };
+	use brotli::{ Decompressor, Progress };
+
+	let reports: Arc<Mutex<Vec<Progress>>> = Arc::new(Mutex::new(Vec::new()));
+	let sink = reports.clone();
+
+	let brotli_stream = std::fs::File::open("data/alice29.txt.compressed").unwrap();
+	let mut decompressor = Decompressor::new(brotli_stream);
+	decompressor.set_progress_callback(Some(10000), move |progress| sink.lock().unwrap().push(*progress));
+
+	let mut decompressed = Vec::new();
+	let _ = decompressor.read_to_end(&mut decompressed);
+
+	let reports = reports.lock().unwrap();
+
+	assert!(reports.len() >= decompressed.len() / 10000);
+	assert_eq!(decompressed.len(), reports.last().unwrap().decompressed_bytes);
+
+	for pair in reports.windows(2) {
+		assert!(pair[0].decompressed_bytes <= pair[1].decompressed_bytes);
+		assert!(pair[0].compressed_bytes <= pair[1].compressed_bytes);
+	}
+}
+
+#[test]
+/// Snapshot
+/// taken between reads, restored with a reader positioned at the saved offset, continues with identical output
+/// (the restored decompressor gets compared on the next 30000 bytes, to keep the test linear in the file size)
+fn should_resume_from_snapshot() {
+	use std::cmp;
+	use std::io::{ Read, Seek, SeekFrom };
+	use brotli::{ Decompressor, Snapshot };
+
+	for name in &["alice29.txt", "metablock_reset", "random_org_10k.bin", "ukkonooa"] {
+		let mut expected = Vec::new();
+		let _ = std::fs::File::open(format!("data/{}", name)).unwrap().read_to_end(&mut expected);
+
+		let mut decompressor = Decompressor::new(std::fs::File::open(format!("data/{}.compressed", name)).unwrap());
+		let mut decompressed = Vec::new();
+		let mut buf = [0u8; 9973];
+
+		loop {
+			let snapshot = Snapshot::from_bytes(decompressor.snapshot().unwrap().as_bytes().to_vec()).unwrap();
+
+			let mut brotli_stream = std::fs::File::open(format!("data/{}.compressed", name)).unwrap();
+			brotli_stream.seek(SeekFrom::Start(snapshot.input_offset())).unwrap();
+
+			let mut rest = Vec::new();
+			Decompressor::from_snapshot(brotli_stream, &snapshot).unwrap().take(30000).read_to_end(&mut rest).unwrap();
+
+			assert_eq!(expected[decompressed.len()..cmp::min(decompressed.len() + 30000, expected.len())], rest[..]);
+
+			match decompressor.read(&mut buf).unwrap() {
+				0 => break,
+				n => decompressed.extend_from_slice(&buf[0..n]),
+			}
+		}
+
+		assert_eq!(expected, decompressed);
+	}
+}
+
+#[test]
+/// Snapshot
+/// with a flipped byte gets rejected by its checksum
+fn should_reject_corrupted_snapshot() {
+	use std::io::Read;
+	use brotli::{ Decompressor, Snapshot };
+
+	let mut decompressor = Decompressor::new(std::fs::File::open("data/alice29.txt.compressed").unwrap());
+	let _ = decompressor.read(&mut [0u8; 1000]);
+
+	let mut bytes = decompressor.snapshot().unwrap().as_bytes().to_vec();
+	let len = bytes.len();
+	bytes[len / 2] ^= 0x01;
+
+	assert!(Snapshot::from_bytes(bytes).is_err());
+	assert!(Snapshot::from_bytes(b"BRSN".to_vec()).is_err());
+}
+
+#[test]
+/// Decompressor
+/// with a prefix dictionary, resolves backward references into the dictionary, also after resuming from a snapshot
+fn should_resolve_references_into_prefix_dictionary() {
+	use std::io::Read;
+	use brotli::{ Decompressor, Snapshot };
+
+	// "World", as a single copy of 5 bytes from 6 bytes back
+	let brotli_stream = [0x1b, 0x04, 0x00, 0x00, 0x20, 0x00, 0x62, 0x90, 0x38, 0x00];
+
+	let mut decompressed = Vec::new();
+	Decompressor::with_prefix_dictionary(&brotli_stream[..], b"Hello, World!").read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(b"World".to_vec(), decompressed);
+
+	// without the dictionary, the distance refers to a word of the static dictionary
+	let mut decompressed = Vec::new();
+	Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+	assert!(b"World".to_vec() != decompressed);
+
+	let decompressor = Decompressor::with_prefix_dictionary(&brotli_stream[..], b"Hello, World!");
+	let snapshot = Snapshot::from_bytes(decompressor.snapshot().unwrap().as_bytes().to_vec()).unwrap();
+
+	let mut decompressed = Vec::new();
+	Decompressor::from_snapshot(&brotli_stream[snapshot.input_offset() as usize..], &snapshot).unwrap().read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(b"World".to_vec(), decompressed);
+}
+
+#[test]
+/// Shared dictionary
+/// Resolves references beyond the window against the LZ77 dictionary and the word lists of a shared dictionary.
+fn should_resolve_references_into_shared_dictionary() {
+	use std::io::Read;
+	use brotli::{ Decompressor, SharedDictionary, SharedDictionaryError, Snapshot };
+
+	// a single copy of 5 bytes from 6 bytes back
+	let brotli_stream = [0x1b, 0x04, 0x00, 0x00, 0x20, 0x00, 0x62, 0x90, 0x38, 0x00];
+
+	// two word lists with 2^3 words of length 5, and the built-in transforms
+	let mut serialized = vec![0x91, 0x00, 0x00, 0x02];
+
+	for words in &[b"zero_one__two__threefour_five_six__seven", b"null_eins_zwei_drei_vier_fuenfsechssiebn"] {
+		serialized.extend_from_slice(&[0x00, 0x03]);
+		serialized.extend_from_slice(&[0; 26]);
+		serialized.extend_from_slice(&words[..]);
+	}
+
+	serialized.push(0x00);
+	// the second word list for the context of the first literal, the first one otherwise
+	serialized.extend_from_slice(&[0x02, 0x00, 0x00, 0x01, 0x00, 0x01, 0x01]);
+	serialized.extend_from_slice(&[0x00; 63]);
+
+	let dictionary = SharedDictionary::from_bytes(&serialized).unwrap();
+
+	let mut decompressed = Vec::new();
+	Decompressor::with_shared_dictionary(&brotli_stream[..], &dictionary).read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(b"fuenf".to_vec(), decompressed);
+
+	let decompressor = Decompressor::with_shared_dictionary(&brotli_stream[..], &dictionary);
+	let snapshot = Snapshot::from_bytes(decompressor.snapshot().unwrap().as_bytes().to_vec()).unwrap();
+
+	let mut decompressed = Vec::new();
+	Decompressor::from_snapshot(&brotli_stream[snapshot.input_offset() as usize..], &snapshot).unwrap().read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(b"fuenf".to_vec(), decompressed);
+
+	// an LZ77 dictionary and the built-in lists
+	let mut serialized = vec![0x91, 0x00, 0x0d];
+	serialized.extend_from_slice(b"Hello, World!");
+	serialized.extend_from_slice(&[0x00, 0x00]);
+
+	let dictionary = SharedDictionary::from_bytes(&serialized).unwrap();
+
+	let mut decompressed = Vec::new();
+	Decompressor::with_shared_dictionary(&brotli_stream[..], &dictionary).read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(b"World".to_vec(), decompressed);
+
+	assert_eq!(Err(SharedDictionaryError::Truncated), SharedDictionary::from_bytes(&serialized[..10]));
+}
+
+#[test]
+/// Dictionary-compressed brotli
+/// Decodes a dcb stream against the dictionary that its header identifies, and rejects mismatching dictionaries.
+fn should_decode_dcb_stream_with_resolved_dictionary() {
+	use std::collections::HashMap;
+	use std::io::{ ErrorKind, Read };
+	use brotli::DcbDecoder;
+
+	let hash = [
+		0xdf, 0xfd, 0x60, 0x21, 0xbb, 0x2b, 0xd5, 0xb0, 0xaf, 0x67, 0x62, 0x90, 0x80, 0x9e, 0xc3, 0xa5,
+		0x31, 0x91, 0xdd, 0x81, 0xc7, 0xf7, 0x0a, 0x4b, 0x28, 0x68, 0x8a, 0x36, 0x21, 0x82, 0x98, 0x6f,
+	];
+	let mut dcb_stream = vec![0xff, 0x44, 0x43, 0x42];
+	dcb_stream.extend_from_slice(&hash);
+	dcb_stream.extend_from_slice(&[0x1b, 0x04, 0x00, 0x00, 0x20, 0x00, 0x62, 0x90, 0x38, 0x00]);
+
+	let mut dictionaries = HashMap::new();
+	dictionaries.insert(hash, b"Hello, World!".to_vec());
+
+	let mut resolver = |hash: &[u8; 32]| dictionaries.get(hash).cloned();
+	let mut decoder = DcbDecoder::new(&dcb_stream[..], &mut resolver).unwrap();
+
+	assert_eq!(&hash, decoder.dictionary_hash());
+
+	let mut decompressed = Vec::new();
+	decoder.read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(b"World".to_vec(), decompressed);
+
+	let mut wrong_dictionary = |_: &[u8; 32]| Some(b"Hello, world!".to_vec());
+	assert_eq!(ErrorKind::InvalidData, DcbDecoder::new(&dcb_stream[..], &mut wrong_dictionary).unwrap_err().kind());
+
+	let mut no_dictionary = |_: &[u8; 32]| None;
+	assert_eq!(ErrorKind::NotFound, DcbDecoder::new(&dcb_stream[..], &mut no_dictionary).unwrap_err().kind());
+
+	assert_eq!(ErrorKind::InvalidData, DcbDecoder::new(&dcb_stream[1..], &mut resolver).unwrap_err().kind());
+	assert_eq!(ErrorKind::UnexpectedEof, DcbDecoder::new(&dcb_stream[..20], &mut resolver).unwrap_err().kind());
+}
+
+#[test]
+/// WOFF2
+/// Converts a web font with transformed glyf, loca and hmtx tables into its TrueType font.
+fn should_convert_woff2_font_to_sfnt() {
+	use brotli::{ woff2_to_sfnt, Woff2Error };
+
+	let woff2 = std::fs::read("data/open-sans-regular.woff2").unwrap();
+	let sfnt = woff2_to_sfnt(&woff2).unwrap();
+
+	// the font as the reconstruction of the reference decoder writes it, with totalSfntSize bytes
+	assert_eq!(std::fs::read("data/open-sans-regular.ttf").unwrap(), sfnt);
+	assert_eq!(93908, sfnt.len());
+
+	// with the checksum adjustment in place, the checksum of the whole font is a constant
+	let checksum = sfnt.chunks(4).fold(0u32, |sum, word| sum.wrapping_add(u32::from_be_bytes([word[0], word[1], word[2], word[3]])));
+
+	assert_eq!(0xb1b0_afba, checksum);
+
+	assert_eq!(Err(Woff2Error::InvalidSignature), woff2_to_sfnt(b"wOFF"));
+	assert_eq!(Err(Woff2Error::Truncated), woff2_to_sfnt(&woff2[..20]));
+	assert_eq!(Err(Woff2Error::Truncated), woff2_to_sfnt(&woff2[..woff2.len() - 1]));
+	assert_eq!(Err(Woff2Error::InvalidTableDirectory), woff2_to_sfnt(&[&woff2[..], &[0]].concat()));
+}
+
+#[test]
+/// WOFF2 table lengths
+/// Rejects table directories whose tables add up to more than the reference decoder accepts, before decompressing them.
+fn should_reject_woff2_tables_of_excessive_length() {
+	use brotli::{ woff2_to_sfnt, Woff2Error };
+
+	let woff2 = |num_tables: u16, orig_length: &[u8]| {
+		let mut woff2 = b"wOF2\x00\x01\x00\x00".to_vec();
+		woff2.extend_from_slice(&[0; 4]);
+		woff2.extend_from_slice(&num_tables.to_be_bytes());
+		woff2.extend_from_slice(&[0; 34]);
+
+		// untransformed name tables
+		for _ in 0..num_tables {
+			woff2.push(0x05);
+			woff2.extend_from_slice(orig_length);
+		}
+
+		let len = woff2.len() as u32;
+		woff2[8..12].copy_from_slice(&len.to_be_bytes());
+
+		woff2
+	};
+
+	// origLength == u32::MAX
+	assert_eq!(Err(Woff2Error::InvalidTableDirectory), woff2_to_sfnt(&woff2(2000, &[0x8f, 0xff, 0xff, 0xff, 0x7f])));
+	// origLength == 1 MiB, 31 MiB in total
+	assert_eq!(Err(Woff2Error::InvalidTableDirectory), woff2_to_sfnt(&woff2(31, &[0xc0, 0x80, 0x00])));
+	// 30 MiB in total, but no data
+	assert_eq!(Err(Woff2Error::InvalidBrotliStream), woff2_to_sfnt(&woff2(30, &[0xc0, 0x80, 0x00])));
+}
+
+#[test]
+/// WOFF2 collection
+/// Converts a font collection into a TrueType collection, with the shared tables written once.
+fn should_convert_woff2_collection_to_sfnt() {
+	use brotli::{ compress, woff2_to_sfnt };
+
+	let head = (0..54).collect::<Vec<u8>>();
+	let cmap = b"cmapdata".to_vec();
+	let name = b"names!".to_vec();
+	let compressed = compress(&[&head[..], &cmap, &name].concat(), 9);
+
+	let mut woff2 = b"wOF2ttcf".to_vec();
+	woff2.extend_from_slice(&[0; 4]);
+	woff2.extend_from_slice(&[0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+	woff2.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+	woff2.extend_from_slice(&[0x00, 0x01, 0x00, 0x00]);
+	woff2.extend_from_slice(&[0; 20]);
+	// head, cmap and name, without transforms
+	woff2.extend_from_slice(&[0x01, 54, 0x00, 8, 0x05, 6]);
+	// two TrueType fonts, both with the head table
+	woff2.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 2]);
+	woff2.extend_from_slice(&[2, 0x00, 0x01, 0x00, 0x00, 0, 1]);
+	woff2.extend_from_slice(&[2, 0x00, 0x01, 0x00, 0x00, 0, 2]);
+	woff2.extend_from_slice(&compressed);
+
+	let len = woff2.len() as u32;
+	woff2[8..12].copy_from_slice(&len.to_be_bytes());
+
+	let ttc = woff2_to_sfnt(&woff2).unwrap();
+	let u32_at = |offset: usize| u32::from_be_bytes([ttc[offset], ttc[offset + 1], ttc[offset + 2], ttc[offset + 3]]) as usize;
+	// (tag, offset, length) of the table records of a font
+	let records = |font: usize| (0..2).map(|i| {
+		let record = u32_at(12 + 4 * font) + 12 + 16 * i;
+
+		(ttc[record..record + 4].to_vec(), u32_at(record + 8), u32_at(record + 12))
+	}).collect::<Vec<_>>();
+
+	assert_eq!(b"ttcf", &ttc[..4]);
+	assert_eq!(2, u32_at(8));
+	// after the TTC header with two offsets, and the two table directories
+	assert_eq!(vec![(b"cmap".to_vec(), 164, 8), (b"head".to_vec(), 108, 54)], records(0));
+	assert_eq!(vec![(b"head".to_vec(), 108, 54), (b"name".to_vec(), 172, 6)], records(1));
+	assert_eq!(&cmap[..], &ttc[164..172]);
+	assert_eq!(&name[..], &ttc[172..178]);
+	assert_eq!(&head[12..], &ttc[108 + 12..108 + 54]);
+	assert_eq!(180, ttc.len());
+}
+
+#[test]
+/// Framed file
+/// Writes a framed file of independent chunks, reads it back sequentially and by chunk, and finds corrupt chunks.
+fn should_round_trip_framed_file_and_report_corrupt_chunk() {
+	use std::io::{ Cursor, ErrorKind, Read, Write };
+	use brotli::{ FramedError, FramedReader, FramedWriter };
+
+	let mut input = Vec::new();
+	std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut input).unwrap();
+
+	let mut writer = FramedWriter::with_chunk_size(Vec::new(), 5, 1 << 15);
+	writer.write_all(&input[..1000]).unwrap();
+	// ends the first chunk early
+	writer.flush().unwrap();
+	writer.write_all(&input[1000..]).unwrap();
+	let framed = writer.finish().unwrap();
+
+	let mut reader = FramedReader::new(Cursor::new(&framed[..])).unwrap();
+	let chunks = reader.chunks().to_vec();
+
+	assert_eq!(1 + (input.len() - 1000).div_ceil(1 << 15), chunks.len());
+	assert_eq!(1000, chunks[0].decompressed_len);
+	assert_eq!(input[1000 + (1 << 15)..1000 + (2 << 15)].to_vec(), reader.read_chunk(2).unwrap());
+
+	let mut decompressed = Vec::new();
+	reader.read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(input, decompressed);
+	assert!(reader.verify().is_ok());
+
+	// a flipped bit in the brotli stream of the third chunk
+	let mut corrupt = framed.clone();
+	corrupt[chunks[2].offset as usize + 100] ^= 0x08;
+
+	let mut reader = FramedReader::new(Cursor::new(&corrupt[..])).unwrap();
+	let e = reader.verify().unwrap_err();
+
+	assert_eq!(ErrorKind::InvalidData, e.kind());
+	assert_eq!(Some(&FramedError::CorruptChunk(2)), e.get_ref().unwrap().downcast_ref::<FramedError>());
+	assert!(reader.read_chunk(1).is_ok());
+
+	let e = FramedReader::new(Cursor::new(&framed[..framed.len() - 1])).unwrap_err();
+
+	assert_eq!(Some(&FramedError::InvalidIndex), e.get_ref().unwrap().downcast_ref::<FramedError>());
+}
+
+#[test]
+/// search()
+/// Finds every occurrence of a pattern in compressed files, at the offsets of a scan of the original files.
+fn should_search_compressed_files_like_their_contents() {
+	use std::io::Read;
+	use brotli::search;
+
+	for name in &["alice29.txt", "asyoulik.txt", "lcet10.txt"] {
+		let mut input = Vec::new();
+		std::fs::File::open(format!("data/{}", name)).unwrap().read_to_end(&mut input).unwrap();
+
+		let mut compressed = Vec::new();
+		std::fs::File::open(format!("data/{}.compressed", name)).unwrap().read_to_end(&mut compressed).unwrap();
+
+		for pattern in &[&b"the"[..], b"Alice", b"  ", b"\r\n\r\n", b"and the"] {
+			let expected = input.windows(pattern.len())
+				.enumerate()
+				.filter(|&(_, window)| window == *pattern)
+				.map(|(i, _)| i as u64)
+				.collect::<Vec<_>>();
+
+			assert_eq!(expected, search(&compressed[..], pattern).unwrap());
+		}
+	}
+}
+
+#[test]
+/// CommandVisitor
+/// Receives the commands of streams, which make up the output, with the bit offsets of the codes they have been read from.
+fn should_visit_commands_that_make_up_the_output() {
+	use std::io::Read;
+	use std::ops::Range;
+	use brotli::{ BlockCategory, BlockSwitchEvent, CommandVisitor, CopyEvent, Decompressor, DictionaryWordEvent, InsertEvent };
+
+	#[derive(Default)]
+	struct Recorder {
+		output: Vec<u8>,
+		/// end of the bits of the last command
+		bit_pos: usize,
+		copies: usize,
+		dictionary_words: usize,
+		literal_block_switches: usize,
+	}
+
+	impl Recorder {
+		fn command(&mut self, bits: &Range<usize>) {
+			assert!(self.bit_pos <= bits.start && bits.start <= bits.end);
+
+			self.bit_pos = bits.end;
+		}
+	}
+
+	impl CommandVisitor for Recorder {
+		fn visit_insert(&mut self, event: &InsertEvent) {
+			self.command(&event.bits);
+			self.output.extend_from_slice(event.literals);
+		}
+
+		fn visit_copy(&mut self, event: &CopyEvent) {
+			self.command(&event.bits);
+			assert_eq!(event.distance_code < 16, event.from_distance_cache);
+
+			for _ in 0..event.bytes.len() {
+				let byte = self.output[self.output.len() - event.distance];
+
+				self.output.push(byte);
+			}
+
+			assert_eq!(event.bytes, &self.output[self.output.len() - event.bytes.len()..]);
+			self.copies += 1;
+		}
+
+		fn visit_dictionary_word(&mut self, event: &DictionaryWordEvent) {
+			self.command(&event.bits);
+			assert_eq!(None, event.dictionary);
+			assert!(event.transform_id < 121);
+
+			self.output.extend_from_slice(event.word);
+			self.dictionary_words += 1;
+		}
+
+		fn visit_block_switch(&mut self, event: &BlockSwitchEvent) {
+			assert!(self.bit_pos <= event.bits.start && event.bits.start < event.bits.end);
+
+			if event.category == BlockCategory::Literals {
+				self.literal_block_switches += 1;
+			}
+		}
+	}
+
+	for name in &["alice29.txt", "asyoulik.txt", "lcet10.txt"] {
+		let mut input = Vec::new();
+		std::fs::File::open(format!("data/{}", name)).unwrap().read_to_end(&mut input).unwrap();
+
+		let mut compressed = Vec::new();
+		std::fs::File::open(format!("data/{}.compressed", name)).unwrap().read_to_end(&mut compressed).unwrap();
+
+		let mut recorder = Recorder::default();
+		Decompressor::new(&compressed[..]).visit_commands(&mut recorder).unwrap();
+
+		assert_eq!(input, recorder.output);
+		assert!(recorder.copies > 0 && recorder.dictionary_words > 0 && recorder.literal_block_switches > 0);
+		assert!(recorder.bit_pos <= 8 * compressed.len());
+
+		// alongside the output, through a small buffer
+		let mut decompressor = Decompressor::new(&compressed[..]);
+		let mut recorder = Recorder::default();
+		let mut decompressed = Vec::new();
+		let mut buf = [0; 100];
+
+		loop {
+			match decompressor.read_visiting(&mut buf, &mut recorder).unwrap() {
+				0 => break,
+				n => decompressed.extend_from_slice(&buf[..n]),
+			}
+		}
+
+		assert_eq!(input, decompressed);
+		assert_eq!(input, recorder.output);
+	}
+}
+
+#[test]
+/// StreamStructure
+/// Records the header fields of alice29.txt.compressed, as in docs/analysis_alice29.txt.compressed.txt.
+fn should_record_structure_of_meta_block_headers() {
+	use std::io::Read;
+	use brotli::{ Decompressor, Field, StreamStructure };
+
+	let mut compressed = Vec::new();
+	std::fs::File::open("data/alice29.txt.compressed").unwrap().read_to_end(&mut compressed).unwrap();
+
+	let structure = StreamStructure::parse(&compressed[..]).unwrap();
+	let meta_block = &structure.meta_blocks[0];
+
+	assert_eq!(Some(Field{ bits: 0..4, value: 22 }), structure.wbits);
+	assert_eq!(Some(4194288), structure.window_size());
+	assert_eq!(1, structure.meta_blocks.len());
+	assert_eq!(Some(Field{ bits: 4..5, value: true }), meta_block.is_last);
+	assert_eq!(Some(Field{ bits: 5..6, value: false }), meta_block.is_last_empty);
+	assert_eq!(Some(Field{ bits: 6..8, value: 5 }), meta_block.m_nibbles);
+	assert_eq!(Some(Field{ bits: 8..28, value: 152089 }), meta_block.m_len);
+	assert_eq!(None, meta_block.is_uncompressed);
+
+	let literal_block_types = meta_block.literal_block_types.as_ref().unwrap();
+	let block_type_code = literal_block_types.block_type_code.as_ref().unwrap();
+	let block_count_code = literal_block_types.block_count_code.as_ref().unwrap();
+
+	assert_eq!(Field{ bits: 28..32, value: 2 }, literal_block_types.n_bltypes);
+	assert_eq!(32..40, block_type_code.bits);
+	assert_eq!(None, block_type_code.h_skip);
+	assert_eq!(vec![0, 1], block_type_code.symbols);
+	assert_eq!(vec![1, 1], block_type_code.code_lengths);
+	assert_eq!(40, block_count_code.bits.start);
+	assert_eq!(Some(0), block_count_code.h_skip);
+	assert_eq!(Some(vec![1, 0, 3, 3, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]), block_count_code.code_length_code_lengths);
+	assert_eq!(block_count_code.bits.end, literal_block_types.first_block_count.as_ref().unwrap().bits.start);
+	assert_eq!(meta_block.n_trees_l.as_ref().unwrap().value as usize, meta_block.literal_prefix_codes.len());
+	assert_eq!(256, meta_block.literal_prefix_codes[0].alphabet_size);
+	assert_eq!(Some(compressed.len() * 8), structure.trailing_bits.as_ref().map(|bits| bits.end));
+
+	// recording while reading gives the same structure
+	let mut decompressor = Decompressor::new(&compressed[..]);
+	decompressor.record_structure();
+
+	let mut decompressed = Vec::new();
+	decompressor.read_to_end(&mut decompressed).unwrap();
+
+	assert_eq!(152089, decompressed.len());
+	assert_eq!(Some(&structure), decompressor.structure());
+}
+
+#[test]
+/// brotli-dump
+/// Annotates streams with the fields and values of the hand-written analyses in docs/, as text and as JSON.
+fn should_dump_streams_like_the_hand_written_analyses() {
+	use std::collections::BTreeMap;
+	use std::io::Write;
+	use std::process::{ Command, Stdio };
+
+	/// Returns the values of the fields that an analysis annotates, per field in stream order. A comment
+	/// like "MLEN - 1 == 0 => MLEN == 1" gives the last value of its field, the symbols of simple prefix
+	/// codes are numbered like "Symbol #1", and the bytes of uncompressed data are listed under "data".
+	fn fields(analysis: &str) -> BTreeMap<String, Vec<String>> {
+		let mut fields = BTreeMap::<String, Vec<String>>::new();
+
+		for line in analysis.lines() {
+			let (bits, comment) = match line.find('#') {
+				Some(i) => (&line[..i], &line[i + 1..]),
+				None => continue,
+			};
+			let tokens = comment.split_whitespace().map(|token| token.trim_end_matches(',')).collect::<Vec<_>>();
+			let mut values = BTreeMap::new();
+
+			for i in 0..tokens.len() {
+				let value = match (tokens[i], tokens.get(i + 1), tokens.get(i + 2), tokens.get(i + 3), tokens.get(i + 4)) {
+					(name, Some(&"=>"), Some(&"decodes"), Some(&"to"), Some(value)) => Some((name.to_string(), value)),
+					("Symbol", Some(number), Some(&"=>"), Some(_), Some(&"==")) | ("Symbol", Some(number), Some(&"=>"), Some(_), Some(&"=>")) =>
+						tokens.get(i + 5).map(|value| (format!("Symbol {}", number), value)),
+					(name, Some(&"=>"), Some(value), _, _) | (name, Some(&"=="), Some(value), _, _) | (name, Some(&"="), Some(value), _, _) => Some((name.to_string(), value)),
+					_ => None,
+				};
+
+				// fields that a comment refers to, like "for NBLTYPESL == 1", are annotated elsewhere
+				let is_reference = i > 0 && (tokens[i - 1] == "for" || tokens[i - 1] == "of");
+
+				if let (Some((name, value)), false) = (value, is_reference) {
+					let is_field = name.starts_with("Symbol #") || name.len() >= 4 && name.chars().all(|c| c.is_ascii_uppercase());
+
+					if is_field && (value.chars().all(|c| c.is_ascii_digit()) || *value == "true" || *value == "false") {
+						values.insert(name, value.to_string());
+					}
+				}
+			}
+
+			let bytes = bits.split_whitespace().collect::<Vec<_>>();
+
+			if !bytes.is_empty() && bytes.iter().all(|byte| byte.len() == 4 && byte.starts_with("0x")) {
+				let bytes = bytes.iter().map(|byte| u8::from_str_radix(&byte[2..], 16).unwrap().to_string()).collect::<Vec<_>>();
+
+				values.insert(String::from("data"), format!("[{}]", bytes.join(", ")));
+			}
+
+			for (name, value) in values {
+				fields.entry(name).or_default().push(value);
+			}
+		}
+
+		fields
+	}
+
+	/// Returns the values of the fields of a JSON dump, under the names that the analyses use.
+	fn json_fields(json: &str) -> BTreeMap<String, Vec<String>> {
+		let names = [("WBITS", "wbits"), ("ISLAST", "is_last"), ("ISLASTEMPTY", "is_last_empty"), ("MNIBBLES", "m_nibbles"),
+			("MSKIPBYTES", "m_skip_bytes"), ("MSKIPLEN", "m_skip_len"), ("MLEN", "m_len"), ("ISUNCOMPRESSED", "is_uncompressed"),
+			("NPOSTFIX", "n_postfix"), ("NTREESL", "n_trees_l"), ("NTREESD", "n_trees_d"), ("RLEMAX", "rle_max"), ("IMTF", "inverse_move_to_front")];
+		let mut fields = BTreeMap::new();
+
+		for &(name, key) in &names {
+			let values = json.split(&format!("\"{}\": {{", key)).skip(1)
+				.map(|member| member.split("\"value\": ").nth(1).unwrap().split([',', '\n']).next().unwrap().to_string())
+				.collect();
+
+			fields.insert(name.to_string(), values);
+		}
+
+		fields.insert(String::from("data"), json.split("\"data\": [").skip(1).map(|data| format!("[{}", &data[..=data.find(']').unwrap()])).collect());
+
+		fields
+	}
+
+	let dump = |args: &[&str]| {
+		let output = Command::new(env!("CARGO_BIN_EXE_brotli-dump")).args(args).output().unwrap();
+
+		assert!(output.status.success(), "{:?}", args);
+
+		String::from_utf8(output.stdout).unwrap()
+	};
+
+	// analyses of streams that are in data/, the ones of alice29.txt, asyoulik.txt and quickfox_repeated end early,
+	// the one of empty.compressed.17 shows one of its many meta-blocks for all of them
+	let analyses = [
+		("analysis_x.compressed.txt", "x.compressed"),
+		("analysis_x.compressed.00.txt", "x.compressed.00"),
+		("analysis_x.compressed.03.txt", "x.compressed.03"),
+		("analysis_empty.compressed.txt", "empty.compressed"),
+		("analysis_empty.compressed.01.txt", "empty.compressed.01"),
+		("analysis_empty.compressed.02.txt", "empty.compressed.02"),
+		("analysis_empty.compressed.15.txt", "empty.compressed.15"),
+		("analysis_empty.compressed.16.txt", "empty.compressed.16"),
+		("analysis_quickfox_repeated.txt", "quickfox_repeated.compressed"),
+		("analysis_alice29.txt.compressed.txt", "alice29.txt.compressed"),
+		("analysis_asyoulik.txt.compressed.txt", "asyoulik.txt.compressed"),
+	];
+
+	for &(doc, name) in &analyses {
+		let path = format!("data/{}", name);
+		let text = fields(&dump(&[&path]));
+		let json = json_fields(&dump(&["--json", &path]));
+		let docs = std::fs::read_to_string(format!("docs/{}", doc)).unwrap();
+
+		// some files hold more than one analysis of the stream
+		for analysis in docs.split("Analysis of ").skip(1) {
+			let expected = fields(analysis);
+
+			assert!(expected.contains_key("WBITS") && expected.contains_key("ISLAST"), "{}", doc);
+
+			for (field, values) in &expected {
+				assert!(text.get(field).is_some_and(|dumped| dumped.starts_with(values)), "{} in {}: {:?}, dumped {:?}", field, doc, values, text.get(field));
+
+				if let Some(dumped) = json.get(field) {
+					assert!(dumped.starts_with(values), "{} in {}: {:?}, dumped as JSON {:?}", field, doc, values, dumped);
+				}
+			}
+		}
+	}
+
+	// a truncated stream on standard input is dumped up to the point where it fails to decode
+	let mut child = Command::new(env!("CARGO_BIN_EXE_brotli-dump")).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+
+	child.stdin.take().unwrap().write_all(&[0x0b, 0x00, 0x80]).unwrap();
+
+	let output = child.wait_with_output().unwrap();
+	let truncated = String::from_utf8(output.stdout).unwrap();
+
+	assert!(!output.status.success());
+	assert!(truncated.contains("1                   # ISUNCOMPRESSED => true\n"));
+	assert!(truncated.ends_with("\nError: Encountered unexpected EOF\n"));
+}
+
+#[test]
+/// ParallelDecoder
+/// Decodes the chunks of a framed file on multiple threads, and names the first segment that fails to decode.
+fn should_decode_segments_in_parallel_and_in_order() {
+	use std::io::{ Cursor, ErrorKind, Read, Write };
+	use brotli::{ FramedReader, FramedWriter, ParallelDecoder };
+
+	let mut input = Vec::new();
+	std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut input).unwrap();
+
+	let mut writer = FramedWriter::with_chunk_size(Vec::new(), 5, 1 << 13);
+	writer.write_all(&input).unwrap();
+	let framed = writer.finish().unwrap();
+
+	let segments = FramedReader::new(Cursor::new(&framed[..])).unwrap().chunks().iter()
+		.map(|chunk| chunk.offset as usize..chunk.offset as usize + chunk.compressed_len as usize)
+		.collect::<Vec<_>>();
+
+	for &(threads, max_pending) in &[(1, 1), (4, 1), (4, 3), (16, 64)] {
+		let mut decoder = ParallelDecoder::new(&framed, segments.clone());
+		decoder.set_threads(threads);
+		decoder.set_max_pending(max_pending);
+
+		let mut decompressed = Vec::new();
+
+		assert_eq!(input.len() as u64, decoder.decode_to(&mut decompressed).unwrap());
+		assert_eq!(input, decompressed);
+	}
+
+	// the chunk headers are not brotli streams
+	let mut broken = segments.clone();
+	broken[5].start -= 12;
+
+	let mut decoder = ParallelDecoder::new(&framed, broken);
+	decoder.set_threads(4);
+
+	let mut decompressed = Vec::new();
+	let e = decoder.decode_to(&mut decompressed).unwrap_err();
+
+	assert_eq!(ErrorKind::InvalidData, e.kind());
+	assert!(e.to_string().ends_with("in segment 5"));
+	assert_eq!(input[..5 << 13].to_vec(), decompressed);
+}
+
+/// Counts the bytes that are read from the wrapped input stream.
+struct CountingReader<R> {
+	inner: R,
+	count: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.count.set(self.count.get() + n);
+
+		Ok(n)
+	}
+}
+
+impl<R: std::io::Seek> std::io::Seek for CountingReader<R> {
+	fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+		self.inner.seek(pos)
+	}
+}
+
+#[test]
+/// SeekableDecompressor
+/// returns the same bytes as sequential decompression after seeking forwards, backwards, and relative to the end
+fn should_read_at_seek_positions() {
+	use std::io::{ Read, Seek, SeekFrom };
+	use brotli::SeekableDecompressor;
+
+	let mut expected = Vec::new();
+	let _ = std::fs::File::open("data/metablock_reset").unwrap().read_to_end(&mut expected);
+
+	let brotli_stream = std::fs::File::open("data/metablock_reset.compressed").unwrap();
+	let mut decompressor = SeekableDecompressor::new(brotli_stream).unwrap();
+	decompressor.set_checkpoint_interval(0);
+
+	let mut buf = [0u8; 1000];
+
+	for &pos in &[800000, 100, 900000, 0, 400000, 400500, 131072, 912000] {
+		assert_eq!(pos, decompressor.seek(SeekFrom::Start(pos)).unwrap());
+
+		let n = decompressor.read(&mut buf).unwrap();
+		let pos = pos as usize;
+
+		assert_eq!(expected[pos..pos + n], buf[0..n]);
+	}
+
+	assert_eq!(expected.len() as u64 - 10, decompressor.seek(SeekFrom::End(-10)).unwrap());
+
+	let mut tail = Vec::new();
+	decompressor.read_to_end(&mut tail).unwrap();
+
+	assert_eq!(expected[expected.len() - 10..], tail[..]);
+	assert_eq!(0, decompressor.read(&mut buf).unwrap());
+	assert!(decompressor.seek(SeekFrom::Current(-(expected.len() as i64) - 1)).is_err());
+}
+
+#[test]
+/// SeekableDecompressor
+/// with a saved and loaded index, resumes from the nearest checkpoint instead of reading the whole input
+fn should_seek_with_loaded_index() {
+	use std::cell::Cell;
+	use std::io::{ Read, Seek, SeekFrom };
+	use std::rc::Rc;
+	use brotli::SeekableDecompressor;
+
+	let mut expected = Vec::new();
+	let _ = std::fs::File::open("data/metablock_reset").unwrap().read_to_end(&mut expected);
+
+	let brotli_stream = std::fs::File::open("data/metablock_reset.compressed").unwrap();
+	let mut decompressor = SeekableDecompressor::new(brotli_stream).unwrap();
+	decompressor.set_checkpoint_interval(0);
+
+	assert_eq!(expected.len() as u64, decompressor.build_index().unwrap());
+
+	let mut index = Vec::new();
+	decompressor.save_index(&mut index).unwrap();
+
+	let count = Rc::new(Cell::new(0));
+	let brotli_stream = CountingReader{
+		inner: std::fs::File::open("data/metablock_reset.compressed").unwrap(),
+		count: count.clone(),
+	};
+	let mut decompressor = SeekableDecompressor::new(brotli_stream).unwrap();
+	decompressor.load_index(&index[..]).unwrap();
+	count.set(0);
+
+	let pos = expected.len() - 1000;
+	decompressor.seek(SeekFrom::Start(pos as u64)).unwrap();
+
+	let mut tail = Vec::new();
+	decompressor.read_to_end(&mut tail).unwrap();
+
+	let compressed_len = std::fs::metadata("data/metablock_reset.compressed").unwrap().len() as usize;
+
+	assert_eq!(expected[pos..], tail[..]);
+	assert!(count.get() < compressed_len / 2);
+	assert!(decompressor.load_index(&index[1..]).is_err());
+}
+
+#[test]
+/// BufRead
+/// lines() on decompressed text yields the same lines as on the original text
+fn should_read_lines_from_decompressor() {
+	use std::io::{ BufRead, Read };
+	use brotli::Decompressor;
+
+	let mut expected = String::new();
+	let _ = std::fs::File::open("data/alice29.txt").unwrap().read_to_string(&mut expected);
+
+	let brotli_stream = std::fs::File::open("data/alice29.txt.compressed").unwrap();
+	let lines = Decompressor::new(brotli_stream).lines().map(|line| line.unwrap()).collect::<Vec<_>>();
+
+	assert_eq!(expected.lines().collect::<Vec<_>>(), lines);
+}
+
+#[test]
+/// BufRead
+/// read_until(), fill_buf() and read() can be mixed on the same decompressor
+fn should_mix_buf_read_and_read() {
+	use std::io::{ BufRead, Read };
+	use brotli::Decompressor;
+
+	let mut expected = Vec::new();
+	let _ = std::fs::File::open("data/metablock_reset").unwrap().read_to_end(&mut expected);
+
+	let brotli_stream = std::fs::File::open("data/metablock_reset.compressed").unwrap();
+	let mut decompressor = Decompressor::new(brotli_stream);
+	let mut decompressed = Vec::new();
+	let mut buf = [0u8; 777];
+
+	loop {
+		let n = decompressor.read_until(b'\n', &mut decompressed).unwrap();
+
+		let available = decompressor.fill_buf().unwrap().len();
+		let consumed = available / 3;
+		decompressed.extend_from_slice(&decompressor.fill_buf().unwrap()[0..consumed]);
+		decompressor.consume(consumed);
+
+		let m = decompressor.read(&mut buf).unwrap();
+		decompressed.extend_from_slice(&buf[0..m]);
+
+		if n == 0 && available == 0 && m == 0 {
+			break;
+		}
+	}
+
+	assert_eq!(expected, decompressed);
+}
+
+#[test]
+/// DecompressorWriter
+/// produces the same output as Decompressor, no matter how the compressed input is split into writes
+fn should_decompress_written_chunks() {
+	use std::io::{ Read, Write };
+	use brotli::DecompressorWriter;
+
+	for &(name, chunk_size) in &[("alice29.txt", 1), ("alice29.txt", 4096), ("metablock_reset", 1000), ("random_org_10k.bin", 7), ("empty", 1), ("ukkonooa", 3), ("x", 1)] {
+		let mut expected = Vec::new();
+		let _ = std::fs::File::open(format!("data/{}", name)).unwrap().read_to_end(&mut expected);
+
+		let mut brotli_stream = Vec::new();
+		let _ = std::fs::File::open(format!("data/{}.compressed", name)).unwrap().read_to_end(&mut brotli_stream);
+
+		let mut decompressor = DecompressorWriter::new(Vec::new());
+
+		for chunk in brotli_stream.chunks(chunk_size) {
+			decompressor.write_all(chunk).unwrap();
+		}
+
+		assert_eq!(expected, decompressor.finish().unwrap());
+	}
+}
+
+#[test]
+/// DecompressorWriter
+/// decodes as much as possible on every write, before the rest of the input has arrived
+fn should_decompress_before_finish() {
+	use std::io::{ Read, Write };
+	use brotli::DecompressorWriter;
+
+	let mut brotli_stream = Vec::new();
+	let _ = std::fs::File::open("data/alice29.txt.compressed").unwrap().read_to_end(&mut brotli_stream);
+
+	let mut decompressor = DecompressorWriter::new(Vec::new());
+	decompressor.write_all(&brotli_stream[0..brotli_stream.len() / 2]).unwrap();
+
+	assert!(decompressor.get_ref().len() > 50000);
+}
+
+#[test]
+/// DecompressorWriter
+/// fails in finish() if the stream is truncated, and on write if there is data after the end of the stream
+fn should_reject_truncated_and_trailing_input_in_writer() {
+	use std::io::{ Read, Write };
+	use brotli::DecompressorWriter;
+
+	let mut brotli_stream = Vec::new();
+	let _ = std::fs::File::open("data/alice29.txt.compressed").unwrap().read_to_end(&mut brotli_stream);
+
+	let mut decompressor = DecompressorWriter::new(Vec::new());
+	decompressor.write_all(&brotli_stream[0..brotli_stream.len() - 1]).unwrap();
+
+	assert!(decompressor.finish().is_err());
+
+	let mut decompressor = DecompressorWriter::new(Vec::new());
+	decompressor.write_all(&brotli_stream).unwrap();
+
+	assert!(decompressor.write_all(&[0]).is_err());
+}
+
+#[test]
+/// Compressor
+/// output of every quality level decompresses to the original, for every file in data/
+fn should_round_trip_every_data_file() {
+	use std::io::Read;
+	use brotli::{ compress, Decompressor };
+
+	let mut paths = std::fs::read_dir("data").unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+	paths.sort();
+
+	for path in paths {
+		let mut input = Vec::new();
+		let _ = std::fs::File::open(&path).unwrap().read_to_end(&mut input);
+
+		// the first few KiB at every quality level, but quality 11 only for the files that are not
+		// brotli streams themselves, and the whole file at the faster ones,
+		// should_round_trip_every_whole_data_file() covers the rest
+		let head = &input[..std::cmp::min(input.len(), 4096)];
+		let max_quality = if path.to_string_lossy().contains(".compressed") { 10 } else { 11 };
+		let runs = (0..max_quality + 1).map(|quality| (head, quality)).chain([0, 4].iter().map(|&quality| (&input[..], quality)));
+
+		for (input, quality) in runs {
+			let brotli_stream = compress(input, quality);
+
+			let mut decompressed = Vec::new();
+			Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+			assert!(input == &decompressed[..], "{:?} at quality {}, {} bytes", path, quality, input.len());
+		}
+	}
+}
+
+#[test]
+#[ignore]
+/// Compressor
+/// output of every quality level decompresses to the original, for the whole of every file in data/.
+/// Takes minutes in debug builds, run it with cargo test --release -- --ignored
+fn should_round_trip_every_whole_data_file() {
+	use std::io::Read;
+	use brotli::{ compress, Decompressor };
+
+	let mut paths = std::fs::read_dir("data").unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+	paths.sort();
+
+	for path in paths {
+		let mut input = Vec::new();
+		let _ = std::fs::File::open(&path).unwrap().read_to_end(&mut input);
+
+		for quality in 0..12 {
+			let brotli_stream = compress(&input, quality);
+
+			let mut decompressed = Vec::new();
+			Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+			assert!(input == decompressed, "{:?} at quality {}", path, quality);
+		}
+	}
+}
+
+#[test]
+/// Compressor
+/// output of every window size decompresses to the original, also when written in small pieces
+fn should_round_trip_with_every_wbits() {
+	use std::io::{ Read, Write };
+	use brotli::{ Compressor, Decompressor };
+
+	let mut input = Vec::new();
+	let _ = std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut input);
+
+	for wbits in 10..25 {
+		let mut compressor = Compressor::with_wbits(Vec::new(), 5, wbits);
+
+		for chunk in input.chunks(1000) {
+			compressor.write_all(chunk).unwrap();
+		}
+
+		let brotli_stream = compressor.finish().unwrap();
+
+		let mut decompressed = Vec::new();
+		Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+		assert!(input == decompressed, "WBITS {}", wbits);
+	}
+}
+
+#[test]
+/// Compressor
+/// compresses better with higher quality levels, and ends the stream when dropped
+fn should_compress_better_with_higher_quality() {
+	use std::io::{ Read, Write };
+	use brotli::{ compress, Compressor };
+
+	let mut input = Vec::new();
+	let _ = std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut input);
+	let input = &input[..16000];
+
+	let (fast, dense) = (compress(input, 0).len(), compress(input, 11).len());
+
+	assert!(fast < input.len() / 2);
+	assert!(dense < fast);
+
+	let mut brotli_stream = Vec::new();
+	{
+		let mut compressor = Compressor::new(&mut brotli_stream, 11);
+		compressor.write_all(input).unwrap();
+	}
+
+	assert_eq!(dense, brotli_stream.len());
+}
+
+#[test]
+/// Compressor
+/// output decompresses to the original with every built-in MatchFinder, at every quality and window size
+fn should_round_trip_with_every_match_finder() {
+	use std::io::{ Read, Write };
+	use brotli::{ BinaryTree, Compressor, Decompressor, HashChain, SingleHash };
+
+	let mut input = Vec::new();
+	let _ = std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut input);
+
+	for &(quality, wbits, len) in &[(0, 10, 60000), (5, 12, 60000), (9, 16, 60000), (11, 10, 4096)] {
+		let input = &input[..len];
+		let brotli_streams = vec![
+			Compressor::with_match_finder(Vec::new(), quality, wbits, SingleHash::new(12)),
+			Compressor::with_match_finder(Vec::new(), quality, wbits, HashChain::new(16, wbits, 64)),
+			Compressor::with_match_finder(Vec::new(), quality, wbits, BinaryTree::new(16, wbits, 64)),
+		].into_iter().map(|mut compressor| {
+			compressor.write_all(input).unwrap();
+			compressor.finish().unwrap()
+		});
+
+		for (i, brotli_stream) in brotli_streams.enumerate() {
+			let mut decompressed = Vec::new();
+			Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+			assert!(input == &decompressed[..], "quality {}, WBITS {}, match finder {}", quality, wbits, i);
+		}
+	}
+}
+
+#[test]
+/// Compressor
+/// ignores the matches of a MatchFinder that reach beyond the input or the window, or that do not repeat the input
+fn should_ignore_invalid_matches_of_a_match_finder() {
+	use std::io::{ Read, Write };
+	use brotli::{ Compressor, Decompressor, MatchFinder };
+
+	#[derive(Debug)]
+	struct Faulty;
+
+	impl MatchFinder for Faulty {
+		fn insert_until(&mut self, _: &[u8], _: usize, _: usize) {}
+
+		fn skip_until(&mut self, _: usize) {}
+
+		fn find_matches(&self, _: &[u8], _: usize, pos: usize, max_len: usize, max_distance: usize, f: &mut dyn FnMut(usize, usize) -> bool) {
+			let _ = f(8, pos + 5) && f(8, 0) && f(8, max_distance + 1) && f(max_len + 1, 1) && f(1, 1) && f(6, 3);
+		}
+	}
+
+	let input = b"abcdefghijabcdefghij, which does not repeat with a distance of 3".repeat(20);
+
+	for &quality in &[0, 5, 11] {
+		let mut compressor = Compressor::with_match_finder(Vec::new(), quality, 10, Faulty);
+		compressor.write_all(&input).unwrap();
+		let brotli_stream = compressor.finish().unwrap();
+
+		let mut decompressed = Vec::new();
+		Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+		assert!(input == decompressed, "quality {}", quality);
+	}
+}
+
+#[test]
+/// Compressor
+/// output for records with a fixed stride decompresses to the original, with the NPOSTFIX and NDIRECT it picks
+fn should_round_trip_strided_records() {
+	use std::io::Read;
+	use brotli::{ compress, Decompressor };
+
+	let mut input = Vec::new();
+
+	for i in 0..5000u32 {
+		let sample = (i * 7919) % 1024;
+
+		input.extend_from_slice(&[(sample >> 8) as u8, sample as u8, 0, (i % 3) as u8, 0xff, 0, 0, 0]);
+	}
+
+	for quality in 0..12 {
+		let brotli_stream = compress(&input, quality);
+
+		let mut decompressed = Vec::new();
+		Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+		assert!(input == decompressed, "quality {}", quality);
+	}
+}
+
+#[test]
+/// Compressor
+/// flush() makes all the input written so far decodable from the output written so far
+fn should_decode_everything_before_a_flush() {
+	use std::io::{ Read, Write };
+	use brotli::{ Compressor, DecompressorWriter };
+
+	let mut input = Vec::new();
+	let _ = std::fs::File::open("data/alice29.txt").unwrap().read_to_end(&mut input);
+	let input = &input[..20000];
+
+	for &quality in &[0, 5, 10, 11] {
+		let mut compressor = Compressor::new(Vec::new(), quality);
+		let mut decompressor = DecompressorWriter::new(Vec::new());
+		let (mut written, mut flushed) = (0, 0);
+
+		for &len in &[1, 0, 17, 300, 0, 2, 5000, 9000, 40] {
+			compressor.write_all(&input[written..written + len]).unwrap();
+			written += len;
+
+			compressor.flush().unwrap();
+
+			let brotli_stream = compressor.get_ref();
+			decompressor.write_all(&brotli_stream[flushed..]).unwrap();
+			flushed = brotli_stream.len();
+
+			assert!(input[..written] == decompressor.get_ref()[..], "quality {}, {} bytes", quality, written);
+		}
+
+		compressor.write_all(&input[written..]).unwrap();
+
+		let brotli_stream = compressor.finish().unwrap();
+		decompressor.write_all(&brotli_stream[flushed..]).unwrap();
+
+		assert!(input == &decompressor.finish().unwrap()[..], "quality {}", quality);
+	}
+}
+
+#[test]
+/// StoredWriter
+/// writes the WBITS header, uncompressed meta-blocks, and an empty ISLAST meta-block
+fn should_write_stored_meta_blocks() {
+	use std::io::Write;
+	use brotli::StoredWriter;
+
+	assert_eq!(vec![0x06], StoredWriter::new(Vec::new()).finish().unwrap());
+
+	let mut writer = StoredWriter::new(Vec::new());
+	writer.write_all(b"ab").unwrap();
+
+	assert_eq!(vec![0x10, 0x00, 0x10, b'a', b'b', 0x03], writer.finish().unwrap());
+}
+
+#[test]
+/// StoredWriter
+/// output decompresses to the original, no matter how the input is split into writes and flushes
+fn should_round_trip_stored_writes() {
+	use std::io::{ Read, Write };
+	use brotli::{ Decompressor, StoredWriter };
+
+	let mut input = Vec::new();
+	let _ = std::fs::File::open("data/plrabn12.txt").unwrap().read_to_end(&mut input);
+
+	for &(chunk_size, flush) in &[(1, false), (1000, true), (65535, false), (100000, true), (input.len(), false)] {
+		let mut writer = StoredWriter::new(Vec::new());
+
+		for chunk in input.chunks(chunk_size) {
+			writer.write_all(chunk).unwrap();
+
+			if flush {
+				writer.flush().unwrap();
+			}
+		}
+
+		let brotli_stream = writer.finish().unwrap();
+
+		let mut decompressed = Vec::new();
+		Decompressor::new(&brotli_stream[..]).read_to_end(&mut decompressed).unwrap();
+
+		assert!(input == decompressed, "chunk size {}", chunk_size);
+		assert!(brotli_stream.len() < input.len() + input.len() / 100);
+	}
+}
+
+#[test]
+/// StoredWriter
+/// makes everything written so far decodable on flush
+fn should_flush_stored_meta_block() {
+	use std::io::Write;
+	use brotli::{ DecompressorWriter, StoredWriter };
+
+	let mut writer = StoredWriter::new(Vec::new());
+	writer.write_all(b"partial").unwrap();
+	writer.flush().unwrap();
+
+	let mut decompressor = DecompressorWriter::new(Vec::new());
+	decompressor.write_all(writer.get_ref()).unwrap();
+
+	assert_eq!(b"partial", &decompressor.get_ref()[..]);
+}